@@ -60,20 +60,15 @@ mkdir -p templates/auth templates/user templates/admin
 echo "🔨 Building project..."
 cargo build --release
 
-# Create admin user script
-echo "👤 Creating admin user helper script..."
-cat > create_admin.sql << EOF
--- Run this to make a user an admin (replace email with actual email)
--- psql \$DATABASE_URL -c "UPDATE users SET is_admin = true WHERE email = 'your-email@example.com';"
-EOF
-
 echo "✅ Setup complete!"
 echo ""
 echo "🎯 Next steps:"
 echo "1. Start the server: cargo run"
 echo "2. Visit http://localhost:3000"
-echo "3. Register a user account"
-echo "4. Make yourself admin by running: psql \$DATABASE_URL -f create_admin.sql"
-echo "   (Edit the SQL file first with your email)"
+echo "3. Create a super-admin: cargo run --bin superior6-admin -- users create --email you@example.com --name \"Your Name\" --role super_admin"
+echo "   (or set INITIAL_ADMIN_EMAIL in .env, register with that email, confirm the address"
+echo "   from the email you're sent, then restart the server; it's only checked at startup)"
+echo "4. Set up the season: cargo run --bin superior6-admin -- seasons create 2025-26 --first-deadline 2025-08-15T18:00:00Z"
+echo "5. Grant other admins their roles at /admin/roles, or with superior6-admin users promote"
 echo ""
 echo "🏆 Happy predicting!"
\ No newline at end of file
@@ -0,0 +1,425 @@
+// account.rs
+//
+// Changes players make to their own account: email, password, exporting
+// everything we hold about them and deleting the account.
+
+use std::str::FromStr;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{query, query_as, Connection, FromRow, PgConnection, PgExecutor, PgPool};
+use uuid::Uuid;
+use crate::audit;
+use crate::auth::{hash_password, unusable_password_hash, verify_password};
+use crate::errors::AppError;
+use crate::models::User;
+use crate::roles;
+
+/// What anonymised accounts are shown as in league tables.
+pub const DELETED_PLAYER_NAME: &str = "Deleted player";
+
+/// How recently players who use single sign-on must have signed in to make
+/// the changes that otherwise ask for their password.
+pub const REAUTHENTICATE_MINUTES: i64 = 10;
+
+/// How a player's account is removed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeletionMode {
+    /// Strips everything that identifies the player but keeps their
+    /// predictions and scores, so past league tables don't change.
+    Anonymise,
+    /// Deletes the account and everything in it. Only allowed before the
+    /// player has scored, so no league table changes.
+    Erase,
+}
+
+impl FromStr for DeletionMode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "anonymise" => Ok(DeletionMode::Anonymise),
+            "erase" => Ok(DeletionMode::Erase),
+            other => Err(format!("Unknown deletion option: {}", other)),
+        }
+    }
+}
+
+/// The address an anonymised account is left with. It can't receive mail or
+/// clash with anyone who registers later.
+pub fn anonymised_email(user_id: Uuid) -> String {
+    format!("deleted-{}@invalid", user_id)
+}
+
+/// Whether the account has been linked to a single sign-on identity.
+pub async fn uses_single_sign_on(db: &PgPool, user_id: Uuid) -> Result<bool, AppError> {
+    let linked = query!("SELECT id FROM oidc_identities WHERE user_id = $1 LIMIT 1", user_id)
+        .fetch_optional(db)
+        .await?
+        .is_some();
+
+    Ok(linked)
+}
+
+/// Whether the player has shown it's really them before a sensitive change:
+/// with their password or, as accounts made through single sign-on don't
+/// have one, by signing in with it within the last `REAUTHENTICATE_MINUTES`.
+pub async fn reauthenticated(
+    db: &PgPool,
+    user: &User,
+    session_id: Option<Uuid>,
+    current_password: &str,
+) -> Result<bool, AppError> {
+    if !current_password.is_empty() && verify_password(current_password, &user.password_hash)? {
+        return Ok(true);
+    }
+
+    let Some(session_id) = session_id else {
+        return Ok(false);
+    };
+
+    let recent = query!(
+        r#"
+        SELECT EXISTS (
+            SELECT 1 FROM sessions s
+            JOIN oidc_identities i ON i.user_id = s.user_id
+            WHERE s.id = $1 AND s.user_id = $2 AND s.revoked_at IS NULL
+              AND s.created_at > NOW() - make_interval(mins => $3)
+        ) AS "recent!"
+        "#,
+        session_id,
+        user.id,
+        REAUTHENTICATE_MINUTES as i32
+    )
+    .fetch_one(db)
+    .await?
+    .recent;
+
+    Ok(recent)
+}
+
+/// Whether another account already uses `email`, ignoring case.
+pub async fn email_taken(db: impl PgExecutor<'_>, email: &str, user_id: Uuid) -> Result<bool, AppError> {
+    let taken = query!(
+        "SELECT id FROM users WHERE LOWER(email) = LOWER($1) AND id <> $2",
+        email,
+        user_id
+    )
+    .fetch_optional(db)
+    .await?
+    .is_some();
+
+    Ok(taken)
+}
+
+/// Switches the account to a new address. When `verified` is false the
+/// account goes back to waiting for confirmation, as it did on sign-up.
+pub async fn change_email(db: &mut PgConnection, user_id: Uuid, email: &str, verified: bool) -> Result<User, AppError> {
+    if email_taken(&mut *db, email, user_id).await? {
+        return Err(AppError::EmailExists);
+    }
+
+    let user = query_as::<_, User>(
+        r#"
+        UPDATE users
+        SET email = $1, email_verified_at = CASE WHEN $2 THEN NOW() ELSE NULL END
+        WHERE id = $3
+        RETURNING *
+        "#
+    )
+    .bind(email)
+    .bind(verified)
+    .bind(user_id)
+    .fetch_optional(&mut *db)
+    .await?
+    .ok_or(AppError::UserNotFound)?;
+
+    // Any confirmation link sent to the old address must stop working
+    query!(
+        "UPDATE email_verifications SET used_at = NOW() WHERE user_id = $1 AND used_at IS NULL",
+        user_id
+    )
+    .execute(&mut *db)
+    .await?;
+
+    Ok(user)
+}
+
+/// Sets a new password. Every session is signed out by the database when the
+/// hash changes, so the caller needs to start a fresh one.
+pub async fn change_password(db: &mut PgConnection, user_id: Uuid, password: &str, bcrypt_cost: u32) -> Result<(), AppError> {
+    query!(
+        "UPDATE users SET password_hash = $1 WHERE id = $2",
+        hash_password(password, bcrypt_cost)?,
+        user_id
+    )
+    .execute(&mut *db)
+    .await?;
+
+    query!(
+        "UPDATE password_resets SET used_at = NOW() WHERE user_id = $1 AND used_at IS NULL",
+        user_id
+    )
+    .execute(&mut *db)
+    .await?;
+
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExportedProfile {
+    pub id: Uuid,
+    pub name: String,
+    pub display_name: String,
+    pub email: String,
+    pub email_verified_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct ExportedPrediction {
+    pub season: String,
+    pub week_number: i32,
+    pub home_team: String,
+    pub away_team: String,
+    pub kickoff_time: DateTime<Utc>,
+    pub home_score_prediction: i32,
+    pub away_score_prediction: i32,
+    pub home_score: Option<i32>,
+    pub away_score: Option<i32>,
+    pub points_awarded: i32,
+    pub submitted_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct ExportedGameweekScore {
+    pub season: String,
+    pub week_number: i32,
+    pub total_points: i32,
+    pub exact_scores: i32,
+    pub correct_results: i32,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct ExportedSeasonScore {
+    pub season: String,
+    pub total_points: i32,
+    pub total_exact_scores: i32,
+    pub total_correct_results: i32,
+    pub gameweeks_played: i32,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct ExportedAchievement {
+    pub badge: String,
+    pub season: String,
+    pub week_number: Option<i32>,
+    pub awarded_at: DateTime<Utc>,
+}
+
+/// Everything a player can download about themselves.
+#[derive(Debug, Serialize)]
+pub struct AccountExport {
+    pub exported_at: DateTime<Utc>,
+    pub profile: ExportedProfile,
+    pub predictions: Vec<ExportedPrediction>,
+    pub gameweek_scores: Vec<ExportedGameweekScore>,
+    pub season_scores: Vec<ExportedSeasonScore>,
+    pub achievements: Vec<ExportedAchievement>,
+}
+
+pub async fn export_data(db: &PgPool, user: &User) -> Result<AccountExport, AppError> {
+    let predictions = query_as::<_, ExportedPrediction>(
+        r#"
+        SELECT
+            gw.season, gw.week_number, f.home_team, f.away_team, f.kickoff_time,
+            p.home_score_prediction, p.away_score_prediction, f.home_score, f.away_score,
+            COALESCE(p.points_awarded, 0) AS points_awarded, p.created_at AS submitted_at
+        FROM predictions p
+        JOIN fixtures f ON p.fixture_id = f.id
+        JOIN gameweeks gw ON f.gameweek_id = gw.id
+        WHERE p.user_id = $1
+        ORDER BY gw.season, gw.week_number, f.fixture_order
+        "#
+    )
+    .bind(user.id)
+    .fetch_all(db)
+    .await?;
+
+    let gameweek_scores = query_as::<_, ExportedGameweekScore>(
+        r#"
+        SELECT
+            gw.season, gw.week_number,
+            COALESCE(gs.total_points, 0) AS total_points,
+            COALESCE(gs.exact_scores, 0) AS exact_scores,
+            COALESCE(gs.correct_results, 0) AS correct_results
+        FROM gameweek_scores gs
+        JOIN gameweeks gw ON gs.gameweek_id = gw.id
+        WHERE gs.user_id = $1
+        ORDER BY gw.season, gw.week_number
+        "#
+    )
+    .bind(user.id)
+    .fetch_all(db)
+    .await?;
+
+    let season_scores = query_as::<_, ExportedSeasonScore>(
+        r#"
+        SELECT
+            season,
+            COALESCE(total_points, 0) AS total_points,
+            COALESCE(total_exact_scores, 0) AS total_exact_scores,
+            COALESCE(total_correct_results, 0) AS total_correct_results,
+            COALESCE(gameweeks_played, 0) AS gameweeks_played
+        FROM season_scores
+        WHERE user_id = $1
+        ORDER BY season
+        "#
+    )
+    .bind(user.id)
+    .fetch_all(db)
+    .await?;
+
+    let achievements = query_as::<_, ExportedAchievement>(
+        r#"
+        SELECT a.badge, a.season, gw.week_number, a.created_at AS awarded_at
+        FROM achievements a
+        LEFT JOIN gameweeks gw ON a.gameweek_id = gw.id
+        WHERE a.user_id = $1
+        ORDER BY a.created_at
+        "#
+    )
+    .bind(user.id)
+    .fetch_all(db)
+    .await?;
+
+    Ok(AccountExport {
+        exported_at: Utc::now(),
+        profile: ExportedProfile {
+            id: user.id,
+            name: user.name.clone(),
+            display_name: user.display_name.clone(),
+            email: user.email.clone(),
+            email_verified_at: user.email_verified_at,
+            created_at: user.created_at,
+        },
+        predictions,
+        gameweek_scores,
+        season_scores,
+        achievements,
+    })
+}
+
+/// Payments are kept for the league's books, and past league tables have to
+/// stay as they were, so players with ledger entries or any scores can only
+/// anonymise their account.
+pub async fn can_erase(db: impl PgExecutor<'_>, user_id: Uuid) -> Result<bool, AppError> {
+    let has_records = query!(
+        r#"
+        SELECT EXISTS (SELECT 1 FROM ledger_entries WHERE user_id = $1)
+            OR EXISTS (SELECT 1 FROM gameweek_scores WHERE user_id = $1)
+            OR EXISTS (SELECT 1 FROM season_scores WHERE user_id = $1)
+            OR EXISTS (SELECT 1 FROM season_positions WHERE user_id = $1) AS "has_records!"
+        "#,
+        user_id
+    )
+    .fetch_one(db)
+    .await?
+    .has_records;
+
+    Ok(!has_records)
+}
+
+/// Removes the account, along with the personal details the audit log holds
+/// about it. Staff must have their roles taken away first (`HoldsRoles`), and
+/// `Erase` is refused once the player has ledger entries or scores
+/// (`Forbidden`).
+pub async fn delete_account(db: &mut PgConnection, user_id: Uuid, mode: DeletionMode) -> Result<(), AppError> {
+    if !roles::user_roles(&mut *db, user_id).await?.is_empty() {
+        return Err(AppError::HoldsRoles);
+    }
+
+    match mode {
+        DeletionMode::Anonymise => anonymise(db, user_id).await,
+        DeletionMode::Erase => erase(db, user_id).await,
+    }
+}
+
+async fn anonymise(db: &mut PgConnection, user_id: Uuid) -> Result<(), AppError> {
+    // Nobody knows this password and the address can't receive a reset link
+    let unusable = unusable_password_hash()?;
+
+    let mut tx = db.begin().await?;
+
+    let updated = query!(
+        r#"
+        UPDATE users
+        SET name = $1, display_name = $1, email = $2, password_hash = $3, deleted_at = NOW()
+        WHERE id = $4 AND deleted_at IS NULL
+        "#,
+        DELETED_PLAYER_NAME,
+        anonymised_email(user_id),
+        unusable,
+        user_id
+    )
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+
+    if updated == 0 {
+        return Err(AppError::UserNotFound);
+    }
+
+    let personal_tables = [
+        "sessions", "api_tokens", "two_factor", "recovery_codes", "password_resets", "email_verifications",
+        "oidc_identities", "announcement_dismissals",
+    ];
+    for table in personal_tables {
+        query(&format!("DELETE FROM {} WHERE user_id = $1", table))
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    audit::forget_user(&mut tx, user_id).await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+async fn erase(db: &mut PgConnection, user_id: Uuid) -> Result<(), AppError> {
+    if !can_erase(&mut *db, user_id).await? {
+        return Err(AppError::Forbidden);
+    }
+
+    let mut tx = db.begin().await?;
+
+    // Predictions and badges cascade
+    let deleted = query!("DELETE FROM users WHERE id = $1", user_id)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+
+    if deleted == 0 {
+        return Err(AppError::UserNotFound);
+    }
+
+    audit::forget_user(&mut tx, user_id).await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deletion_modes() {
+        assert_eq!("anonymise".parse::<DeletionMode>(), Ok(DeletionMode::Anonymise));
+        assert_eq!("erase".parse::<DeletionMode>(), Ok(DeletionMode::Erase));
+        assert!("everything".parse::<DeletionMode>().is_err());
+
+        let user_id = Uuid::nil();
+        assert_eq!(anonymised_email(user_id), "deleted-00000000-0000-0000-0000-000000000000@invalid");
+    }
+}
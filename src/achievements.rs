@@ -0,0 +1,429 @@
+// achievements.rs
+
+use std::collections::{HashMap, HashSet};
+use sqlx::{query, query_as, PgPool};
+use uuid::Uuid;
+use crate::errors::AppError;
+use crate::models::{Achievement, Fixture, Gameweek, GameweekScore, Prediction};
+use crate::scoring::GAMEWEEKS_PER_SEASON;
+
+pub const PERFECT_WEEK_EXACT_SCORES: i32 = 6;
+pub const EXACT_STREAK_LENGTH: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Badge {
+    PerfectWeek,
+    ExactStreak,
+    WeeklyWinner,
+    GoallessDraw,
+    EverPresent,
+}
+
+impl Badge {
+    pub fn key(&self) -> &'static str {
+        match self {
+            Badge::PerfectWeek => "perfect_week",
+            Badge::ExactStreak => "exact_streak",
+            Badge::WeeklyWinner => "weekly_winner",
+            Badge::GoallessDraw => "goalless_draw",
+            Badge::EverPresent => "ever_present",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Self> {
+        match key {
+            "perfect_week" => Some(Badge::PerfectWeek),
+            "exact_streak" => Some(Badge::ExactStreak),
+            "weekly_winner" => Some(Badge::WeeklyWinner),
+            "goalless_draw" => Some(Badge::GoallessDraw),
+            "ever_present" => Some(Badge::EverPresent),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Badge::PerfectWeek => "Perfect Week",
+            Badge::ExactStreak => "Hat-Trick",
+            Badge::WeeklyWinner => "Top of the Week",
+            Badge::GoallessDraw => "Bore Draw",
+            Badge::EverPresent => "Ever Present",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Badge::PerfectWeek => "All six scores exactly right in one gameweek",
+            Badge::ExactStreak => "Three exact scores in a row",
+            Badge::WeeklyWinner => "Top of the weekly table",
+            Badge::GoallessDraw => "Correctly predicted a 0-0",
+            Badge::EverPresent => "Played every gameweek of a full season",
+        }
+    }
+
+    pub fn icon(&self) -> &'static str {
+        match self {
+            Badge::PerfectWeek => "💯",
+            Badge::ExactStreak => "🎩",
+            Badge::WeeklyWinner => "👑",
+            Badge::GoallessDraw => "😴",
+            Badge::EverPresent => "📅",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Award {
+    pub user_id: Uuid,
+    pub badge: Badge,
+    pub gameweek_id: Option<Uuid>,
+}
+
+/// Everything the rules need to know about a season's completed gameweeks.
+#[derive(Debug, Default)]
+pub struct SeasonContext {
+    /// Completed gameweeks, in week order.
+    pub gameweeks: Vec<Gameweek>,
+    /// Fixtures with results, in week then fixture order.
+    pub fixtures: Vec<Fixture>,
+    pub predictions: Vec<Prediction>,
+    pub scores: Vec<GameweekScore>,
+}
+
+type Rule = fn(&SeasonContext) -> Vec<Award>;
+
+const RULES: &[Rule] = &[
+    perfect_week,
+    exact_streak,
+    weekly_winner,
+    goalless_draw,
+    ever_present,
+];
+
+pub fn evaluate(ctx: &SeasonContext) -> HashSet<Award> {
+    RULES.iter().flat_map(|rule| rule(ctx)).collect()
+}
+
+fn is_exact(fixture: &Fixture, prediction: &Prediction) -> bool {
+    fixture.home_score == Some(prediction.home_score_prediction)
+        && fixture.away_score == Some(prediction.away_score_prediction)
+}
+
+fn perfect_week(ctx: &SeasonContext) -> Vec<Award> {
+    ctx.scores
+        .iter()
+        .filter(|score| score.exact_scores >= PERFECT_WEEK_EXACT_SCORES)
+        .map(|score| Award {
+            user_id: score.user_id,
+            badge: Badge::PerfectWeek,
+            gameweek_id: Some(score.gameweek_id),
+        })
+        .collect()
+}
+
+/// Streaks run across gameweek boundaries; a missing prediction breaks them.
+fn exact_streak(ctx: &SeasonContext) -> Vec<Award> {
+    let predictions: HashMap<(Uuid, Uuid), &Prediction> = ctx.predictions
+        .iter()
+        .map(|p| ((p.user_id, p.fixture_id), p))
+        .collect();
+
+    let users: HashSet<Uuid> = ctx.predictions.iter().map(|p| p.user_id).collect();
+
+    let mut awards = Vec::new();
+    for user_id in users {
+        let mut streak = 0;
+        for fixture in &ctx.fixtures {
+            let exact = predictions
+                .get(&(user_id, fixture.id))
+                .is_some_and(|p| is_exact(fixture, p));
+
+            if !exact {
+                streak = 0;
+                continue;
+            }
+
+            streak += 1;
+            if streak == EXACT_STREAK_LENGTH {
+                awards.push(Award {
+                    user_id,
+                    badge: Badge::ExactStreak,
+                    gameweek_id: Some(fixture.gameweek_id),
+                });
+            }
+        }
+    }
+
+    awards
+}
+
+fn weekly_winner(ctx: &SeasonContext) -> Vec<Award> {
+    let mut best: HashMap<Uuid, i32> = HashMap::new();
+    for score in &ctx.scores {
+        let top = best.entry(score.gameweek_id).or_insert(0);
+        *top = (*top).max(score.total_points);
+    }
+
+    ctx.scores
+        .iter()
+        .filter(|score| score.total_points > 0 && best.get(&score.gameweek_id) == Some(&score.total_points))
+        .map(|score| Award {
+            user_id: score.user_id,
+            badge: Badge::WeeklyWinner,
+            gameweek_id: Some(score.gameweek_id),
+        })
+        .collect()
+}
+
+fn goalless_draw(ctx: &SeasonContext) -> Vec<Award> {
+    let goalless: HashMap<Uuid, &Fixture> = ctx.fixtures
+        .iter()
+        .filter(|f| f.home_score == Some(0) && f.away_score == Some(0))
+        .map(|f| (f.id, f))
+        .collect();
+
+    ctx.predictions
+        .iter()
+        .filter(|p| p.home_score_prediction == 0 && p.away_score_prediction == 0)
+        .filter_map(|p| goalless.get(&p.fixture_id).map(|f| Award {
+            user_id: p.user_id,
+            badge: Badge::GoallessDraw,
+            gameweek_id: Some(f.gameweek_id),
+        }))
+        .collect()
+}
+
+fn ever_present(ctx: &SeasonContext) -> Vec<Award> {
+    if ctx.gameweeks.len() < GAMEWEEKS_PER_SEASON {
+        return vec![];
+    }
+
+    let mut played: HashMap<Uuid, usize> = HashMap::new();
+    for score in &ctx.scores {
+        *played.entry(score.user_id).or_insert(0) += 1;
+    }
+
+    played
+        .into_iter()
+        .filter(|(_, count)| *count >= ctx.gameweeks.len())
+        .map(|(user_id, _)| Award {
+            user_id,
+            badge: Badge::EverPresent,
+            gameweek_id: None,
+        })
+        .collect()
+}
+
+pub async fn load_season_context(
+    db: &PgPool,
+    season: &str,
+) -> Result<SeasonContext, AppError> {
+    let gameweeks = query_as::<_, Gameweek>(
+        "SELECT * FROM gameweeks WHERE season = $1 AND is_completed = true ORDER BY week_number",
+    )
+    .bind(season)
+    .fetch_all(db)
+    .await?;
+
+    let gameweek_ids: Vec<Uuid> = gameweeks.iter().map(|gw| gw.id).collect();
+
+    let fixtures = query_as::<_, Fixture>(
+        r#"
+        SELECT f.* FROM fixtures f
+        JOIN gameweeks gw ON f.gameweek_id = gw.id
+        WHERE f.gameweek_id = ANY($1) AND f.home_score IS NOT NULL AND f.away_score IS NOT NULL
+        ORDER BY gw.week_number, f.fixture_order
+        "#,
+    )
+    .bind(&gameweek_ids)
+    .fetch_all(db)
+    .await?;
+
+    let fixture_ids: Vec<Uuid> = fixtures.iter().map(|f| f.id).collect();
+
+    let predictions = query_as::<_, Prediction>(
+        r#"
+        SELECT p.* FROM predictions p
+        JOIN users u ON p.user_id = u.id
+        WHERE p.fixture_id = ANY($1) AND u.is_admin = false
+        "#,
+    )
+    .bind(&fixture_ids)
+    .fetch_all(db)
+    .await?;
+
+    let scores = query_as::<_, GameweekScore>(
+        r#"
+        SELECT gs.* FROM gameweek_scores gs
+        JOIN users u ON gs.user_id = u.id
+        WHERE gs.gameweek_id = ANY($1) AND u.is_admin = false
+        "#,
+    )
+    .bind(&gameweek_ids)
+    .fetch_all(db)
+    .await?;
+
+    Ok(SeasonContext {
+        gameweeks,
+        fixtures,
+        predictions,
+        scores,
+    })
+}
+
+/// Re-evaluates every rule for the season, adding newly earned badges and
+/// removing any that no longer hold after a result correction.
+pub async fn award_season_achievements(
+    db: &PgPool,
+    season: &str,
+) -> Result<(), AppError> {
+    let ctx = load_season_context(db, season).await?;
+    let awards = evaluate(&ctx);
+
+    let existing = query_as::<_, Achievement>(
+        "SELECT * FROM achievements WHERE season = $1",
+    )
+    .bind(season)
+    .fetch_all(db)
+    .await?;
+
+    let mut already_awarded = HashSet::new();
+    for achievement in existing {
+        let Some(badge) = Badge::from_key(&achievement.badge) else {
+            continue;
+        };
+
+        let award = Award {
+            user_id: achievement.user_id,
+            badge,
+            gameweek_id: achievement.gameweek_id,
+        };
+
+        if awards.contains(&award) {
+            already_awarded.insert(award);
+        } else {
+            query("DELETE FROM achievements WHERE id = $1")
+                .bind(achievement.id)
+                .execute(db)
+                .await?;
+        }
+    }
+
+    for award in awards.difference(&already_awarded) {
+        query(
+            r#"
+            INSERT INTO achievements (user_id, badge, season, gameweek_id)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT DO NOTHING
+            "#
+        )
+        .bind(award.user_id)
+        .bind(award.badge.key())
+        .bind(season)
+        .bind(award.gameweek_id)
+        .execute(db)
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Evaluates achievements for every season with at least one completed
+/// gameweek. Returns the number of seasons processed.
+pub async fn backfill_achievements(db: &PgPool) -> Result<usize, AppError> {
+    let seasons = query!(
+        "SELECT DISTINCT season FROM gameweeks WHERE is_completed = true ORDER BY season"
+    )
+    .fetch_all(db)
+    .await?;
+
+    for row in &seasons {
+        award_season_achievements(db, &row.season).await?;
+    }
+
+    Ok(seasons.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn fixture(gameweek_id: Uuid, home: i32, away: i32) -> Fixture {
+        Fixture {
+            id: Uuid::new_v4(),
+            gameweek_id,
+            home_team: "Home".to_string(),
+            away_team: "Away".to_string(),
+            kickoff_time: Utc::now(),
+            home_score: Some(home),
+            away_score: Some(away),
+            fixture_order: 1,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    fn prediction(user_id: Uuid, fixture: &Fixture, home: i32, away: i32) -> Prediction {
+        Prediction {
+            id: Uuid::new_v4(),
+            user_id,
+            fixture_id: fixture.id,
+            home_score_prediction: home,
+            away_score_prediction: away,
+            points_awarded: 0,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_exact_streak_spans_gameweeks() {
+        let user = Uuid::new_v4();
+        let (gw1, gw2) = (Uuid::new_v4(), Uuid::new_v4());
+        let fixtures = vec![fixture(gw1, 1, 0), fixture(gw1, 2, 2), fixture(gw2, 0, 3)];
+        let predictions = fixtures.iter().map(|f| prediction(user, f, f.home_score.unwrap(), f.away_score.unwrap())).collect();
+
+        let ctx = SeasonContext { fixtures, predictions, ..Default::default() };
+
+        assert_eq!(exact_streak(&ctx), vec![Award { user_id: user, badge: Badge::ExactStreak, gameweek_id: Some(gw2) }]);
+    }
+
+    #[test]
+    fn test_missing_prediction_breaks_streak() {
+        let user = Uuid::new_v4();
+        let gw = Uuid::new_v4();
+        let fixtures = vec![fixture(gw, 1, 0), fixture(gw, 2, 2), fixture(gw, 0, 3), fixture(gw, 1, 1)];
+        let predictions = vec![
+            prediction(user, &fixtures[0], 1, 0),
+            prediction(user, &fixtures[1], 2, 2),
+            prediction(user, &fixtures[3], 1, 1),
+        ];
+
+        let ctx = SeasonContext { fixtures, predictions, ..Default::default() };
+
+        assert!(exact_streak(&ctx).is_empty());
+    }
+
+    #[test]
+    fn test_goalless_draw() {
+        let user = Uuid::new_v4();
+        let gw = Uuid::new_v4();
+        let fixtures = vec![fixture(gw, 0, 0), fixture(gw, 1, 1)];
+        let predictions = vec![
+            prediction(user, &fixtures[0], 0, 0),
+            prediction(user, &fixtures[1], 0, 0),
+        ];
+
+        let ctx = SeasonContext { fixtures, predictions, ..Default::default() };
+
+        assert_eq!(goalless_draw(&ctx).len(), 1);
+    }
+
+    #[test]
+    fn test_badge_keys_round_trip() {
+        for badge in [Badge::PerfectWeek, Badge::ExactStreak, Badge::WeeklyWinner, Badge::GoallessDraw, Badge::EverPresent] {
+            assert_eq!(Badge::from_key(badge.key()), Some(badge));
+        }
+    }
+}
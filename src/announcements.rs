@@ -0,0 +1,274 @@
+// announcements.rs
+//
+// Notices from the admins, such as rule changes or postponed fixtures, shown
+// on the home page and dashboard between their start and end times until a
+// player dismisses them. Gameweeks can also carry a note of their own.
+
+use std::str::FromStr;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::Serialize;
+use sqlx::{query, query_as, FromRow, PgExecutor, PgPool};
+use uuid::Uuid;
+use crate::errors::AppError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Info,
+    Warning,
+    Critical,
+}
+
+impl Severity {
+    pub const ALL: [Severity; 3] = [Severity::Info, Severity::Warning, Severity::Critical];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Critical => "critical",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Severity::Info => "Information",
+            Severity::Warning => "Warning",
+            Severity::Critical => "Critical",
+        }
+    }
+
+    /// Tailwind classes for the notice on player pages.
+    pub fn classes(&self) -> &'static str {
+        match self {
+            Severity::Info => "bg-blue-50 border-blue-400 text-blue-900",
+            Severity::Warning => "bg-yellow-50 border-yellow-400 text-yellow-900",
+            Severity::Critical => "bg-red-50 border-red-500 text-red-900",
+        }
+    }
+}
+
+impl FromStr for Severity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Severity::ALL
+            .into_iter()
+            .find(|severity| severity.as_str() == s)
+            .ok_or_else(|| format!("unknown severity '{}'", s))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Announcement {
+    pub id: Uuid,
+    pub title: String,
+    pub body: String,
+    pub severity: String,
+    pub starts_at: DateTime<Utc>,
+    /// `None` to show it until it's ended by hand.
+    pub ends_at: Option<DateTime<Utc>>,
+    /// Set when it was called off before it started.
+    pub cancelled_at: Option<DateTime<Utc>>,
+    pub created_by: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Announcement {
+    pub fn severity(&self) -> Severity {
+        self.severity.parse().unwrap_or(Severity::Info)
+    }
+
+    pub fn is_live(&self, now: DateTime<Utc>) -> bool {
+        self.cancelled_at.is_none() && self.starts_at <= now && self.ends_at.is_none_or(|ends_at| ends_at > now)
+    }
+
+    /// "Cancelled", "Scheduled", "Live" or "Ended", for the admin list.
+    pub fn status(&self, now: DateTime<Utc>) -> &'static str {
+        if self.cancelled_at.is_some() {
+            "Cancelled"
+        } else if self.starts_at > now {
+            "Scheduled"
+        } else if self.is_live(now) {
+            "Live"
+        } else {
+            "Ended"
+        }
+    }
+}
+
+/// Reads a `datetime-local` form value as UTC. Blank is `None`.
+pub fn parse_schedule_time(value: &str) -> Result<Option<DateTime<Utc>>, String> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Ok(None);
+    }
+
+    ["%Y-%m-%dT%H:%M", "%Y-%m-%dT%H:%M:%S"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+        .map(|time| Some(time.and_utc()))
+        .ok_or_else(|| format!("'{}' isn't a date and time", value))
+}
+
+/// Announcements showing now, most severe first. Signed-in players don't see
+/// the ones they've dismissed.
+pub async fn current(db: &PgPool, user_id: Option<Uuid>) -> Result<Vec<Announcement>, AppError> {
+    let announcements = query_as::<_, Announcement>(
+        r#"
+        SELECT a.* FROM announcements a
+        WHERE a.cancelled_at IS NULL
+          AND a.starts_at <= NOW() AND (a.ends_at IS NULL OR a.ends_at > NOW())
+          AND NOT EXISTS (
+              SELECT 1 FROM announcement_dismissals d
+              WHERE d.announcement_id = a.id AND d.user_id = $1
+          )
+        ORDER BY CASE a.severity WHEN 'critical' THEN 0 WHEN 'warning' THEN 1 ELSE 2 END, a.starts_at DESC
+        "#
+    )
+    .bind(user_id)
+    .fetch_all(db)
+    .await?;
+
+    Ok(announcements)
+}
+
+/// Every announcement, newest first, for the admin page.
+pub async fn all(db: &PgPool) -> Result<Vec<Announcement>, AppError> {
+    let announcements = query_as::<_, Announcement>(
+        "SELECT * FROM announcements ORDER BY starts_at DESC"
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(announcements)
+}
+
+pub async fn create(
+    db: impl PgExecutor<'_>,
+    title: &str,
+    body: &str,
+    severity: Severity,
+    starts_at: DateTime<Utc>,
+    ends_at: Option<DateTime<Utc>>,
+    created_by: Uuid,
+) -> Result<Announcement, AppError> {
+    let announcement = query_as::<_, Announcement>(
+        r#"
+        INSERT INTO announcements (title, body, severity, starts_at, ends_at, created_by)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        RETURNING *
+        "#
+    )
+    .bind(title.trim())
+    .bind(body.trim())
+    .bind(severity.as_str())
+    .bind(starts_at)
+    .bind(ends_at)
+    .bind(created_by)
+    .fetch_one(db)
+    .await?;
+
+    Ok(announcement)
+}
+
+/// Takes an announcement down now. One that hasn't started yet is cancelled
+/// instead, so it never shows.
+pub async fn end(db: impl PgExecutor<'_>, announcement_id: Uuid) -> Result<Announcement, AppError> {
+    query_as::<_, Announcement>(
+        r#"
+        UPDATE announcements
+        SET ends_at = CASE WHEN starts_at >= NOW() THEN ends_at ELSE NOW() END,
+            cancelled_at = CASE WHEN starts_at >= NOW() THEN NOW() END
+        WHERE id = $1 AND cancelled_at IS NULL AND (ends_at IS NULL OR ends_at > NOW())
+        RETURNING *
+        "#
+    )
+    .bind(announcement_id)
+    .fetch_optional(db)
+    .await?
+    .ok_or(AppError::NotFound)
+}
+
+pub async fn dismiss(db: &PgPool, announcement_id: Uuid, user_id: Uuid) -> Result<(), AppError> {
+    query!(
+        r#"
+        INSERT INTO announcement_dismissals (announcement_id, user_id)
+        SELECT id, $2 FROM announcements WHERE id = $1
+        ON CONFLICT DO NOTHING
+        "#,
+        announcement_id,
+        user_id
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+/// Sets or, when blank, clears a gameweek's note. Returns the note it had.
+pub async fn set_gameweek_note(db: impl PgExecutor<'_>, gameweek_id: Uuid, note: &str) -> Result<Option<String>, AppError> {
+    let note = Some(note.trim()).filter(|note| !note.is_empty());
+
+    let previous = query!(
+        r#"
+        UPDATE gameweeks gw SET note = $1
+        FROM (SELECT id, note FROM gameweeks WHERE id = $2 FOR UPDATE) old
+        WHERE gw.id = old.id
+        RETURNING old.note
+        "#,
+        note,
+        gameweek_id
+    )
+    .fetch_optional(db)
+    .await?
+    .ok_or(AppError::NotFound)?
+    .note;
+
+    Ok(previous)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn test_schedule() {
+        let now = Utc::now();
+        let mut announcement = Announcement {
+            id: Uuid::nil(),
+            title: "Gameweek 12 postponed".to_string(),
+            body: "Moved to midweek".to_string(),
+            severity: "warning".to_string(),
+            starts_at: now + Duration::hours(1),
+            ends_at: None,
+            cancelled_at: None,
+            created_by: None,
+            created_at: now,
+        };
+
+        assert_eq!(announcement.severity(), Severity::Warning);
+        assert_eq!(announcement.status(now), "Scheduled");
+
+        announcement.starts_at = now - Duration::hours(1);
+        assert_eq!(announcement.status(now), "Live");
+
+        announcement.ends_at = Some(now);
+        assert_eq!(announcement.status(now), "Ended");
+
+        announcement.cancelled_at = Some(now);
+        assert_eq!(announcement.status(now), "Cancelled");
+        assert!(!announcement.is_live(now - Duration::minutes(30)));
+
+        assert!("urgent".parse::<Severity>().is_err());
+    }
+
+    #[test]
+    fn test_parse_schedule_time() {
+        let time = parse_schedule_time("2024-09-14T12:30").unwrap().unwrap();
+        assert_eq!(time.to_rfc3339(), "2024-09-14T12:30:00+00:00");
+
+        assert_eq!(parse_schedule_time("  "), Ok(None));
+        assert!(parse_schedule_time("next Tuesday").is_err());
+    }
+}
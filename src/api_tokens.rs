@@ -0,0 +1,176 @@
+// api_tokens.rs
+
+use std::str::FromStr;
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use sqlx::{query, query_as, PgPool};
+use uuid::Uuid;
+use crate::errors::AppError;
+use crate::models::{ApiToken, User};
+
+/// Prefix that makes tokens easy to recognise, e.g. in leaked-secret scans.
+const TOKEN_PREFIX: &str = "s6_";
+const TOKEN_BYTES: usize = 32;
+/// Characters of the token kept in clear so users can tell tokens apart.
+const DISPLAY_PREFIX_LEN: usize = 10;
+
+/// What a personal API token is allowed to do. Tokens never grant admin
+/// access; that needs a signed-in session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TokenScope {
+    Read,
+    Predict,
+}
+
+impl TokenScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TokenScope::Read => "read",
+            TokenScope::Predict => "predict",
+        }
+    }
+}
+
+impl FromStr for TokenScope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "read" => Ok(TokenScope::Read),
+            "predict" => Ok(TokenScope::Predict),
+            other => Err(format!("unknown token scope '{}'", other)),
+        }
+    }
+}
+
+/// A newly generated token. `secret` is shown to the user once and never stored.
+pub struct NewToken {
+    pub secret: String,
+    pub hash: String,
+    pub display_prefix: String,
+}
+
+/// A random hex secret, also used for single-use links sent by email.
+pub fn random_secret() -> String {
+    let mut bytes = [0u8; TOKEN_BYTES];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+pub fn generate_token() -> NewToken {
+    let secret = format!("{}{}", TOKEN_PREFIX, random_secret());
+
+    NewToken {
+        hash: hash_token(&secret),
+        display_prefix: secret[..DISPLAY_PREFIX_LEN].to_string(),
+        secret,
+    }
+}
+
+/// Tokens are high-entropy, so a fast unsalted hash is enough and lets us
+/// look them up directly.
+pub fn hash_token(secret: &str) -> String {
+    hex::encode(Sha256::digest(secret.as_bytes()))
+}
+
+pub async fn create_token(
+    db: &PgPool,
+    user_id: Uuid,
+    name: &str,
+    scope: TokenScope,
+) -> Result<String, AppError> {
+    let token = generate_token();
+
+    query!(
+        r#"
+        INSERT INTO api_tokens (user_id, name, token_hash, token_prefix, scope)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        user_id,
+        name,
+        token.hash,
+        token.display_prefix,
+        scope.as_str()
+    )
+    .execute(db)
+    .await?;
+
+    Ok(token.secret)
+}
+
+pub async fn user_tokens(db: &PgPool, user_id: Uuid) -> Result<Vec<ApiToken>, AppError> {
+    let tokens = query_as::<_, ApiToken>(
+        "SELECT * FROM api_tokens WHERE user_id = $1 ORDER BY revoked_at IS NOT NULL, created_at DESC"
+    )
+    .bind(user_id)
+    .fetch_all(db)
+    .await?;
+
+    Ok(tokens)
+}
+
+pub async fn revoke_token(db: &PgPool, user_id: Uuid, token_id: Uuid) -> Result<(), AppError> {
+    let result = query!(
+        "UPDATE api_tokens SET revoked_at = NOW() WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL",
+        token_id,
+        user_id
+    )
+    .execute(db)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound);
+    }
+
+    Ok(())
+}
+
+/// Resolves a bearer token to its user and scope, recording when it was used.
+pub async fn authenticate_token(db: &PgPool, secret: &str) -> Result<(User, TokenScope), AppError> {
+    let token = query_as::<_, ApiToken>(
+        "SELECT * FROM api_tokens WHERE token_hash = $1 AND revoked_at IS NULL"
+    )
+    .bind(hash_token(secret))
+    .fetch_optional(db)
+    .await?
+    .ok_or(AppError::InvalidToken)?;
+
+    let user = query_as::<_, User>(
+        "SELECT * FROM users WHERE id = $1"
+    )
+    .bind(token.user_id)
+    .fetch_optional(db)
+    .await?
+    .ok_or(AppError::UserNotFound)?;
+
+    // Only touch the row once a minute so busy bots don't write on every request
+    query!(
+        r#"
+        UPDATE api_tokens SET last_used_at = NOW()
+        WHERE id = $1 AND (last_used_at IS NULL OR last_used_at < NOW() - INTERVAL '1 minute')
+        "#,
+        token.id
+    )
+    .execute(db)
+    .await?;
+
+    let scope = token.scope.parse().map_err(|_| AppError::Internal)?;
+
+    Ok((user, scope))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generated_tokens_hash_consistently() {
+        let token = generate_token();
+
+        assert!(token.secret.starts_with(TOKEN_PREFIX));
+        assert_eq!(token.secret.len(), TOKEN_PREFIX.len() + TOKEN_BYTES * 2);
+        assert!(token.secret.starts_with(&token.display_prefix));
+        assert_eq!(token.hash, hash_token(&token.secret));
+        assert_ne!(token.hash, generate_token().hash);
+    }
+}
@@ -0,0 +1,417 @@
+// audit.rs
+//
+// The audit log: who did what, when and from where, with the values before
+// and after for anything that changed. Events are recorded on the same
+// connection as the change they describe, inside its transaction, so one is
+// never committed without the other.
+//
+// `audit_events` is append-only; the database refuses updates and deletes.
+// The one exception is `forget_user`, which account deletion uses to take a
+// player's personal details out of the events that mention them.
+
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{query, query_as, Connection, FromRow, PgConnection, PgExecutor, PgPool};
+use uuid::Uuid;
+use crate::errors::AppError;
+use crate::sessions::ClientInfo;
+
+pub const LOGIN_SUCCEEDED: &str = "login.succeeded";
+pub const LOGIN_FAILED: &str = "login.failed";
+pub const LOGIN_LOCKED_OUT: &str = "login.locked_out";
+pub const TWO_FACTOR_FAILED: &str = "login.two_factor_failed";
+pub const SSO_FAILED: &str = "login.sso_failed";
+pub const SSO_LINKED: &str = "login.sso_linked";
+pub const SSO_REGISTERED: &str = "login.sso_registered";
+pub const IMPERSONATION_STARTED: &str = "impersonation.started";
+pub const IMPERSONATION_ENDED: &str = "impersonation.ended";
+/// A change made while viewing the site as a player.
+pub const IMPERSONATION_REQUEST: &str = "impersonation.request";
+pub const ROLE_GRANTED: &str = "role.granted";
+pub const ROLE_REVOKED: &str = "role.revoked";
+pub const USER_CREATED: &str = "user.created";
+pub const USER_UPDATED: &str = "user.updated";
+pub const USER_SUSPENDED: &str = "user.suspended";
+pub const USER_REINSTATED: &str = "user.reinstated";
+pub const USER_PASSWORD_RESET_FORCED: &str = "user.password_reset_forced";
+pub const USER_MERGED: &str = "user.merged";
+pub const ACCOUNT_UPDATED: &str = "account.updated";
+pub const ACCOUNT_EMAIL_CHANGED: &str = "account.email_changed";
+pub const ACCOUNT_PASSWORD_CHANGED: &str = "account.password_changed";
+pub const ACCOUNT_DELETED: &str = "account.deleted";
+pub const GAMEWEEK_CREATED: &str = "gameweek.created";
+pub const GAMEWEEK_NOTE_UPDATED: &str = "gameweek.note_updated";
+pub const ANNOUNCEMENT_CREATED: &str = "announcement.created";
+pub const ANNOUNCEMENT_ENDED: &str = "announcement.ended";
+pub const FIXTURES_REPLACED: &str = "fixtures.replaced";
+pub const RESULTS_SUBMITTED: &str = "results.submitted";
+pub const SCORES_RECALCULATED: &str = "scores.recalculated";
+pub const SETTING_CHANGED: &str = "setting.changed";
+
+/// Groups of actions the viewer can filter on, by the prefix before the dot.
+pub const CATEGORIES: [(&str, &str); 11] = [
+    ("login", "Logins"),
+    ("impersonation", "Viewing as a player"),
+    ("role", "Role changes"),
+    ("user", "User management"),
+    ("account", "Account changes"),
+    ("gameweek", "Gameweeks"),
+    ("announcement", "Announcements"),
+    ("fixtures", "Fixtures"),
+    ("results", "Results"),
+    ("scores", "Score recalculation"),
+    ("setting", "Settings"),
+];
+
+pub const EVENTS_PER_PAGE: i64 = 50;
+
+/// Recorded in the details of changes made with `superior6-admin`, as there's
+/// no admin to name.
+pub const VIA_CLI: &str = "superior6-admin";
+
+/// Who made a change that both the admin pages and `superior6-admin` can make.
+#[derive(Debug, Clone, Copy)]
+pub enum Actor<'a> {
+    Admin(Uuid, &'a ClientInfo),
+    Cli,
+}
+
+/// An event waiting to be recorded.
+#[derive(Debug)]
+pub struct Event<'a> {
+    action: &'a str,
+    actor_id: Option<Uuid>,
+    ip_address: Option<&'a str>,
+    details: Value,
+    before: Option<Value>,
+    after: Option<Value>,
+    via: Option<&'a str>,
+}
+
+impl<'a> Event<'a> {
+    pub fn new(action: &'a str) -> Self {
+        Self {
+            action,
+            actor_id: None,
+            ip_address: None,
+            details: Value::Object(Default::default()),
+            before: None,
+            after: None,
+            via: None,
+        }
+    }
+
+    /// Done by `actor_id`, from the client making the request.
+    pub fn by(mut self, actor_id: Uuid, client: &'a ClientInfo) -> Self {
+        self.actor_id = Some(actor_id);
+        self.ip_address = client.ip_address.as_deref();
+        self
+    }
+
+    pub fn by_actor(mut self, actor: Actor<'a>) -> Self {
+        match actor {
+            Actor::Admin(actor_id, client) => self.by(actor_id, client),
+            Actor::Cli => {
+                self.via = Some(VIA_CLI);
+                self
+            }
+        }
+    }
+
+    pub fn details(mut self, details: Value) -> Self {
+        self.details = details;
+        self
+    }
+
+    /// The values something had before the action and has after it.
+    pub fn change(mut self, before: impl Serialize, after: impl Serialize) -> Self {
+        self.before = serde_json::to_value(before).ok();
+        self.after = serde_json::to_value(after).ok();
+        self
+    }
+
+    pub async fn record(mut self, db: impl PgExecutor<'_>) -> Result<(), AppError> {
+        if let (Some(via), Value::Object(details)) = (self.via, &mut self.details) {
+            details.insert("via".to_string(), via.into());
+        }
+
+        query!(
+            r#"
+            INSERT INTO audit_events (action, actor_id, actor_email, ip_address, details, before_values, after_values)
+            VALUES ($1, $2, (SELECT email FROM users WHERE id = $2), $3, $4, $5, $6)
+            "#,
+            self.action,
+            self.actor_id,
+            self.ip_address,
+            self.details,
+            self.before,
+            self.after
+        )
+        .execute(db)
+        .await?;
+
+        Ok(())
+    }
+}
+
+/// Records an event with no before and after values.
+pub async fn record(
+    db: impl PgExecutor<'_>,
+    action: &str,
+    actor_id: Option<Uuid>,
+    ip_address: Option<&str>,
+    details: Value,
+) -> Result<(), AppError> {
+    let event = Event {
+        actor_id,
+        ip_address,
+        ..Event::new(action).details(details)
+    };
+
+    event.record(db).await
+}
+
+/// Strips the user's email, IP address and names from every event that
+/// mentions them, leaving their ID so what they did can still be followed.
+pub async fn forget_user(db: &mut PgConnection, user_id: Uuid) -> Result<(), AppError> {
+    let mut tx = db.begin().await?;
+
+    // Lets this transaction through the append-only trigger
+    query!("SELECT set_config('superior6.audit_scrub', 'on', true)")
+        .fetch_one(&mut *tx)
+        .await?;
+
+    query!(
+        r#"
+        UPDATE audit_events SET
+            actor_email = CASE WHEN actor_id = $1 THEN NULL ELSE actor_email END,
+            ip_address = CASE WHEN actor_id = $1 THEN NULL ELSE ip_address END,
+            details = audit_scrub_json(
+                CASE WHEN actor_id = $1 THEN details - ARRAY['name', 'display_name', 'email', 'from', 'to'] ELSE details END,
+                $1::TEXT
+            ),
+            before_values = audit_scrub_json(before_values, $1::TEXT),
+            after_values = audit_scrub_json(after_values, $1::TEXT)
+        WHERE actor_id = $1
+           OR POSITION($1::TEXT IN details::TEXT) > 0
+           OR POSITION($1::TEXT IN COALESCE(before_values::TEXT, '')) > 0
+           OR POSITION($1::TEXT IN COALESCE(after_values::TEXT, '')) > 0
+        "#,
+        user_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    query!("SELECT set_config('superior6.audit_scrub', 'off', true)")
+        .fetch_one(&mut *tx)
+        .await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// A row in the audit log viewer.
+#[derive(Debug, Clone, FromRow)]
+pub struct AuditEvent {
+    pub id: Uuid,
+    pub action: String,
+    pub actor_id: Option<Uuid>,
+    pub actor_email: Option<String>,
+    pub ip_address: Option<String>,
+    pub details: Value,
+    pub before_values: Option<Value>,
+    pub after_values: Option<Value>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl AuditEvent {
+    /// The part of the action before the dot, e.g. "results".
+    pub fn category(&self) -> &str {
+        self.action.split('.').next().unwrap_or(&self.action)
+    }
+
+    pub fn details_text(&self) -> String {
+        match &self.details {
+            Value::Object(fields) if fields.is_empty() => String::new(),
+            details => details.to_string(),
+        }
+    }
+
+    pub fn before_text(&self) -> Option<String> {
+        self.before_values.as_ref().map(pretty)
+    }
+
+    pub fn after_text(&self) -> Option<String> {
+        self.after_values.as_ref().map(pretty)
+    }
+}
+
+fn pretty(value: &Value) -> String {
+    serde_json::to_string_pretty(value).unwrap_or_else(|_| value.to_string())
+}
+
+/// What the viewer is narrowed down to. Everything is optional.
+#[derive(Debug, Clone, Default)]
+pub struct AuditFilter {
+    /// One of the `CATEGORIES` prefixes.
+    pub category: Option<String>,
+    /// Part of the actor's email.
+    pub actor: Option<String>,
+    /// Text anywhere in the details or the before and after values, e.g. a
+    /// team name or user ID.
+    pub search: Option<String>,
+    pub from: Option<NaiveDate>,
+    /// Inclusive: events on this day are shown.
+    pub to: Option<NaiveDate>,
+}
+
+impl AuditFilter {
+    fn category_pattern(&self) -> Option<String> {
+        self.category
+            .as_deref()
+            .filter(|category| CATEGORIES.iter().any(|(prefix, _)| prefix == category))
+            .map(|category| format!("{}.%", category))
+    }
+
+    fn starts_at(&self) -> Option<DateTime<Utc>> {
+        self.from.and_then(|day| day.and_hms_opt(0, 0, 0)).map(|time| time.and_utc())
+    }
+
+    fn ends_before(&self) -> Option<DateTime<Utc>> {
+        self.to
+            .and_then(|day| day.and_hms_opt(0, 0, 0))
+            .map(|time| time.and_utc() + Duration::days(1))
+    }
+}
+
+/// The viewer's filter form. Blank fields are sent as empty strings.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct AuditQuery {
+    #[serde(default)]
+    pub category: String,
+    #[serde(default)]
+    pub actor: String,
+    #[serde(default)]
+    pub q: String,
+    #[serde(default)]
+    pub from: String,
+    #[serde(default)]
+    pub to: String,
+    #[serde(skip_serializing)]
+    pub page: Option<i64>,
+}
+
+fn non_empty(value: &str) -> Option<String> {
+    Some(value.trim().to_string()).filter(|value| !value.is_empty())
+}
+
+fn parse_date(value: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(value.trim(), "%Y-%m-%d").ok()
+}
+
+impl AuditQuery {
+    pub fn filter(&self) -> AuditFilter {
+        AuditFilter {
+            category: non_empty(&self.category),
+            actor: non_empty(&self.actor),
+            search: non_empty(&self.q),
+            from: parse_date(&self.from),
+            to: parse_date(&self.to),
+        }
+    }
+}
+
+/// Turns a search box into an ILIKE pattern, or `None` if it's blank.
+fn contains_pattern(text: Option<&str>) -> Option<String> {
+    text.map(str::trim)
+        .filter(|text| !text.is_empty())
+        .map(crate::user_admin::search_pattern)
+}
+
+#[derive(Debug)]
+pub struct AuditPage {
+    pub events: Vec<AuditEvent>,
+    pub total: i64,
+    /// 1-based.
+    pub page: i64,
+}
+
+impl AuditPage {
+    pub fn total_pages(&self) -> i64 {
+        ((self.total + EVENTS_PER_PAGE - 1) / EVENTS_PER_PAGE).max(1)
+    }
+
+    pub fn has_previous(&self) -> bool {
+        self.page > 1
+    }
+
+    pub fn has_next(&self) -> bool {
+        self.page < self.total_pages()
+    }
+}
+
+const FILTER_CONDITIONS: &str = r#"
+    ($1::TEXT IS NULL OR action LIKE $1)
+    AND ($2::TEXT IS NULL OR actor_email ILIKE $2)
+    AND ($3::TEXT IS NULL OR details::TEXT ILIKE $3 OR before_values::TEXT ILIKE $3 OR after_values::TEXT ILIKE $3)
+    AND ($4::TIMESTAMPTZ IS NULL OR created_at >= $4)
+    AND ($5::TIMESTAMPTZ IS NULL OR created_at < $5)
+"#;
+
+/// Newest first.
+pub async fn search_events(db: &PgPool, filter: &AuditFilter, page: i64) -> Result<AuditPage, AppError> {
+    let page = page.max(1);
+    let category = filter.category_pattern();
+    let actor = contains_pattern(filter.actor.as_deref());
+    let search = contains_pattern(filter.search.as_deref());
+
+    let total: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM audit_events WHERE {}", FILTER_CONDITIONS))
+        .bind(&category)
+        .bind(&actor)
+        .bind(&search)
+        .bind(filter.starts_at())
+        .bind(filter.ends_before())
+        .fetch_one(db)
+        .await?;
+
+    let events = query_as::<_, AuditEvent>(&format!(
+        "SELECT * FROM audit_events WHERE {} ORDER BY created_at DESC LIMIT $6 OFFSET $7",
+        FILTER_CONDITIONS
+    ))
+    .bind(&category)
+    .bind(&actor)
+    .bind(&search)
+    .bind(filter.starts_at())
+    .bind(filter.ends_before())
+    .bind(EVENTS_PER_PAGE)
+    .bind((page - 1) * EVENTS_PER_PAGE)
+    .fetch_all(db)
+    .await?;
+
+    Ok(AuditPage { events, total, page })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter_bounds() {
+        let filter = AuditFilter {
+            category: Some("results".to_string()),
+            from: NaiveDate::from_ymd_opt(2024, 9, 1),
+            to: NaiveDate::from_ymd_opt(2024, 9, 1),
+            ..Default::default()
+        };
+
+        assert_eq!(filter.category_pattern().as_deref(), Some("results.%"));
+        assert_eq!(filter.starts_at().unwrap().to_rfc3339(), "2024-09-01T00:00:00+00:00");
+        // The whole of the last day is included
+        assert_eq!(filter.ends_before().unwrap().to_rfc3339(), "2024-09-02T00:00:00+00:00");
+
+        let unknown = AuditFilter { category: Some("%".to_string()), ..Default::default() };
+        assert_eq!(unknown.category_pattern(), None);
+        assert_eq!(contains_pattern(Some("  ")), None);
+    }
+}
@@ -2,38 +2,53 @@
 
 use axum::{async_trait, RequestPartsExt};
 use axum::extract::FromRequestParts;
+use axum::http::header::AUTHORIZATION;
 use axum::http::request::Parts;
 use axum_extra::extract::CookieJar;
-use bcrypt::{hash, verify, DEFAULT_COST};
-use chrono::{Duration, Utc};
+use bcrypt::{hash, verify};
+use chrono::{DateTime, Utc};
 use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
 use serde::{Deserialize, Serialize};
-use sqlx::query_as;
 use uuid::Uuid;
 use crate::AppState;
+use crate::api_tokens::{authenticate_token, random_secret, TokenScope};
 use crate::errors::AppError;
+use crate::impersonation::Impersonating;
 use crate::models::User;
+use crate::roles::{any_grants, user_roles, AdminPermissions, Permission, Role};
+use crate::sessions::{session_user, SESSION_COOKIE};
+use crate::{telemetry, two_factor};
 
+/// Session cookie claims. Everything else about the user, including whether
+/// they are an admin, is read from the database on each request so that
+/// changes take effect immediately.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
-    pub sub: String,
-    pub email: String,
-    pub is_admin: bool,
+    pub sub: Uuid,
+    /// The `sessions` row this token belongs to.
+    pub sid: Uuid,
+    /// The session's generation when this token was issued. Rotating the
+    /// token moves the session on, which retires the older tokens.
+    #[serde(default)]
+    pub generation: i32,
+    pub iat: i64,
     pub exp: i64,
 }
 
 impl Claims {
-    pub fn new(user: &User, jwt_secret: &str) -> Result<String, AppError> {
-        let expiration = Utc::now()
-            .checked_add_signed(Duration::days(30))
-            .expect("valid timestamp")
-            .timestamp();
-
+    pub fn new(
+        user_id: Uuid,
+        session_id: Uuid,
+        generation: i32,
+        expires_at: DateTime<Utc>,
+        jwt_secret: &str,
+    ) -> Result<String, AppError> {
         let claims = Claims {
-            sub: user.id.to_string(),
-            email: user.email.clone(),
-            is_admin: user.is_admin,
-            exp: expiration,
+            sub: user_id,
+            sid: session_id,
+            generation,
+            iat: Utc::now().timestamp(),
+            exp: expires_at.timestamp(),
         };
 
         encode(
@@ -55,8 +70,16 @@ impl Claims {
     }
 }
 
-pub fn hash_password(password: &str) -> Result<String, AppError> {
-    hash(password, DEFAULT_COST).map_err(|_| AppError::HashingError)
+/// Hashes with the configured `bcrypt_cost`.
+pub fn hash_password(password: &str, cost: u32) -> Result<String, AppError> {
+    hash(password, cost).map_err(|_| AppError::HashingError)
+}
+
+/// A hash no password matches, for accounts that mustn't sign in with one.
+/// The secret is random and thrown away, so it's hashed as cheaply as bcrypt
+/// allows.
+pub fn unusable_password_hash() -> Result<String, AppError> {
+    hash(random_secret(), 4).map_err(|_| AppError::HashingError)
 }
 
 pub fn verify_password(password: &str, hash: &str) -> Result<bool, AppError> {
@@ -66,6 +89,61 @@ pub fn verify_password(password: &str, hash: &str) -> Result<bool, AppError> {
 #[derive(Debug, Clone)]
 pub struct AuthUser {
     pub user: User,
+    /// Set when the request was authenticated with a personal API token
+    /// rather than the session cookie.
+    pub token_scope: Option<TokenScope>,
+    /// The signed-in session, when authenticated by cookie.
+    pub session_id: Option<Uuid>,
+    /// The admin viewing the site as this user, if that's who's really here.
+    pub impersonated_by: Option<Uuid>,
+}
+
+impl AuthUser {
+    /// Sessions can do anything the user can; tokens only what their scope allows.
+    pub fn require_scope(&self, scope: TokenScope) -> Result<(), AppError> {
+        match self.token_scope {
+            Some(granted) if granted < scope => Err(AppError::InsufficientScope),
+            _ => Ok(()),
+        }
+    }
+
+    /// For pages that manage the account itself, which tokens and
+    /// impersonating admins must not reach.
+    pub fn require_session(&self) -> Result<(), AppError> {
+        if self.token_scope.is_some() {
+            return Err(AppError::InsufficientScope);
+        }
+        if self.impersonated_by.is_some() {
+            return Err(AppError::Impersonating);
+        }
+
+        Ok(())
+    }
+}
+
+fn bearer_token(parts: &Parts) -> Result<Option<&str>, AppError> {
+    let Some(header) = parts.headers.get(AUTHORIZATION) else {
+        return Ok(None);
+    };
+
+    let (scheme, token) = header
+        .to_str()
+        .ok()
+        .and_then(|value| value.split_once(' '))
+        .ok_or(AppError::InvalidToken)?;
+
+    if !scheme.eq_ignore_ascii_case("bearer") {
+        return Err(AppError::InvalidToken);
+    }
+
+    Ok(Some(token.trim()))
+}
+
+fn ensure_not_suspended(user: &User) -> Result<(), AppError> {
+    match user.suspended_at {
+        Some(_) => Err(AppError::AccountSuspended),
+        None => Ok(()),
+    }
 }
 
 #[async_trait]
@@ -76,36 +154,74 @@ impl FromRequestParts<AppState> for AuthUser {
         parts: &mut Parts,
         state: &AppState,
     ) -> Result<Self, Self::Rejection> {
+        // An Authorization header takes precedence over the cookie
+        if let Some(token) = bearer_token(parts)? {
+            let (user, scope) = authenticate_token(&state.db, token).await?;
+            ensure_not_suspended(&user)?;
+            telemetry::record_user(user.id, None);
+            return Ok(AuthUser { user, token_scope: Some(scope), session_id: None, impersonated_by: None });
+        }
+
         let cookies = parts
             .extract::<CookieJar>()
             .await
             .map_err(|_| AppError::InvalidToken)?;
 
         let token = cookies
-            .get("auth_token")
+            .get(SESSION_COOKIE)
             .map(|cookie| cookie.value())
             .ok_or(AppError::MissingToken)?;
 
         let claims = Claims::from_token(token, &state.config.jwt_secret)?;
+        let user = session_user(&state.db, &claims).await?;
+        ensure_not_suspended(&user)?;
 
-        let user_id = Uuid::parse_str(&claims.sub)
-            .map_err(|_| AppError::InvalidToken)?;
+        // Set by the `impersonation::track` middleware
+        let impersonating = parts
+            .extensions
+            .get::<Impersonating>()
+            .filter(|impersonating| impersonating.impersonation.session_id == claims.sid);
 
-        let user = query_as::<_, User>(
-            "SELECT * FROM users WHERE id = $1",
-        )
-        .bind(user_id)
-        .fetch_one(&state.db)
-        .await
-        .map_err(|_| AppError::UserNotFound)?;
+        if let Some(impersonating) = impersonating {
+            telemetry::record_user(impersonating.user.id, Some(user.id));
+            return Ok(AuthUser {
+                user: impersonating.user.clone(),
+                token_scope: None,
+                session_id: Some(claims.sid),
+                impersonated_by: Some(user.id),
+            });
+        }
 
-        Ok(AuthUser { user })
+        telemetry::record_user(user.id, None);
+        Ok(AuthUser { user, token_scope: None, session_id: Some(claims.sid), impersonated_by: None })
     }
 }
 
+/// A signed-in user holding at least one admin role. Handlers check the
+/// specific permission they need with `require`.
 #[derive(Debug, Clone)]
 pub struct AdminUser {
     pub user: User,
+    pub roles: Vec<Role>,
+    pub session_id: Uuid,
+}
+
+impl AdminUser {
+    pub fn can(&self, permission: Permission) -> bool {
+        any_grants(&self.roles, permission)
+    }
+
+    pub fn require(&self, permission: Permission) -> Result<(), AppError> {
+        if self.can(permission) {
+            Ok(())
+        } else {
+            Err(AppError::Forbidden)
+        }
+    }
+
+    pub fn permissions(&self) -> AdminPermissions {
+        AdminPermissions::for_roles(&self.roles)
+    }
 }
 
 #[async_trait]
@@ -117,12 +233,20 @@ impl FromRequestParts<AppState> for AdminUser {
         state: &AppState,
     ) -> Result<Self, Self::Rejection> {
         let auth_user = AuthUser::from_request_parts(parts, state).await?;
+        auth_user.require_session()?;
 
-        if !auth_user.user.is_admin {
+        let roles = user_roles(&state.db, auth_user.user.id).await?;
+        if roles.is_empty() {
             return Err(AppError::Forbidden);
         }
 
-        Ok(AdminUser { user: auth_user.user })
+        if state.config.require_admin_two_factor && !two_factor::is_enabled(&state.db, auth_user.user.id).await? {
+            return Err(AppError::TwoFactorRequired);
+        }
+
+        let session_id = auth_user.session_id.ok_or(AppError::MissingToken)?;
+
+        Ok(AdminUser { user: auth_user.user, roles, session_id })
     }
 }
 
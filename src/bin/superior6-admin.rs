@@ -0,0 +1,534 @@
+// bin/superior6-admin.rs
+//
+// League administration from the command line, for setting up a new install
+// and for jobs that are awkward through the admin pages:
+//
+//     superior6-admin migrate
+//     superior6-admin users create --email ref@example.com --name "Ref" --role super_admin
+//     superior6-admin seasons create 2025-26 --first-deadline 2025-08-15T18:00:00Z
+//     superior6-admin fixtures import 2025-26 1 fixtures.csv
+//     superior6-admin results import 2025-26 1 results.csv
+//     superior6-admin recompute 2025-26 --week 1
+//     superior6-admin export standings 2025-26 --format json
+//
+// It uses the server's configuration and database, and records what it
+// changes in the audit log without an actor. Fixtures and results go through
+// the same code as the admin pages.
+//
+// The server applies migrations when it starts; run `migrate` first on a new
+// install to set up the database without it.
+
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+use chrono::{DateTime, Duration, Utc};
+use clap::{Parser, Subcommand, ValueEnum};
+use rand::distributions::{Alphanumeric, DistString};
+use serde::Serialize;
+use serde_json::{json, Value};
+use sqlx::postgres::PgPoolOptions;
+use sqlx::{query, query_as, PgPool};
+use validator::Validate;
+
+use superior6::achievements::award_season_achievements;
+use superior6::audit::{self, Actor};
+use superior6::auth::hash_password;
+use superior6::config::{Config, Sources};
+use superior6::exports;
+use superior6::gameweeks;
+use superior6::imports;
+use superior6::ledger;
+use superior6::models::{CreateGameweek, CreateUser, Gameweek, User};
+use superior6::queries::gameweek_fixtures;
+use superior6::roles::{self, Role};
+
+type CliResult<T = ()> = Result<T, Box<dyn Error>>;
+
+#[derive(Parser)]
+#[command(name = "superior6-admin", about = "Superior 6 league administration")]
+struct Cli {
+    /// TOML config file, as for the server.
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Bring the database schema up to date
+    Migrate,
+    /// Create accounts and grant admin roles
+    #[command(subcommand)]
+    Users(UserCommand),
+    /// Set up a season's gameweeks
+    #[command(subcommand)]
+    Seasons(SeasonCommand),
+    /// Add single gameweeks and choose the active one
+    #[command(subcommand)]
+    Gameweeks(GameweekCommand),
+    /// Replace a gameweek's fixtures from a CSV file
+    #[command(subcommand)]
+    Fixtures(ImportCommand),
+    /// Enter a gameweek's results from a CSV file and score it
+    #[command(subcommand)]
+    Results(ImportCommand),
+    /// Recalculate scores for one gameweek or a whole season
+    Recompute {
+        season: String,
+        /// Only this gameweek. Otherwise every completed gameweek.
+        #[arg(long)]
+        week: Option<i32>,
+    },
+    /// Write out a season's data
+    Export {
+        #[arg(value_enum)]
+        data: ExportData,
+        season: String,
+        #[arg(long, value_enum, default_value_t = ExportFormat::Csv)]
+        format: ExportFormat,
+        /// Defaults to standard output.
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum UserCommand {
+    /// Create a verified account. Prints a generated password unless one is
+    /// given on standard input.
+    Create {
+        #[arg(long)]
+        email: String,
+        #[arg(long)]
+        name: String,
+        /// Defaults to the name.
+        #[arg(long)]
+        display_name: Option<String>,
+        /// Read the password from the first line of standard input.
+        #[arg(long)]
+        password_stdin: bool,
+        #[arg(long, value_parser = parse_role)]
+        role: Vec<Role>,
+    },
+    /// Grant an existing account an admin role
+    Promote {
+        email: String,
+        #[arg(long, value_parser = parse_role, default_value = "super_admin")]
+        role: Role,
+    },
+}
+
+#[derive(Subcommand)]
+enum SeasonCommand {
+    /// Create a season's gameweeks with evenly spaced deadlines, skipping any
+    /// that already exist. None is made active.
+    Create {
+        season: String,
+        /// Deadline for gameweek 1, e.g. 2025-08-15T18:00:00Z.
+        #[arg(long)]
+        first_deadline: DateTime<Utc>,
+        #[arg(long, default_value_t = 38)]
+        weeks: i32,
+        #[arg(long, default_value_t = 7)]
+        days_between: i64,
+    },
+}
+
+#[derive(Subcommand)]
+enum GameweekCommand {
+    Create {
+        season: String,
+        week: i32,
+        #[arg(long)]
+        deadline: DateTime<Utc>,
+        /// Make it the gameweek players predict on now.
+        #[arg(long)]
+        activate: bool,
+    },
+    /// Make a gameweek the one players predict on now
+    Activate {
+        season: String,
+        week: i32,
+    },
+}
+
+#[derive(Subcommand)]
+enum ImportCommand {
+    /// Fixtures: home_team,away_team,kickoff_time. Results:
+    /// home_team,away_team,home_score,away_score.
+    Import {
+        season: String,
+        week: i32,
+        file: PathBuf,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ExportData {
+    Standings,
+    Predictions,
+    Ledger,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum ExportFormat {
+    Csv,
+    Json,
+}
+
+fn parse_role(value: &str) -> Result<Role, String> {
+    value.parse()
+}
+
+#[tokio::main]
+async fn main() {
+    dotenvy::dotenv().ok();
+    let cli = Cli::parse();
+
+    if let Err(error) = run(cli).await {
+        eprintln!("error: {}", error);
+        std::process::exit(1);
+    }
+}
+
+async fn run(cli: Cli) -> CliResult {
+    let config = Config::load(&Sources::new(cli.config.as_deref())?)?;
+
+    let db = PgPoolOptions::new()
+        .max_connections(config.database_max_connections)
+        .connect(&config.database_url)
+        .await?;
+
+    match cli.command {
+        Command::Migrate => migrate(&db).await,
+        Command::Users(UserCommand::Create { email, name, display_name, password_stdin, role }) => {
+            create_user(&db, &config, &email, &name, display_name.as_deref(), password_stdin, &role).await
+        }
+        Command::Users(UserCommand::Promote { email, role }) => promote_user(&db, &email, role).await,
+        Command::Seasons(SeasonCommand::Create { season, first_deadline, weeks, days_between }) => {
+            create_season(&db, &season, first_deadline, weeks, days_between).await
+        }
+        Command::Gameweeks(GameweekCommand::Create { season, week, deadline, activate }) => {
+            create_gameweek(&db, &season, week, deadline, activate).await
+        }
+        Command::Gameweeks(GameweekCommand::Activate { season, week }) => {
+            let gameweek = find_gameweek(&db, &season, week).await?;
+            activate_gameweek(&db, &gameweek).await
+        }
+        Command::Fixtures(ImportCommand::Import { season, week, file }) => {
+            import_fixtures(&db, &season, week, &file).await
+        }
+        Command::Results(ImportCommand::Import { season, week, file }) => {
+            import_results(&db, &season, week, &file).await
+        }
+        Command::Recompute { season, week } => recompute(&db, &season, week).await,
+        Command::Export { data, season, format, output } => {
+            export(&db, data, &season, format, output).await
+        }
+    }
+}
+
+async fn migrate(db: &PgPool) -> CliResult {
+    sqlx::migrate!("src/migrations")
+        .run(db)
+        .await?;
+    println!("The database is up to date");
+
+    Ok(())
+}
+
+async fn find_user(db: &PgPool, email: &str) -> CliResult<User> {
+    query_as::<_, User>("SELECT * FROM users WHERE LOWER(email) = LOWER($1)")
+        .bind(email.trim())
+        .fetch_optional(db)
+        .await?
+        .ok_or_else(|| format!("nobody has registered as {}", email.trim()).into())
+}
+
+async fn find_gameweek(db: &PgPool, season: &str, week: i32) -> CliResult<Gameweek> {
+    query_as::<_, Gameweek>("SELECT * FROM gameweeks WHERE season = $1 AND week_number = $2")
+        .bind(season)
+        .bind(week)
+        .fetch_optional(db)
+        .await?
+        .ok_or_else(|| format!("there's no gameweek {} in {}", week, season).into())
+}
+
+fn read_password(password_stdin: bool) -> CliResult<(String, bool)> {
+    if !password_stdin {
+        return Ok((Alphanumeric.sample_string(&mut rand::thread_rng(), 16), true));
+    }
+
+    let mut password = String::new();
+    io::stdin().lock().read_line(&mut password)?;
+
+    Ok((password.trim_end_matches(['\r', '\n']).to_string(), false))
+}
+
+async fn create_user(
+    db: &PgPool,
+    config: &Config,
+    email: &str,
+    name: &str,
+    display_name: Option<&str>,
+    password_stdin: bool,
+    roles: &[Role],
+) -> CliResult {
+    let (password, generated) = read_password(password_stdin)?;
+    let input = CreateUser {
+        name: name.trim().to_string(),
+        display_name: display_name.unwrap_or(name).trim().to_string(),
+        email: email.trim().to_string(),
+        password,
+    };
+
+    // The same rules as the registration form
+    input.validate()?;
+
+    if query!("SELECT id FROM users WHERE LOWER(email) = LOWER($1)", input.email).fetch_optional(db).await?.is_some() {
+        return Err(format!("{} is already registered", input.email).into());
+    }
+
+    let mut tx = db.begin().await?;
+    let user = query_as::<_, User>(
+        r#"
+        INSERT INTO users (name, display_name, email, password_hash, email_verified_at)
+        VALUES ($1, $2, $3, $4, NOW())
+        RETURNING *
+        "#
+    )
+    .bind(&input.name)
+    .bind(&input.display_name)
+    .bind(&input.email)
+    .bind(hash_password(&input.password, config.bcrypt_cost)?)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    audit::Event::new(audit::USER_CREATED)
+        .by_actor(Actor::Cli)
+        .details(json!({ "user_id": user.id }))
+        .record(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    println!("Created {} ({})", user.email, user.id);
+
+    if generated {
+        println!("Password: {}", input.password);
+    }
+
+    for role in roles {
+        promote_user(db, &user.email, *role).await?;
+    }
+
+    Ok(())
+}
+
+async fn promote_user(db: &PgPool, email: &str, role: Role) -> CliResult {
+    let user = find_user(db, email).await?;
+
+    let role_names = |roles: Vec<Role>| roles.iter().map(Role::as_str).collect::<Vec<_>>();
+    let mut tx = db.begin().await?;
+    let before = roles::user_roles(&mut *tx, user.id).await?;
+
+    if !roles::grant_role(&mut tx, user.id, role, None).await? {
+        println!("{} is already a {}", user.email, role.label().to_lowercase());
+        return Ok(());
+    }
+
+    audit::Event::new(audit::ROLE_GRANTED)
+        .by_actor(Actor::Cli)
+        .details(json!({ "user_id": user.id, "role": role.as_str() }))
+        .change(role_names(before), role_names(roles::user_roles(&mut *tx, user.id).await?))
+        .record(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    println!("{} is now a {}", user.email, role.label().to_lowercase());
+
+    Ok(())
+}
+
+async fn insert_gameweek(db: &PgPool, input: &CreateGameweek) -> CliResult<Option<Gameweek>> {
+    input.validate().map_err(|_| "seasons must be 7 to 20 characters, e.g. 2025-26")?;
+
+    let mut tx = db.begin().await?;
+    let gameweek = query_as::<_, Gameweek>(
+        r#"
+        INSERT INTO gameweeks (week_number, season, deadline, is_active)
+        VALUES ($1, $2, $3, false)
+        ON CONFLICT DO NOTHING
+        RETURNING *
+        "#
+    )
+    .bind(input.week_number)
+    .bind(&input.season)
+    .bind(input.deadline)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    if let Some(gameweek) = &gameweek {
+        audit::Event::new(audit::GAMEWEEK_CREATED)
+            .by_actor(Actor::Cli)
+            .details(json!({ "gameweek_id": gameweek.id }))
+            .change(Value::Null, gameweek)
+            .record(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+
+    Ok(gameweek)
+}
+
+async fn create_season(
+    db: &PgPool,
+    season: &str,
+    first_deadline: DateTime<Utc>,
+    weeks: i32,
+    days_between: i64,
+) -> CliResult {
+    let mut created = 0;
+    for week_number in 1..=weeks {
+        let input = CreateGameweek {
+            week_number,
+            season: season.to_string(),
+            deadline: first_deadline + Duration::days(days_between * (week_number - 1) as i64),
+        };
+
+        if insert_gameweek(db, &input).await?.is_some() {
+            created += 1;
+        }
+    }
+
+    println!("Created {} of {} gameweeks for {}", created, weeks, season);
+    println!("Run `superior6-admin gameweeks activate {} 1` when it's time to open predictions", season);
+
+    Ok(())
+}
+
+async fn create_gameweek(
+    db: &PgPool,
+    season: &str,
+    week_number: i32,
+    deadline: DateTime<Utc>,
+    activate: bool,
+) -> CliResult {
+    let input = CreateGameweek { week_number, season: season.to_string(), deadline };
+    let gameweek = insert_gameweek(db, &input)
+        .await?
+        .ok_or_else(|| format!("gameweek {} already exists for {}", week_number, season))?;
+
+    println!("Created gameweek {} of {}", gameweek.week_number, gameweek.season);
+
+    if activate {
+        activate_gameweek(db, &gameweek).await?;
+    }
+
+    Ok(())
+}
+
+/// As on the admin pages, only one gameweek is active at a time.
+async fn activate_gameweek(db: &PgPool, gameweek: &Gameweek) -> CliResult {
+    let mut tx = db.begin().await?;
+
+    query!("UPDATE gameweeks SET is_active = false WHERE is_active = true AND id <> $1", gameweek.id)
+        .execute(&mut *tx)
+        .await?;
+    query!("UPDATE gameweeks SET is_active = true WHERE id = $1", gameweek.id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+    println!("Gameweek {} of {} is now active", gameweek.week_number, gameweek.season);
+
+    Ok(())
+}
+
+async fn import_fixtures(db: &PgPool, season: &str, week: i32, file: &PathBuf) -> CliResult {
+    let gameweek = find_gameweek(db, season, week).await?;
+    let fixtures = imports::read_fixtures(File::open(file)?)
+        .map_err(|error| format!("{}: {}", file.display(), error))?;
+
+    let created = gameweeks::replace_fixtures(db, gameweek.id, &fixtures, Actor::Cli).await?;
+    println!("Imported {} fixtures for gameweek {} of {}", created.len(), week, season);
+
+    Ok(())
+}
+
+/// Enters the results and scores the gameweek, the same as submitting them
+/// on the results page.
+async fn import_results(db: &PgPool, season: &str, week: i32, file: &PathBuf) -> CliResult {
+    let gameweek = find_gameweek(db, season, week).await?;
+    let fixtures = gameweek_fixtures(db, gameweek.id).await?;
+    if fixtures.is_empty() {
+        return Err(format!("gameweek {} of {} has no fixtures yet", week, season).into());
+    }
+
+    let lines = imports::read_results(File::open(file)?)
+        .map_err(|error| format!("{}: {}", file.display(), error))?;
+    let results = imports::match_results(&fixtures, &lines)
+        .map_err(|error| format!("{}: {}", file.display(), error))?;
+
+    gameweeks::enter_results(db, &gameweek, &results, Actor::Cli).await?;
+
+    println!("Entered {} results and scored gameweek {} of {}", results.len(), week, season);
+
+    Ok(())
+}
+
+async fn recompute(db: &PgPool, season: &str, week: Option<i32>) -> CliResult {
+    let weeks = match week {
+        Some(week) => vec![find_gameweek(db, season, week).await?],
+        None => query_as::<_, Gameweek>(
+            "SELECT * FROM gameweeks WHERE season = $1 AND is_completed = true ORDER BY week_number"
+        )
+        .bind(season)
+        .fetch_all(db)
+        .await?,
+    };
+
+    for gameweek in &weeks {
+        gameweeks::rescore(db, gameweek.id, "recompute", Actor::Cli).await?;
+        println!("Rescored gameweek {} of {}", gameweek.week_number, season);
+    }
+
+    award_season_achievements(db, season).await?;
+
+    Ok(())
+}
+
+fn to_json(rows: &impl Serialize) -> CliResult<String> {
+    Ok(serde_json::to_string_pretty(rows)? + "\n")
+}
+
+async fn export(
+    db: &PgPool,
+    data: ExportData,
+    season: &str,
+    format: ExportFormat,
+    output: Option<PathBuf>,
+) -> CliResult {
+    let json = format == ExportFormat::Json;
+
+    let contents = match data {
+        ExportData::Standings => {
+            let rows = exports::standings(db, season).await?;
+            if json { to_json(&rows)? } else { exports::standings_csv(season, &rows) }
+        }
+        ExportData::Predictions => {
+            let rows = exports::predictions(db, season).await?;
+            if json { to_json(&rows)? } else { exports::predictions_csv(season, &rows) }
+        }
+        ExportData::Ledger => {
+            let rows = ledger::ledger_rows(db, season).await?;
+            if json { to_json(&rows)? } else { ledger::ledger_csv(season, &rows) }
+        }
+    };
+
+    match output {
+        Some(path) => File::create(path)?.write_all(contents.as_bytes())?,
+        None => io::stdout().lock().write_all(contents.as_bytes())?,
+    }
+
+    Ok(())
+}
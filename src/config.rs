@@ -1,25 +1,525 @@
 // config.rs
+//
+// Settings come from, highest priority first: command-line flags, environment
+// variables (including `.env`), a TOML config file, then the defaults below.
+// Every setting is named by its environment variable; in the config file the
+// same name is written in lower case, e.g. `bcrypt_cost = 12`.
 
+use std::collections::HashMap;
 use std::env;
+use std::fmt::Display;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use axum_extra::extract::cookie::SameSite;
+use reqwest::Url;
+use crate::ledger::{parse_pounds, PotSplit};
+use crate::mailer::{MailTransport, SmtpTls};
+use crate::oidc::{oidc_from, OidcConfig};
+use crate::prizes::{MonthDefinition, TieSplit};
+use crate::telemetry::{parse_filter, LogFormat};
+
+/// Read when no file is named and `SUPERIOR6_CONFIG` isn't set, if it exists.
+pub const DEFAULT_CONFIG_FILE: &str = "superior6.toml";
+/// Only good enough for development; production refuses to start with it.
+pub const DEFAULT_JWT_SECRET: &str = "supersecretkey";
+/// Production secrets must be at least this long.
+const MIN_JWT_SECRET_LENGTH: usize = 32;
+
+/// Every setting there is, so typos in the config file are caught.
+const SETTINGS: [&str; 34] = [
+    "APP_ENV",
+    "BIND_ADDRESS",
+    "LOG_FORMAT",
+    "RUST_LOG",
+    "METRICS_TOKEN",
+    "DATABASE_URL",
+    "DATABASE_MAX_CONNECTIONS",
+    "JWT_SECRET",
+    "BCRYPT_COST",
+    "SESSION_LIFETIME_DAYS",
+    "COOKIE_SECURE",
+    "COOKIE_SAME_SITE",
+    "PRIZE_TIE_SPLIT",
+    "PRIZE_MONTHS",
+    "ENTRY_FEE",
+    "POT_WEEKLY_PERCENT",
+    "POT_MONTHLY_PERCENT",
+    "POT_SEASON_PERCENTS",
+    "APP_URL",
+    "MAIL_TRANSPORT",
+    "MAIL_FROM",
+    "MAIL_DIR",
+    "SMTP_HOST",
+    "SMTP_PORT",
+    "SMTP_TLS",
+    "SMTP_USERNAME",
+    "SMTP_PASSWORD",
+    "REQUIRE_ADMIN_2FA",
+    "CORS_ALLOWED_ORIGINS",
+    "INITIAL_ADMIN_EMAIL",
+    "OIDC_ISSUER_URL",
+    "OIDC_CLIENT_ID",
+    "OIDC_CLIENT_SECRET",
+    "OIDC_PROVIDER_NAME",
+];
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("couldn't read {path}: {source}")]
+    Read { path: PathBuf, source: std::io::Error },
+    #[error("{path} isn't valid TOML: {source}")]
+    Parse { path: PathBuf, source: toml::de::Error },
+    #[error("unknown setting '{0}' in the config file")]
+    UnknownSetting(String),
+    #[error("{setting} = '{value}': {reason}")]
+    Invalid { setting: String, value: String, reason: String },
+    #[error("refusing to start in production: {0}")]
+    Unsafe(String),
+}
+
+pub(crate) fn invalid(setting: &str, value: &str, reason: impl Display) -> ConfigError {
+    ConfigError::Invalid {
+        setting: setting.to_string(),
+        value: value.to_string(),
+        reason: reason.to_string(),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Environment {
+    Development,
+    Production,
+}
+
+impl FromStr for Environment {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "development" | "dev" => Ok(Environment::Development),
+            "production" | "prod" => Ok(Environment::Production),
+            other => Err(format!("unknown environment '{}'", other)),
+        }
+    }
+}
+
+/// Attributes for the cookies we set.
+#[derive(Debug, Clone, Copy)]
+pub struct CookieSettings {
+    /// Only sent over HTTPS. On by default in production.
+    pub secure: bool,
+    pub same_site: SameSite,
+}
+
+impl Default for CookieSettings {
+    fn default() -> Self {
+        Self { secure: false, same_site: SameSite::Lax }
+    }
+}
+
+/// `SameSite=None` isn't offered, since the CSRF protection counts on forms
+/// from other sites arriving without our cookies.
+fn parse_same_site(value: &str) -> Result<SameSite, String> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "lax" => Ok(SameSite::Lax),
+        "strict" => Ok(SameSite::Strict),
+        _ => Err("must be 'lax' or 'strict'".to_string()),
+    }
+}
+
+/// A comma-separated list of origins, each written `scheme://host[:port]`.
+/// A wildcard isn't allowed, since cross-origin requests carry cookies.
+fn parse_origins(value: &str) -> Result<Vec<String>, String> {
+    value
+        .split(',')
+        .map(|origin| origin.trim().trim_end_matches('/'))
+        .filter(|origin| !origin.is_empty())
+        .map(|origin| {
+            if origin == "*" {
+                return Err("a wildcard isn't allowed, since cross-origin requests carry cookies".to_string());
+            }
+            let url = Url::parse(origin)
+                .map_err(|_| format!("'{}' isn't an origin such as https://stats.example.com", origin))?;
+            let bare = matches!(url.scheme(), "http" | "https")
+                && url.username().is_empty()
+                && url.password().is_none()
+                && url.path() == "/"
+                && url.query().is_none()
+                && url.fragment().is_none();
+            if !bare || url.host().is_none() {
+                return Err(format!("'{}' must be just scheme://host[:port]", origin));
+            }
+
+            Ok(url.origin().ascii_serialization())
+        })
+        .collect()
+}
+
+fn parse_bool(value: &str) -> Result<bool, String> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "true" | "yes" | "on" | "1" => Ok(true),
+        "false" | "no" | "off" | "0" => Ok(false),
+        _ => Err("must be true or false".to_string()),
+    }
+}
+
+/// Where each setting is read from. See the top of this file for the order.
+#[derive(Debug, Default)]
+pub struct Sources {
+    flags: HashMap<&'static str, String>,
+    file: HashMap<String, String>,
+    /// Set by tests, so the shell running them can't change the outcome.
+    ignore_env: bool,
+}
+
+impl Sources {
+    /// Reads the config file: `path` if given, otherwise the one named by
+    /// `SUPERIOR6_CONFIG`, otherwise `superior6.toml` if there is one.
+    pub fn new(path: Option<&Path>) -> Result<Self, ConfigError> {
+        let path = match path {
+            Some(path) => Some(path.to_path_buf()),
+            None => match env::var("SUPERIOR6_CONFIG") {
+                Ok(path) if !path.trim().is_empty() => Some(PathBuf::from(path.trim())),
+                _ => Some(PathBuf::from(DEFAULT_CONFIG_FILE)).filter(|path| path.exists()),
+            },
+        };
+
+        let Some(path) = path else {
+            return Ok(Self::default());
+        };
+
+        let contents = std::fs::read_to_string(&path)
+            .map_err(|source| ConfigError::Read { path: path.clone(), source })?;
+
+        Self::from_toml(&contents).map_err(|error| match error {
+            ConfigError::Parse { source, .. } => ConfigError::Parse { path, source },
+            error => error,
+        })
+    }
+
+    fn from_toml(contents: &str) -> Result<Self, ConfigError> {
+        let table: toml::Table = contents
+            .parse()
+            .map_err(|source| ConfigError::Parse { path: PathBuf::new(), source })?;
+
+        let mut file = HashMap::new();
+        for (key, value) in table {
+            let setting = key.to_ascii_uppercase();
+            if !SETTINGS.contains(&setting.as_str()) {
+                return Err(ConfigError::UnknownSetting(key));
+            }
+
+            file.insert(setting.clone(), toml_value(&setting, value)?);
+        }
+
+        Ok(Self { file, ..Self::default() })
+    }
+
+    /// Overrides a setting from a command-line flag, when the flag was given.
+    pub fn flag(mut self, setting: &'static str, value: Option<impl ToString>) -> Self {
+        if let Some(value) = value {
+            self.flags.insert(setting, value.to_string());
+        }
+        self
+    }
+
+    /// The setting's value, if it's been set anywhere. Blank counts as unset.
+    pub fn get(&self, setting: &str) -> Option<String> {
+        let set = |value: &String| !value.trim().is_empty();
+
+        self.flags
+            .get(setting)
+            .cloned()
+            .filter(set)
+            .or_else(|| env::var(setting).ok().filter(|_| !self.ignore_env).filter(set))
+            .or_else(|| self.file.get(setting).cloned().filter(set))
+    }
+
+    pub fn parse<T>(&self, setting: &str, default: T) -> Result<T, ConfigError>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        self.parse_with(setting, default, |value| value.trim().parse::<T>().map_err(|error| error.to_string()))
+    }
+
+    fn parse_with<T>(
+        &self,
+        setting: &str,
+        default: T,
+        parse: impl Fn(&str) -> Result<T, String>,
+    ) -> Result<T, ConfigError> {
+        match self.get(setting) {
+            Some(value) => parse(&value).map_err(|reason| invalid(setting, &value, reason)),
+            None => Ok(default),
+        }
+    }
+
+    /// A whole number from `min` to `max`.
+    fn number<T>(&self, setting: &str, default: T, min: T, max: T) -> Result<T, ConfigError>
+    where
+        T: FromStr + PartialOrd + Display + Copy,
+        T::Err: Display,
+    {
+        let value = self.parse(setting, default)?;
+        if value < min || value > max {
+            return Err(invalid(setting, &value.to_string(), format!("must be between {} and {}", min, max)));
+        }
+
+        Ok(value)
+    }
+}
+
+/// Config file values as they'd be written in the environment. Lists become
+/// comma-separated.
+fn toml_value(setting: &str, value: toml::Value) -> Result<String, ConfigError> {
+    match value {
+        toml::Value::String(value) => Ok(value),
+        toml::Value::Integer(value) => Ok(value.to_string()),
+        toml::Value::Float(value) => Ok(value.to_string()),
+        toml::Value::Boolean(value) => Ok(value.to_string()),
+        toml::Value::Array(values) => Ok(values
+            .into_iter()
+            .map(|value| toml_value(setting, value))
+            .collect::<Result<Vec<_>, _>>()?
+            .join(",")),
+        other => Err(invalid(setting, &other.to_string(), "must be a string, number, boolean or list")),
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct Config {
+    pub environment: Environment,
+    pub bind_address: SocketAddr,
+    /// JSON by default in production.
+    pub log_format: LogFormat,
+    /// Which log lines to keep, as in `RUST_LOG`, e.g. `info,sqlx=warn`.
+    pub log_filter: String,
+    /// When set, scrapes of /metrics must send it as a bearer token.
+    pub metrics_token: Option<String>,
     pub database_url: String,
+    pub database_max_connections: u32,
     pub jwt_secret: String,
     pub bcrypt_cost: u32,
+    /// A session ends after this long without being used.
+    pub session_lifetime_days: i64,
+    pub cookies: CookieSettings,
+    pub prize_tie_split: TieSplit,
+    pub prize_months: MonthDefinition,
+    pub entry_fee_pence: i64,
+    pub pot_split: PotSplit,
+    /// Public base URL, used for links in emails.
+    pub app_url: String,
+    pub mail_transport: MailTransport,
+    pub mail_from: String,
+    pub mail_dir: String,
+    pub smtp_host: String,
+    pub smtp_port: u16,
+    /// STARTTLS by default in production, plain text otherwise.
+    pub smtp_tls: SmtpTls,
+    pub smtp_username: Option<String>,
+    pub smtp_password: Option<String>,
+    /// Admins can't use admin pages until they have set up two-factor auth.
+    pub require_admin_two_factor: bool,
+    /// Other origins allowed to call us from a browser. Empty means
+    /// same-origin only.
+    pub cors_allowed_origins: Vec<String>,
+    /// Made a super-admin at startup while the league has none, once the
+    /// account has confirmed its address, so a new install has someone who
+    /// can grant roles.
+    pub initial_admin_email: Option<String>,
+    /// OpenID Connect single sign-on, offered next to password login when set.
+    pub oidc: Option<OidcConfig>,
 }
 
 impl Config {
-    pub fn from_env() -> Result<Self, env::VarError> {
-        Ok(Self {
-            database_url: env::var("DATABASE_URL")
-                .unwrap_or_else(|_| "postgresql://localhost/superior6".to_string()),
-            jwt_secret: env::var("JWT_SECRET")
-                .unwrap_or_else(|_| "supersecretkey".to_string()),
-            bcrypt_cost: env::var("BCRYPT_COST")
-                .unwrap_or_else(|_| "12".to_string())
-                .parse()
-                .unwrap_or(12),
-        })
+    pub fn load(sources: &Sources) -> Result<Self, ConfigError> {
+        let environment = sources.parse("APP_ENV", Environment::Development)?;
+        let production = environment == Environment::Production;
+
+        let config = Self {
+            environment,
+            bind_address: sources.parse("BIND_ADDRESS", SocketAddr::from(([0, 0, 0, 0], 6666)))?,
+            log_format: sources.parse("LOG_FORMAT", if production { LogFormat::Json } else { LogFormat::Pretty })?,
+            log_filter: sources.parse_with("RUST_LOG", "info".to_string(), parse_filter)?,
+            metrics_token: sources.get("METRICS_TOKEN"),
+            database_url: sources
+                .get("DATABASE_URL")
+                .unwrap_or_else(|| "postgresql://localhost/superior6".to_string()),
+            database_max_connections: sources.number("DATABASE_MAX_CONNECTIONS", 10, 1, 1000)?,
+            jwt_secret: sources
+                .get("JWT_SECRET")
+                .unwrap_or_else(|| DEFAULT_JWT_SECRET.to_string()),
+            bcrypt_cost: sources.number("BCRYPT_COST", 12, 4, 31)?,
+            session_lifetime_days: sources.number("SESSION_LIFETIME_DAYS", 30, 1, 365)?,
+            cookies: CookieSettings {
+                secure: sources.parse_with("COOKIE_SECURE", production, parse_bool)?,
+                same_site: sources.parse_with("COOKIE_SAME_SITE", SameSite::Lax, parse_same_site)?,
+            },
+            prize_tie_split: sources.parse("PRIZE_TIE_SPLIT", TieSplit::Share)?,
+            prize_months: sources.parse("PRIZE_MONTHS", MonthDefinition::Calendar)?,
+            entry_fee_pence: sources.parse_with("ENTRY_FEE", 0, |fee| {
+                parse_pounds(fee).ok_or_else(|| "must be an amount in pounds, e.g. 10.00".to_string())
+            })?,
+            pot_split: pot_split_from(sources)?,
+            app_url: sources
+                .get("APP_URL")
+                .unwrap_or_else(|| "http://localhost:6666".to_string())
+                .trim_end_matches('/')
+                .to_string(),
+            mail_transport: sources.parse("MAIL_TRANSPORT", MailTransport::Stdout)?,
+            mail_from: sources
+                .get("MAIL_FROM")
+                .unwrap_or_else(|| "noreply@superior6.local".to_string()),
+            mail_dir: sources
+                .get("MAIL_DIR")
+                .unwrap_or_else(|| "mail".to_string()),
+            smtp_host: sources
+                .get("SMTP_HOST")
+                .unwrap_or_else(|| "localhost".to_string()),
+            smtp_port: sources.parse("SMTP_PORT", 1025)?,
+            smtp_tls: sources.parse("SMTP_TLS", if production { SmtpTls::StartTls } else { SmtpTls::None })?,
+            smtp_username: sources.get("SMTP_USERNAME"),
+            smtp_password: sources.get("SMTP_PASSWORD"),
+            require_admin_two_factor: sources.parse_with("REQUIRE_ADMIN_2FA", true, parse_bool)?,
+            cors_allowed_origins: sources.parse_with("CORS_ALLOWED_ORIGINS", Vec::new(), parse_origins)?,
+            initial_admin_email: sources.get("INITIAL_ADMIN_EMAIL"),
+            oidc: oidc_from(sources)?,
+        };
+
+        config.check_production()?;
+
+        Ok(config)
+    }
+
+    /// Whether the signing secret is the built-in one anyone could look up.
+    pub fn uses_default_secret(&self) -> bool {
+        self.jwt_secret == DEFAULT_JWT_SECRET
+    }
+
+    fn check_production(&self) -> Result<(), ConfigError> {
+        if self.environment != Environment::Production {
+            return Ok(());
+        }
+
+        if self.uses_default_secret() || self.jwt_secret.len() < MIN_JWT_SECRET_LENGTH {
+            return Err(ConfigError::Unsafe(format!(
+                "JWT_SECRET must be set to a random string of at least {} characters",
+                MIN_JWT_SECRET_LENGTH
+            )));
+        }
+
+        if self.metrics_token.is_none() {
+            return Err(ConfigError::Unsafe(
+                "METRICS_TOKEN must be set, or anyone who can reach the server can read /metrics".to_string(),
+            ));
+        }
+
+        if self.smtp_username.is_some() && self.smtp_tls == SmtpTls::None {
+            return Err(ConfigError::Unsafe(
+                "SMTP_USERNAME is set but SMTP_TLS is 'none', which would send the password in plain text".to_string(),
+            ));
+        }
+
+        Ok(())
     }
-}
\ No newline at end of file
+}
+
+/// The default split unless the pot settings are given, in which case all
+/// three must be.
+fn pot_split_from(sources: &Sources) -> Result<PotSplit, ConfigError> {
+    let settings = ["POT_WEEKLY_PERCENT", "POT_MONTHLY_PERCENT", "POT_SEASON_PERCENTS"];
+    if settings.iter().all(|setting| sources.get(setting).is_none()) {
+        return Ok(PotSplit::default());
+    }
+    if let Some(missing) = settings.iter().find(|setting| sources.get(setting).is_none()) {
+        return Err(invalid(missing, "", "must be set along with the other pot percentages"));
+    }
+
+    let season_percents = sources.parse_with("POT_SEASON_PERCENTS", Vec::new(), |value| {
+        value
+            .split(',')
+            .map(|percent| percent.trim().parse::<u32>().map_err(|_| "must be whole percentages, e.g. 35,15,10".to_string()))
+            .collect()
+    })?;
+
+    PotSplit::new(
+        sources.parse("POT_WEEKLY_PERCENT", 0)?,
+        sources.parse("POT_MONTHLY_PERCENT", 0)?,
+        season_percents,
+    )
+    .map_err(|reason| invalid("POT_SEASON_PERCENTS", &sources.get("POT_SEASON_PERCENTS").unwrap_or_default(), reason))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Only what the test sets, whatever the environment holds.
+    fn isolated(sources: Sources) -> Sources {
+        Sources { ignore_env: true, ..sources }
+    }
+
+    #[test]
+    fn test_config_file() {
+        let sources = isolated(Sources::from_toml(
+            r#"
+            bcrypt_cost = 11
+            cookie_secure = true
+            cors_allowed_origins = ["https://stats.example.com", "https://example.org/"]
+            pot_season_percents = [35, 15, 10]
+            "#,
+        )
+        .unwrap())
+        .flag("BIND_ADDRESS", Some("127.0.0.1:8080"));
+
+        assert_eq!(sources.get("POT_SEASON_PERCENTS").as_deref(), Some("35,15,10"));
+        assert_eq!(sources.number("BCRYPT_COST", 12, 4, 31).unwrap(), 11);
+        assert_eq!(sources.get("BIND_ADDRESS").as_deref(), Some("127.0.0.1:8080"));
+
+        assert!(matches!(Sources::from_toml("bcrypt_csot = 11"), Err(ConfigError::UnknownSetting(_))));
+        assert!(isolated(Sources::from_toml("bcrypt_cost = 99").unwrap()).number("BCRYPT_COST", 12, 4, 31).is_err());
+    }
+
+    #[test]
+    fn test_cors_origins() {
+        assert_eq!(
+            parse_origins(" https://Stats.example.com/, http://localhost:3000").unwrap(),
+            ["https://stats.example.com", "http://localhost:3000"]
+        );
+        assert!(parse_origins("*").is_err());
+        assert!(parse_origins("https://example.com/app").is_err());
+        assert!(parse_origins("stats.example.com").is_err());
+    }
+
+    #[test]
+    fn test_partial_oidc_settings() {
+        let sources = isolated(Sources::default()).flag("OIDC_ISSUER_URL", Some("https://id.example.com/"));
+        assert!(matches!(Config::load(&sources), Err(ConfigError::Invalid { setting, .. }) if setting == "OIDC_CLIENT_ID"));
+
+        let sources = sources.flag("OIDC_CLIENT_ID", Some("superior6"));
+        let oidc = Config::load(&sources).unwrap().oidc.unwrap();
+        assert_eq!(oidc.issuer_url, "https://id.example.com");
+    }
+
+    #[test]
+    fn test_production_needs_a_secret() {
+        let sources = isolated(Sources::default())
+            .flag("APP_ENV", Some("production"))
+            .flag("JWT_SECRET", Some(DEFAULT_JWT_SECRET));
+        assert!(matches!(Config::load(&sources), Err(ConfigError::Unsafe(_))));
+
+        let sources = sources.flag("JWT_SECRET", Some("x".repeat(MIN_JWT_SECRET_LENGTH)));
+        assert!(matches!(Config::load(&sources), Err(ConfigError::Unsafe(_))));
+
+        let sources = sources.flag("METRICS_TOKEN", Some("scraper"));
+        let config = Config::load(&sources).unwrap();
+        assert!(config.cookies.secure);
+        assert_eq!(config.log_format, LogFormat::Json);
+        assert_eq!(config.smtp_tls, SmtpTls::StartTls);
+
+        let sources = sources.flag("SMTP_USERNAME", Some("relay")).flag("SMTP_TLS", Some("none"));
+        assert!(matches!(Config::load(&sources), Err(ConfigError::Unsafe(_))));
+    }
+}
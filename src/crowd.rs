@@ -0,0 +1,246 @@
+// crowd.rs
+
+use std::collections::HashMap;
+use sqlx::{query_as, FromRow, PgPool};
+use uuid::Uuid;
+use crate::errors::AppError;
+use crate::models::Fixture;
+use crate::scoring::{calculate_points, MatchResult, POINTS_CORRECT_RESULT, POINTS_EXACT_SCORE};
+
+/// How many of the most popular scorelines are shown for each fixture.
+pub const POPULAR_SCORELINES: usize = 3;
+
+/// Number of players who predicted a given scoreline for a fixture.
+#[derive(Debug, Clone, FromRow)]
+pub struct ScorelineCount {
+    pub fixture_id: Uuid,
+    pub home: i32,
+    pub away: i32,
+    pub count: i64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PopularScoreline {
+    pub home: i32,
+    pub away: i32,
+    pub percent: i64,
+}
+
+/// What the players as a whole predicted for one fixture.
+#[derive(Debug, Clone)]
+pub struct CrowdStats {
+    pub fixture_id: Uuid,
+    pub predictions: i64,
+    pub home_win_percent: i64,
+    pub draw_percent: i64,
+    pub away_win_percent: i64,
+    pub popular_scorelines: Vec<PopularScoreline>,
+    pub average_home_goals: f64,
+    pub average_away_goals: f64,
+    /// The crowd's own prediction: the most popular scoreline within the
+    /// most popular result.
+    pub consensus_home: i32,
+    pub consensus_away: i32,
+    /// Points the consensus prediction earned, once the result is in.
+    pub points: Option<i32>,
+}
+
+impl CrowdStats {
+    pub fn average_goals(&self) -> f64 {
+        self.average_home_goals + self.average_away_goals
+    }
+}
+
+/// The crowd scored over a gameweek as if it were a player.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CrowdScore {
+    pub total_points: i32,
+    pub exact_scores: i32,
+    pub correct_results: i32,
+}
+
+fn percent(count: i64, total: i64) -> i64 {
+    (count * 100 + total / 2) / total
+}
+
+/// Builds the stats for one fixture from its scoreline counts. Returns `None`
+/// when nobody predicted the fixture.
+pub fn compute_crowd_stats(fixture: &Fixture, counts: &[ScorelineCount]) -> Option<CrowdStats> {
+    let total: i64 = counts.iter().map(|c| c.count).sum();
+    if total == 0 {
+        return None;
+    }
+
+    let result_count = |result: MatchResult| -> i64 {
+        counts
+            .iter()
+            .filter(|c| MatchResult::from_scores(c.home, c.away) == result)
+            .map(|c| c.count)
+            .sum()
+    };
+    let home_wins = result_count(MatchResult::HomeWin);
+    let draws = result_count(MatchResult::Draw);
+    let away_wins = result_count(MatchResult::AwayWin);
+
+    // Most votes first; level scorelines are ordered by score so the
+    // consensus is deterministic.
+    let mut ranked: Vec<&ScorelineCount> = counts.iter().collect();
+    ranked.sort_by(|a, b| {
+        b.count
+            .cmp(&a.count)
+            .then((a.home + a.away).cmp(&(b.home + b.away)))
+            .then(a.home.cmp(&b.home))
+    });
+
+    // On a level split between results, home win beats draw beats away win.
+    let consensus_result = if home_wins >= draws && home_wins >= away_wins {
+        MatchResult::HomeWin
+    } else if draws >= away_wins {
+        MatchResult::Draw
+    } else {
+        MatchResult::AwayWin
+    };
+    let consensus = ranked
+        .iter()
+        .find(|c| MatchResult::from_scores(c.home, c.away) == consensus_result)?;
+
+    let points = match (fixture.home_score, fixture.away_score) {
+        (Some(home), Some(away)) => Some(calculate_points(home, away, consensus.home, consensus.away)),
+        _ => None,
+    };
+
+    let goals = |side: fn(&ScorelineCount) -> i32| -> f64 {
+        counts.iter().map(|c| side(c) as i64 * c.count).sum::<i64>() as f64 / total as f64
+    };
+
+    Some(CrowdStats {
+        fixture_id: fixture.id,
+        predictions: total,
+        home_win_percent: percent(home_wins, total),
+        draw_percent: percent(draws, total),
+        away_win_percent: percent(away_wins, total),
+        popular_scorelines: ranked
+            .iter()
+            .take(POPULAR_SCORELINES)
+            .map(|c| PopularScoreline {
+                home: c.home,
+                away: c.away,
+                percent: percent(c.count, total),
+            })
+            .collect(),
+        average_home_goals: goals(|c| c.home),
+        average_away_goals: goals(|c| c.away),
+        consensus_home: consensus.home,
+        consensus_away: consensus.away,
+        points,
+    })
+}
+
+/// Totals the crowd's points across the fixtures that have a result.
+/// Returns `None` until at least one result is in.
+pub fn crowd_score(stats: &[CrowdStats]) -> Option<CrowdScore> {
+    let scored: Vec<i32> = stats.iter().filter_map(|s| s.points).collect();
+    if scored.is_empty() {
+        return None;
+    }
+
+    Some(CrowdScore {
+        total_points: scored.iter().sum(),
+        exact_scores: scored.iter().filter(|p| **p == POINTS_EXACT_SCORE).count() as i32,
+        correct_results: scored.iter().filter(|p| **p == POINTS_CORRECT_RESULT).count() as i32,
+    })
+}
+
+/// Crowd stats for every predicted fixture in a gameweek, in fixture order.
+/// Only players who count for prizes are included: not admins, unconfirmed
+/// or suspended accounts, nor deleted ones.
+pub async fn gameweek_crowd_stats(db: &PgPool, gameweek_id: Uuid) -> Result<Vec<CrowdStats>, AppError> {
+    let fixtures = query_as::<_, Fixture>(
+        "SELECT * FROM fixtures WHERE gameweek_id = $1 ORDER BY fixture_order"
+    )
+        .bind(gameweek_id)
+        .fetch_all(db)
+        .await?;
+
+    let counts = query_as::<_, ScorelineCount>(
+        r#"
+        SELECT p.fixture_id, p.home_score_prediction AS home, p.away_score_prediction AS away, COUNT(*) AS count
+        FROM predictions p
+        JOIN fixtures f ON p.fixture_id = f.id
+        JOIN users u ON p.user_id = u.id
+        WHERE f.gameweek_id = $1 AND u.is_admin = false AND u.email_verified_at IS NOT NULL
+          AND u.suspended_at IS NULL AND u.deleted_at IS NULL
+        GROUP BY p.fixture_id, p.home_score_prediction, p.away_score_prediction
+        "#
+    )
+        .bind(gameweek_id)
+        .fetch_all(db)
+        .await?;
+
+    let mut by_fixture: HashMap<Uuid, Vec<ScorelineCount>> = HashMap::new();
+    for count in counts {
+        by_fixture.entry(count.fixture_id).or_default().push(count);
+    }
+
+    Ok(fixtures
+        .iter()
+        .filter_map(|fixture| {
+            by_fixture
+                .get(&fixture.id)
+                .and_then(|counts| compute_crowd_stats(fixture, counts))
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use super::*;
+
+    fn fixture(result: Option<(i32, i32)>) -> Fixture {
+        Fixture {
+            id: Uuid::new_v4(),
+            gameweek_id: Uuid::new_v4(),
+            home_team: "Home".to_string(),
+            away_team: "Away".to_string(),
+            kickoff_time: Utc::now(),
+            home_score: result.map(|r| r.0),
+            away_score: result.map(|r| r.1),
+            fixture_order: 1,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    fn count(fixture: &Fixture, home: i32, away: i32, count: i64) -> ScorelineCount {
+        ScorelineCount { fixture_id: fixture.id, home, away, count }
+    }
+
+    #[test]
+    fn test_crowd_percentages_and_averages() {
+        let f = fixture(None);
+        let counts = vec![count(&f, 2, 1, 2), count(&f, 1, 0, 1), count(&f, 1, 1, 1)];
+        let stats = compute_crowd_stats(&f, &counts).unwrap();
+
+        assert_eq!(stats.predictions, 4);
+        assert_eq!((stats.home_win_percent, stats.draw_percent, stats.away_win_percent), (75, 25, 0));
+        assert_eq!(stats.popular_scorelines[0], PopularScoreline { home: 2, away: 1, percent: 50 });
+        assert_eq!(stats.average_goals(), 2.25);
+        assert_eq!(stats.points, None);
+    }
+
+    #[test]
+    fn test_consensus_follows_most_popular_result() {
+        // 1-1 is the single most popular scoreline, but more players backed an away win
+        let f = fixture(Some((0, 2)));
+        let counts = vec![count(&f, 1, 1, 3), count(&f, 0, 1, 2), count(&f, 0, 2, 2)];
+        let stats = compute_crowd_stats(&f, &counts).unwrap();
+
+        assert_eq!((stats.consensus_home, stats.consensus_away), (0, 1));
+        assert_eq!(stats.points, Some(2));
+        assert_eq!(
+            crowd_score(&[stats]),
+            Some(CrowdScore { total_points: 2, exact_scores: 0, correct_results: 1 })
+        );
+    }
+}
@@ -0,0 +1,182 @@
+// csrf.rs
+//
+// Cross-site request forgery protection for cookie-authenticated forms,
+// using the double-submit pattern: each browser gets a random token in a
+// cookie, and every form posts the same token back in a hidden field.
+// Another site can make the browser send the cookie, but can't read it to
+// put it in the form.
+
+use axum::body::{to_bytes, Body};
+use axum::extract::{Request, State};
+use axum::http::{header, Method};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum_extra::extract::cookie::Cookie;
+use axum_extra::extract::CookieJar;
+use crate::api_tokens::{hash_token, random_secret};
+use crate::config::CookieSettings;
+use crate::errors::AppError;
+
+pub const CSRF_COOKIE: &str = "csrf_token";
+/// The hidden form field carrying the token.
+pub const CSRF_FIELD: &str = "csrf_token";
+/// Header alternative to the form field, for scripts posting forms.
+pub const CSRF_HEADER: &str = "x-csrf-token";
+
+/// Forms are small; anything bigger is refused rather than buffered.
+const MAX_FORM_BYTES: usize = 2 * 1024 * 1024;
+
+tokio::task_local! {
+    static FORM_TOKEN: String;
+}
+
+/// The token to put in forms rendered for the current request. Templates
+/// call this from their hidden `csrf_token` field.
+pub fn form_token() -> String {
+    FORM_TOKEN.try_with(|token| token.clone()).unwrap_or_default()
+}
+
+fn csrf_cookie(token: String, cookies: &CookieSettings) -> Cookie<'static> {
+    Cookie::build((CSRF_COOKIE, token))
+        .path("/")
+        .http_only(true)
+        .secure(cookies.secure)
+        .same_site(cookies.same_site)
+        .build()
+}
+
+/// Requests that can't be forged from another site don't need a token:
+/// browsers never attach an `Authorization` header on their own, and a JSON
+/// body can't be sent cross-origin without a CORS preflight.
+fn needs_token(request: &Request) -> bool {
+    if matches!(*request.method(), Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE) {
+        return false;
+    }
+
+    let headers = request.headers();
+    if headers.contains_key(header::AUTHORIZATION) {
+        return false;
+    }
+
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("");
+
+    !content_type.starts_with("application/json")
+}
+
+/// Finds the token in a url-encoded form body.
+pub fn token_from_form(body: &[u8]) -> Option<String> {
+    serde_urlencoded::from_bytes::<Vec<(String, String)>>(body)
+        .ok()?
+        .into_iter()
+        .find(|(name, _)| name == CSRF_FIELD)
+        .map(|(_, value)| value)
+}
+
+/// Compares hashes rather than the tokens themselves, so the time taken
+/// says nothing about how much of a guess was right.
+fn tokens_match(expected: &str, submitted: &str) -> bool {
+    !expected.is_empty() && hash_token(expected) == hash_token(submitted)
+}
+
+/// Middleware that checks the token on every state-changing request and
+/// makes sure every browser has one to put in its forms.
+pub async fn protect(
+    State(cookies): State<CookieSettings>,
+    jar: CookieJar,
+    request: Request,
+    next: Next,
+) -> Response {
+    let existing = jar
+        .get(CSRF_COOKIE)
+        .map(|cookie| cookie.value().to_string())
+        .filter(|token| !token.is_empty());
+
+    let request = if needs_token(&request) {
+        let (parts, body) = request.into_parts();
+
+        let header_token = parts
+            .headers
+            .get(CSRF_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+
+        let Ok(bytes) = to_bytes(body, MAX_FORM_BYTES).await else {
+            return AppError::CsrfFailed.into_response();
+        };
+
+        let submitted = header_token.or_else(|| token_from_form(&bytes));
+        let valid = match (&existing, &submitted) {
+            (Some(expected), Some(submitted)) => tokens_match(expected, submitted),
+            _ => false,
+        };
+
+        if !valid {
+            return AppError::CsrfFailed.into_response();
+        }
+
+        Request::from_parts(parts, Body::from(bytes))
+    } else {
+        request
+    };
+
+    let token = existing.clone().unwrap_or_else(random_secret);
+    let response = FORM_TOKEN.scope(token.clone(), next.run(request)).await;
+
+    match existing {
+        Some(_) => response,
+        None => (CookieJar::new().add(csrf_cookie(token, &cookies)), response).into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::StatusCode;
+    use axum::routing::post;
+    use axum::{middleware, Router};
+    use tower::Service;
+
+    async fn send(request: Request) -> Response {
+        let mut app = Router::new()
+            .route("/echo", post(|body: String| async move { body }))
+            .layer(middleware::from_fn_with_state(CookieSettings::default(), protect));
+
+        app.call(request).await.unwrap()
+    }
+
+    fn form_post(cookie: Option<&str>, body: &str) -> Request {
+        let mut request = Request::post("/echo")
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded");
+        if let Some(cookie) = cookie {
+            request = request.header(header::COOKIE, format!("{}={}", CSRF_COOKIE, cookie));
+        }
+        request.body(Body::from(body.to_string())).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_form_posts_need_matching_token() {
+        let missing = send(form_post(Some("abc"), "name=x")).await;
+        assert_eq!(missing.status(), StatusCode::FORBIDDEN);
+
+        let no_cookie = send(form_post(None, "csrf_token=abc")).await;
+        assert_eq!(no_cookie.status(), StatusCode::FORBIDDEN);
+
+        let wrong = send(form_post(Some("abc"), "csrf_token=abd")).await;
+        assert_eq!(wrong.status(), StatusCode::FORBIDDEN);
+
+        // The handler still sees the whole form
+        let ok = send(form_post(Some("abc"), "name=x&csrf_token=abc")).await;
+        assert_eq!(ok.status(), StatusCode::OK);
+        let body = to_bytes(ok.into_body(), 1024).await.unwrap();
+        assert_eq!(&body[..], b"name=x&csrf_token=abc");
+
+        let bearer = Request::post("/echo")
+            .header(header::AUTHORIZATION, "Bearer s6_token")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(send(bearer).await.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,137 @@
+// email_verification.rs
+
+use chrono::{Duration, Utc};
+use sqlx::{query, query_as, Connection, PgConnection, PgExecutor, PgPool};
+use crate::AppState;
+use crate::api_tokens::{hash_token, random_secret};
+use crate::errors::AppError;
+use crate::mailer::Email;
+use crate::models::User;
+use crate::settings::{self, REQUIRE_EMAIL_VERIFICATION};
+
+/// How long a confirmation link works for.
+pub const VERIFICATION_LIFETIME_HOURS: i64 = 48;
+
+pub async fn verification_required(db: impl PgExecutor<'_>) -> Result<bool, AppError> {
+    settings::flag(db, REQUIRE_EMAIL_VERIFICATION).await
+}
+
+/// Turning verification off confirms everyone still pending, so nobody is
+/// left stuck off the leaderboards with no way to confirm. Returns how many
+/// accounts that confirmed.
+pub async fn set_verification_required(db: &mut PgConnection, required: bool) -> Result<u64, AppError> {
+    let mut tx = db.begin().await?;
+
+    settings::set_flag(&mut *tx, REQUIRE_EMAIL_VERIFICATION, required).await?;
+
+    let confirmed = if required {
+        0
+    } else {
+        query!("UPDATE users SET email_verified_at = NOW() WHERE email_verified_at IS NULL")
+            .execute(&mut *tx)
+            .await?
+            .rows_affected()
+    };
+
+    tx.commit().await?;
+
+    Ok(confirmed)
+}
+
+pub async fn pending_count(db: &PgPool) -> Result<i64, AppError> {
+    let count = query!("SELECT COUNT(*) AS count FROM users WHERE email_verified_at IS NULL")
+        .fetch_one(db)
+        .await?
+        .count
+        .unwrap_or(0);
+
+    Ok(count)
+}
+
+/// Emails the user a confirmation link, replacing any earlier one.
+pub async fn send_verification(state: &AppState, user: &User) -> Result<(), AppError> {
+    let token = random_secret();
+
+    let mut tx = state.db.begin().await?;
+
+    query!(
+        "UPDATE email_verifications SET used_at = NOW() WHERE user_id = $1 AND used_at IS NULL",
+        user.id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    query!(
+        "INSERT INTO email_verifications (user_id, token_hash, expires_at) VALUES ($1, $2, $3)",
+        user.id,
+        hash_token(&token),
+        Utc::now() + Duration::hours(VERIFICATION_LIFETIME_HOURS)
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    let link = format!("{}/verify-email?token={}", state.config.app_url, token);
+    let email = Email {
+        to: user.email.clone(),
+        subject: "Confirm your Superior 6 email address".to_string(),
+        body: format!(
+            "Hi {},\n\n\
+             Thanks for joining Superior 6. Open this link within {} hours to confirm\n\
+             your email address and start predicting:\n\n\
+             {}\n\n\
+             If you didn't sign up, you can ignore this email.\n",
+            user.display_name, VERIFICATION_LIFETIME_HOURS, link
+        ),
+    };
+
+    state.mailer.send(&email).await?;
+
+    Ok(())
+}
+
+/// Sends a fresh link if `email` belongs to an unconfirmed account. Says
+/// nothing either way, like the password reset form.
+pub async fn resend_verification(state: &AppState, email: &str) -> Result<(), AppError> {
+    let user = query_as::<_, User>(
+        "SELECT * FROM users WHERE email = $1 AND email_verified_at IS NULL"
+    )
+    .bind(email)
+    .fetch_optional(&state.db)
+    .await?;
+
+    match user {
+        Some(user) => send_verification(state, &user).await,
+        None => Ok(()),
+    }
+}
+
+/// Confirms the email address behind a link. Each link works once.
+pub async fn verify_email(db: &PgPool, token: &str) -> Result<User, AppError> {
+    let mut tx = db.begin().await?;
+
+    let user_id = query!(
+        r#"
+        UPDATE email_verifications SET used_at = NOW()
+        WHERE token_hash = $1 AND used_at IS NULL AND expires_at > NOW()
+        RETURNING user_id
+        "#,
+        hash_token(token)
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(AppError::InvalidVerificationToken)?
+    .user_id;
+
+    let user = query_as::<_, User>(
+        "UPDATE users SET email_verified_at = COALESCE(email_verified_at, NOW()) WHERE id = $1 RETURNING *"
+    )
+    .bind(user_id)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(user)
+}
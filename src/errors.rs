@@ -1,10 +1,14 @@
 // errors.rs
 
-use axum::http::StatusCode;
+use std::collections::BTreeMap;
+use axum::extract::rejection::JsonRejection;
+use axum::http::{header, HeaderValue, StatusCode};
 use axum::Json;
 use axum::response::{IntoResponse, Response};
-use serde_json::json;
+use serde::Serialize;
+use utoipa::ToSchema;
 use thiserror::Error;
+use crate::mailer::MailError;
 
 #[derive(Error, Debug)]
 pub enum AppError {
@@ -38,6 +42,24 @@ pub enum AppError {
     #[error("Forbidden")]
     Forbidden,
 
+    #[error("Missing or invalid CSRF token")]
+    CsrfFailed,
+
+    #[error("Account suspended")]
+    AccountSuspended,
+
+    #[error("Cannot remove the last super-admin")]
+    LastSuperAdmin,
+
+    #[error("Staff must give up their roles first")]
+    HoldsRoles,
+
+    #[error("Not allowed while viewing the site as another user")]
+    Impersonating,
+
+    #[error("Token scope does not allow this")]
+    InsufficientScope,
+
     #[error("Not found")]
     NotFound,
 
@@ -50,6 +72,36 @@ pub enum AppError {
     #[error("Invalid prediction data")]
     InvalidPrediction,
 
+    #[error("Invalid ledger entry")]
+    InvalidLedgerEntry,
+
+    #[error("Invalid results")]
+    InvalidResults,
+
+    #[error("Invalid or expired password reset token")]
+    InvalidResetToken,
+
+    #[error("Invalid or expired email verification token")]
+    InvalidVerificationToken,
+
+    #[error("Invalid two-factor code")]
+    InvalidTwoFactorCode,
+
+    #[error("Two-factor authentication required")]
+    TwoFactorRequired,
+
+    #[error("Too many attempts; retry in {0} seconds")]
+    RateLimited(i64),
+
+    #[error("Single sign-on failed: {0}")]
+    SingleSignOn(String),
+
+    #[error("Mail delivery failed: {0}")]
+    MailDelivery(#[from] MailError),
+
+    #[error("Invalid request body: {0}")]
+    InvalidRequestBody(String),
+
     #[error("Template error: {0}")]
     TemplateError(#[from] askama::Error),
 
@@ -57,32 +109,124 @@ pub enum AppError {
     Internal,
 }
 
+impl AppError {
+    /// HTTP status, stable machine-readable code and human-readable message.
+    /// Codes are part of the API contract; messages may change.
+    fn parts(&self) -> (StatusCode, &'static str, &'static str) {
+        match self {
+            AppError::DatabaseError(_) => (StatusCode::INTERNAL_SERVER_ERROR, "database_error", "Database error"),
+            AppError::Validation(_) => (StatusCode::BAD_REQUEST, "validation_failed", "Validation error"),
+            AppError::HashingError => (StatusCode::INTERNAL_SERVER_ERROR, "hashing_failed", "Hashing error"),
+            AppError::TokenCreation => (StatusCode::INTERNAL_SERVER_ERROR, "token_creation_failed", "Token creation failed"),
+            AppError::InvalidToken => (StatusCode::UNAUTHORIZED, "invalid_token", "Invalid token"),
+            AppError::MissingToken => (StatusCode::UNAUTHORIZED, "missing_token", "Missing token"),
+            AppError::UserNotFound => (StatusCode::NOT_FOUND, "user_not_found", "User not found"),
+            AppError::InvalidCredentials => (StatusCode::UNAUTHORIZED, "invalid_credentials", "Invalid credentials"),
+            AppError::EmailExists => (StatusCode::CONFLICT, "email_exists", "Email already exists"),
+            AppError::Forbidden => (StatusCode::FORBIDDEN, "forbidden", "Forbidden"),
+            AppError::CsrfFailed => (StatusCode::FORBIDDEN, "csrf_failed", "This form has expired. Go back, refresh the page and try again"),
+            AppError::AccountSuspended => (StatusCode::FORBIDDEN, "account_suspended", "This account has been suspended"),
+            AppError::LastSuperAdmin => (StatusCode::CONFLICT, "last_super_admin", "There must always be at least one super-admin"),
+            AppError::HoldsRoles => (StatusCode::CONFLICT, "holds_roles", "Ask a super-admin to remove your roles first"),
+            AppError::Impersonating => (StatusCode::FORBIDDEN, "impersonating", "You're viewing the site as another player. Exit from the banner to do this"),
+            AppError::InsufficientScope => (StatusCode::FORBIDDEN, "insufficient_scope", "This API token's scope does not allow this"),
+            AppError::NotFound => (StatusCode::NOT_FOUND, "not_found", "Not found"),
+            AppError::DeadlinePassed => (StatusCode::BAD_REQUEST, "deadline_passed", "Prediction deadline has passed"),
+            AppError::PredictionsAlreadySubmitted => (StatusCode::BAD_REQUEST, "predictions_already_submitted", "Predictions already submitted for this gameweek"),
+            AppError::InvalidPrediction => (StatusCode::BAD_REQUEST, "invalid_prediction", "Invalid prediction data"),
+            AppError::InvalidLedgerEntry => (StatusCode::BAD_REQUEST, "invalid_ledger_entry", "Invalid ledger entry"),
+            AppError::InvalidResults => (StatusCode::BAD_REQUEST, "invalid_results", "Results must be for the gameweek's own fixtures"),
+            AppError::InvalidResetToken => (StatusCode::BAD_REQUEST, "invalid_reset_token", "This reset link is invalid or has expired"),
+            AppError::InvalidVerificationToken => (StatusCode::BAD_REQUEST, "invalid_verification_token", "This confirmation link is invalid or has expired"),
+            AppError::InvalidTwoFactorCode => (StatusCode::UNAUTHORIZED, "invalid_two_factor_code", "Invalid two-factor code"),
+            AppError::TwoFactorRequired => (StatusCode::FORBIDDEN, "two_factor_required", "Enable two-factor authentication to use admin pages"),
+            AppError::RateLimited(_) => (StatusCode::TOO_MANY_REQUESTS, "rate_limited", "Too many attempts, please try again later"),
+            AppError::SingleSignOn(_) => (StatusCode::BAD_GATEWAY, "single_sign_on_failed", "Single sign-on failed"),
+            AppError::MailDelivery(_) => (StatusCode::INTERNAL_SERVER_ERROR, "mail_delivery_failed", "Email could not be sent"),
+            AppError::InvalidRequestBody(_) => (StatusCode::BAD_REQUEST, "invalid_request_body", "Invalid request body"),
+            AppError::TemplateError(_) => (StatusCode::INTERNAL_SERVER_ERROR, "template_error", "Template error"),
+            AppError::Internal => (StatusCode::INTERNAL_SERVER_ERROR, "internal_error", "Internal server error"),
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        self.parts().1
+    }
+}
+
+/// The JSON body of every error response:
+///
+/// ```json
+/// {
+///   "error": {
+///     "code": "validation_failed",
+///     "message": "Validation error",
+///     "fields": { "home_score_prediction": ["range"] }
+///   }
+/// }
+/// ```
+///
+/// `code` is stable and safe for clients to match on. `fields` is only
+/// present for validation failures and maps each field to the validators
+/// it failed.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ErrorBody {
+    pub error: ErrorDetail,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ErrorDetail {
+    #[schema(example = "deadline_passed")]
+    pub code: &'static str,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fields: Option<BTreeMap<String, Vec<String>>>,
+}
+
+impl From<JsonRejection> for AppError {
+    fn from(rejection: JsonRejection) -> Self {
+        AppError::InvalidRequestBody(rejection.body_text())
+    }
+}
+
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
-        let (status, error_message) = match self {
-            AppError::DatabaseError(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Database error"),
-            AppError::Validation(_) => (StatusCode::BAD_REQUEST, "Validation error"),
-            AppError::HashingError => (StatusCode::INTERNAL_SERVER_ERROR, "Hashing error"),
-            AppError::TokenCreation => (StatusCode::INTERNAL_SERVER_ERROR, "Token creation failed"),
-            AppError::InvalidToken => (StatusCode::UNAUTHORIZED, "Invalid token"),
-            AppError::MissingToken => (StatusCode::UNAUTHORIZED, "Missing token"),
-            AppError::UserNotFound => (StatusCode::NOT_FOUND, "User not found"),
-            AppError::InvalidCredentials => (StatusCode::UNAUTHORIZED, "Invalid credentials"),
-            AppError::EmailExists => (StatusCode::CONFLICT, "Email already exists"),
-            AppError::Forbidden => (StatusCode::FORBIDDEN, "Forbidden"),
-            AppError::NotFound => (StatusCode::NOT_FOUND, "Not found"),
-            AppError::DeadlinePassed => (StatusCode::BAD_REQUEST, "Prediction deadline has passed"),
-            AppError::PredictionsAlreadySubmitted => (StatusCode::BAD_REQUEST, "Predictions already submitted for this gameweek"),
-            AppError::InvalidPrediction => (StatusCode::BAD_REQUEST, "Invalid prediction data"),
-            AppError::TemplateError(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Template error"),
-            AppError::Internal => (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error"),
+        let (status, code, message) = self.parts();
+
+        // Clients only see the generic message, so keep the cause (the
+        // failed query, for a database error) in the request's log
+        if status.is_server_error() {
+            tracing::error!(code, error = %self, "request failed");
+        }
+
+        let message = match &self {
+            AppError::InvalidRequestBody(reason) => format!("{}: {}", message, reason),
+            _ => message.to_string(),
+        };
+
+        let fields = match &self {
+            AppError::Validation(errors) => Some(
+                errors
+                    .field_errors()
+                    .into_iter()
+                    .map(|(field, errors)| {
+                        (field.to_string(), errors.iter().map(|e| e.code.to_string()).collect())
+                    })
+                    .collect(),
+            ),
+            _ => None,
         };
 
-        let body = Json(json!({
-            "error": error_message,
-            "details": self.to_string(),
-        }));
+        let body = Json(ErrorBody {
+            error: ErrorDetail { code, message, fields },
+        });
+
+        let mut response = (status, body).into_response();
 
-        (status, body).into_response()
+        if let AppError::RateLimited(secs) = self {
+            response.headers_mut().insert(header::RETRY_AFTER, HeaderValue::from(secs));
+        }
+
+        response
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,138 @@
+// exports.rs
+//
+// A season's table and predictions as CSV, for `superior6-admin export`.
+// The JSON exports serialise the same rows.
+
+use serde::Serialize;
+use sqlx::{query_as, FromRow, PgPool};
+use crate::errors::AppError;
+use crate::ledger::csv_field;
+use crate::queries::season_leaderboard;
+
+#[derive(Debug, Serialize)]
+pub struct StandingRow {
+    pub position: i32,
+    pub display_name: String,
+    pub points: i32,
+    pub exact_scores: i32,
+    pub correct_results: i32,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct PredictionRow {
+    pub week_number: i32,
+    pub display_name: String,
+    pub home_team: String,
+    pub away_team: String,
+    pub home_score_prediction: i32,
+    pub away_score_prediction: i32,
+    /// `None` until the result is in.
+    pub home_score: Option<i32>,
+    pub away_score: Option<i32>,
+    pub points_awarded: i32,
+}
+
+pub async fn standings(db: &PgPool, season: &str) -> Result<Vec<StandingRow>, AppError> {
+    Ok(season_leaderboard(db, season)
+        .await?
+        .into_iter()
+        .map(|entry| StandingRow {
+            position: entry.position,
+            display_name: entry.user.display_name,
+            points: entry.score,
+            exact_scores: entry.exact_scores,
+            correct_results: entry.correct_results,
+        })
+        .collect())
+}
+
+pub async fn predictions(db: &PgPool, season: &str) -> Result<Vec<PredictionRow>, AppError> {
+    let rows = query_as::<_, PredictionRow>(
+        r#"
+        SELECT gw.week_number, u.display_name, f.home_team, f.away_team,
+               p.home_score_prediction, p.away_score_prediction,
+               f.home_score, f.away_score, p.points_awarded
+        FROM predictions p
+        JOIN fixtures f ON p.fixture_id = f.id
+        JOIN gameweeks gw ON f.gameweek_id = gw.id
+        JOIN users u ON p.user_id = u.id
+        WHERE gw.season = $1
+        ORDER BY gw.week_number, f.fixture_order, u.display_name
+        "#
+    )
+    .bind(season)
+    .fetch_all(db)
+    .await?;
+
+    Ok(rows)
+}
+
+fn csv(header: &str, lines: impl Iterator<Item = Vec<String>>) -> String {
+    let mut csv = format!("{}\n", header);
+    for fields in lines {
+        let line: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+        csv.push_str(&line.join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+fn score(score: Option<i32>) -> String {
+    score.map(|s| s.to_string()).unwrap_or_default()
+}
+
+pub fn standings_csv(season: &str, rows: &[StandingRow]) -> String {
+    csv(
+        "season,position,player,points,exact_scores,correct_results",
+        rows.iter().map(|row| vec![
+            season.to_string(),
+            row.position.to_string(),
+            row.display_name.clone(),
+            row.points.to_string(),
+            row.exact_scores.to_string(),
+            row.correct_results.to_string(),
+        ]),
+    )
+}
+
+pub fn predictions_csv(season: &str, rows: &[PredictionRow]) -> String {
+    csv(
+        "season,gameweek,player,home_team,away_team,predicted_home,predicted_away,home_score,away_score,points",
+        rows.iter().map(|row| vec![
+            season.to_string(),
+            row.week_number.to_string(),
+            row.display_name.clone(),
+            row.home_team.clone(),
+            row.away_team.clone(),
+            row.home_score_prediction.to_string(),
+            row.away_score_prediction.to_string(),
+            score(row.home_score),
+            score(row.away_score),
+            row.points_awarded.to_string(),
+        ]),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_predictions_csv() {
+        let rows = [PredictionRow {
+            week_number: 3,
+            display_name: "=Gaffer".to_string(),
+            home_team: "Brighton, Hove Albion".to_string(),
+            away_team: "Spurs".to_string(),
+            home_score_prediction: 2,
+            away_score_prediction: 1,
+            home_score: None,
+            away_score: None,
+            points_awarded: 0,
+        }];
+
+        let csv = predictions_csv("2024-25", &rows);
+        let line = csv.lines().nth(1).unwrap();
+        assert_eq!(line, "2024-25,3,'=Gaffer,\"Brighton, Hove Albion\",Spurs,2,1,,,0");
+    }
+}
@@ -0,0 +1,164 @@
+// gameweeks.rs
+//
+// Changes to a gameweek's fixtures and results, shared by the admin pages and
+// `superior6-admin` so both save, score and audit them the same way. Each
+// change and its audit entries are committed together.
+
+use serde_json::{json, Value};
+use sqlx::{query, query_as, PgPool};
+use uuid::Uuid;
+use crate::achievements::award_season_achievements;
+use crate::audit::{self, Actor};
+use crate::errors::AppError;
+use crate::models::{CreateFixture, Fixture, FixtureResult, Gameweek};
+use crate::queries::gameweek_fixtures;
+use crate::scoring::{gameweek_points, score_gameweek};
+
+/// Fixtures as they appear in the audit log.
+fn fixture_list(fixtures: &[Fixture]) -> Vec<Value> {
+    fixtures
+        .iter()
+        .map(|f| json!({
+            "fixture_order": f.fixture_order,
+            "home_team": f.home_team,
+            "away_team": f.away_team,
+            "kickoff_time": f.kickoff_time,
+        }))
+        .collect()
+}
+
+/// Each fixture's result, for the audit log.
+fn scorelines(fixtures: &[Fixture]) -> Vec<Value> {
+    fixtures
+        .iter()
+        .map(|f| json!({
+            "fixture_id": f.id,
+            "fixture": format!("{} v {}", f.home_team, f.away_team),
+            "home_score": f.home_score,
+            "away_score": f.away_score,
+        }))
+        .collect()
+}
+
+/// Swaps a gameweek's fixtures for new ones, returning the new list.
+pub async fn replace_fixtures(
+    db: &PgPool,
+    gameweek_id: Uuid,
+    fixtures: &[CreateFixture],
+    actor: Actor<'_>,
+) -> Result<Vec<Fixture>, AppError> {
+    let mut tx = db.begin().await?;
+
+    let replaced = query_as::<_, Fixture>(
+        "DELETE FROM fixtures WHERE gameweek_id = $1 RETURNING *"
+    )
+    .bind(gameweek_id)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let mut created = Vec::with_capacity(fixtures.len());
+    for fixture in fixtures {
+        let fixture = query_as::<_, Fixture>(
+            r#"
+            INSERT INTO fixtures (gameweek_id, home_team, away_team, kickoff_time, fixture_order)
+            VALUES ($1, $2, $3, $4, $5)
+            RETURNING *
+            "#
+        )
+        .bind(gameweek_id)
+        .bind(&fixture.home_team)
+        .bind(&fixture.away_team)
+        .bind(fixture.kickoff_time)
+        .bind(fixture.fixture_order)
+        .fetch_one(&mut *tx)
+        .await?;
+        created.push(fixture);
+    }
+
+    audit::Event::new(audit::FIXTURES_REPLACED)
+        .by_actor(actor)
+        .details(json!({ "gameweek_id": gameweek_id }))
+        .change(fixture_list(&replaced), fixture_list(&created))
+        .record(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+
+    Ok(created)
+}
+
+/// Enters the results, scores the gameweek and marks it completed, then
+/// re-evaluates the season's badges. Results for fixtures in other gameweeks
+/// are refused with `InvalidResults`.
+pub async fn enter_results(
+    db: &PgPool,
+    gameweek: &Gameweek,
+    results: &[FixtureResult],
+    actor: Actor<'_>,
+) -> Result<(), AppError> {
+    let mut tx = db.begin().await?;
+
+    let scores_before = scorelines(&gameweek_fixtures(&mut *tx, gameweek.id).await?);
+    let points_before = gameweek_points(&mut *tx, gameweek.id).await?;
+
+    for result in results {
+        let updated = query!(
+            "UPDATE fixtures SET home_score = $1, away_score = $2 WHERE id = $3 AND gameweek_id = $4",
+            result.home_score,
+            result.away_score,
+            result.fixture_id,
+            gameweek.id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        if updated.rows_affected() == 0 {
+            return Err(AppError::InvalidResults);
+        }
+    }
+
+    audit::Event::new(audit::RESULTS_SUBMITTED)
+        .by_actor(actor)
+        .details(json!({ "gameweek_id": gameweek.id }))
+        .change(scores_before, scorelines(&gameweek_fixtures(&mut *tx, gameweek.id).await?))
+        .record(&mut *tx)
+        .await?;
+
+    score_gameweek(&mut tx, gameweek.id).await?;
+
+    audit::Event::new(audit::SCORES_RECALCULATED)
+        .by_actor(actor)
+        .details(json!({ "gameweek_id": gameweek.id, "cause": audit::RESULTS_SUBMITTED }))
+        .change(points_before, gameweek_points(&mut *tx, gameweek.id).await?)
+        .record(&mut *tx)
+        .await?;
+
+    query!("UPDATE gameweeks SET is_completed = true WHERE id = $1", gameweek.id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+
+    // Badges are worked out from the saved scores, so if this fails,
+    // recomputing the season puts them right.
+    award_season_achievements(db, &gameweek.season).await
+}
+
+/// Scores a gameweek again from its saved results, e.g. after a rule change.
+pub async fn rescore(db: &PgPool, gameweek_id: Uuid, cause: &str, actor: Actor<'_>) -> Result<(), AppError> {
+    let mut tx = db.begin().await?;
+    let points_before = gameweek_points(&mut *tx, gameweek_id).await?;
+
+    score_gameweek(&mut tx, gameweek_id).await?;
+
+    audit::Event::new(audit::SCORES_RECALCULATED)
+        .by_actor(actor)
+        .details(json!({ "gameweek_id": gameweek_id, "cause": cause }))
+        .change(points_before, gameweek_points(&mut *tx, gameweek_id).await?)
+        .record(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
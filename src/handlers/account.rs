@@ -0,0 +1,459 @@
+// handlers/account.rs
+
+use askama::Template;
+use axum::extract::{Path, State};
+use axum::http::header;
+use axum::response::{Html, IntoResponse, Redirect, Response};
+use axum::Form;
+use axum_extra::extract::CookieJar;
+use chrono::Utc;
+use serde_json::json;
+use uuid::Uuid;
+use validator::Validate;
+use crate::AppState;
+use crate::account::{self, DeletionMode};
+use crate::api_tokens::{create_token as create_api_token, revoke_token as revoke_api_token, user_tokens, TokenScope};
+use crate::audit;
+use crate::auth::AuthUser;
+use crate::email_verification::{send_verification, verification_required};
+use crate::errors::AppError;
+use crate::models::{ChangeEmail, ChangePassword, CreateApiToken, DeleteAccount, TwoFactorCode, UpdateUserNames, User};
+use crate::roles;
+use crate::sessions::{
+    clear_session_cookie, revoke_all_sessions, revoke_session as revoke_user_session, session_cookie, start_session,
+    user_sessions, ClientInfo
+};
+use crate::templates::account::{
+    AccountSettingsTemplate, ApiTokensTemplate, SessionsTemplate, TwoFactorSetup, TwoFactorTemplate
+};
+use crate::two_factor;
+use crate::user_admin;
+
+pub async fn tokens(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+) -> Result<impl IntoResponse, AppError> {
+    auth_user.require_session()?;
+
+    let tokens = user_tokens(&state.db, auth_user.user.id).await?;
+    let template = ApiTokensTemplate::new(&auth_user.user, tokens, None, None);
+
+    Ok(Html(template.render()?))
+}
+
+pub async fn create_token(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+    Form(input): Form<CreateApiToken>,
+) -> Result<impl IntoResponse, AppError> {
+    auth_user.require_session()?;
+
+    let result = match (input.validate(), input.scope.parse::<TokenScope>()) {
+        (Err(_), _) => Err("Token names must be between 1 and 100 characters".to_string()),
+        (_, Err(error)) => Err(error),
+        (Ok(()), Ok(scope)) => Ok(create_api_token(&state.db, auth_user.user.id, input.name.trim(), scope).await?),
+    };
+
+    let tokens = user_tokens(&state.db, auth_user.user.id).await?;
+    let template = match result {
+        Ok(secret) => ApiTokensTemplate::new(&auth_user.user, tokens, Some(secret), None),
+        Err(error) => ApiTokensTemplate::new(&auth_user.user, tokens, None, Some(error)),
+    };
+
+    Ok(Html(template.render()?))
+}
+
+pub async fn revoke_token(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+    Path(token_id): Path<Uuid>,
+) -> Result<impl IntoResponse, AppError> {
+    auth_user.require_session()?;
+
+    revoke_api_token(&state.db, auth_user.user.id, token_id).await?;
+
+    Ok(Redirect::to("/account/tokens"))
+}
+
+pub async fn sessions(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+) -> Result<impl IntoResponse, AppError> {
+    auth_user.require_session()?;
+
+    let sessions = user_sessions(&state.db, auth_user.user.id).await?;
+    let template = SessionsTemplate::new(&auth_user.user, sessions, auth_user.session_id);
+
+    Ok(Html(template.render()?))
+}
+
+pub async fn revoke_session(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+    Path(session_id): Path<Uuid>,
+) -> Result<impl IntoResponse, AppError> {
+    auth_user.require_session()?;
+
+    revoke_user_session(&state.db, auth_user.user.id, session_id).await?;
+
+    Ok(Redirect::to("/account/sessions"))
+}
+
+pub async fn sign_out_everywhere(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+) -> Result<impl IntoResponse, AppError> {
+    auth_user.require_session()?;
+
+    revoke_all_sessions(&state.db, auth_user.user.id).await?;
+
+    Ok((CookieJar::new().add(clear_session_cookie()), Redirect::to("/login")))
+}
+
+/// Staff must keep 2FA on when the server requires it.
+async fn two_factor_required(state: &AppState, user: &User) -> Result<bool, AppError> {
+    Ok(state.config.require_admin_two_factor && !roles::user_roles(&state.db, user.id).await?.is_empty())
+}
+
+async fn two_factor_template<'a>(state: &AppState, user: &'a User) -> Result<TwoFactorTemplate<'a>, AppError> {
+    let enabled = two_factor::is_enabled(&state.db, user.id).await?;
+    let recovery_codes_left = two_factor::unused_recovery_codes(&state.db, user.id).await?;
+
+    Ok(TwoFactorTemplate::new(user, enabled, recovery_codes_left, two_factor_required(state, user).await?))
+}
+
+fn two_factor_setup(user: &User, secret: String) -> Result<TwoFactorSetup, AppError> {
+    let qr_svg = two_factor::qr_code_svg(&two_factor::provisioning_uri(&secret, &user.email))?;
+
+    Ok(TwoFactorSetup { secret, qr_svg })
+}
+
+pub async fn two_factor(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+) -> Result<impl IntoResponse, AppError> {
+    auth_user.require_session()?;
+
+    let template = two_factor_template(&state, &auth_user.user).await?;
+
+    Ok(Html(template.render()?))
+}
+
+pub async fn setup_two_factor(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+) -> Result<impl IntoResponse, AppError> {
+    auth_user.require_session()?;
+
+    let secret = two_factor::begin_enrolment(&state.db, auth_user.user.id).await?;
+    let mut template = two_factor_template(&state, &auth_user.user).await?;
+    template.setup = Some(two_factor_setup(&auth_user.user, secret)?);
+
+    Ok(Html(template.render()?))
+}
+
+pub async fn enable_two_factor(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+    Form(input): Form<TwoFactorCode>,
+) -> Result<impl IntoResponse, AppError> {
+    auth_user.require_session()?;
+
+    let result = two_factor::confirm_enrolment(&state.db, auth_user.user.id, &input.code).await;
+    let mut template = two_factor_template(&state, &auth_user.user).await?;
+
+    match result {
+        Ok(codes) => template.recovery_codes = Some(codes),
+        Err(AppError::InvalidTwoFactorCode) => {
+            // Let them try again against the same secret
+            if let Some(pending) = two_factor::two_factor_for(&state.db, auth_user.user.id).await? {
+                if pending.enabled_at.is_none() {
+                    template.setup = Some(two_factor_setup(&auth_user.user, pending.secret)?);
+                }
+            }
+            template.error = Some("That code didn't match. Check your device's clock and try again.".to_string());
+        }
+        Err(error) => return Err(error),
+    }
+
+    Ok(Html(template.render()?))
+}
+
+pub async fn regenerate_recovery_codes(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+    Form(input): Form<TwoFactorCode>,
+) -> Result<impl IntoResponse, AppError> {
+    auth_user.require_session()?;
+
+    let result = two_factor::verify_user_code(&state.db, auth_user.user.id, &input.code).await;
+    let codes = match result {
+        Ok(()) => Some(two_factor::replace_recovery_codes(&state.db, auth_user.user.id).await?),
+        Err(AppError::InvalidTwoFactorCode) => None,
+        Err(error) => return Err(error),
+    };
+
+    let mut template = two_factor_template(&state, &auth_user.user).await?;
+    match codes {
+        Some(codes) => template.recovery_codes = Some(codes),
+        None => template.error = Some("That code didn't work.".to_string()),
+    }
+
+    Ok(Html(template.render()?))
+}
+
+pub async fn disable_two_factor(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+    Form(input): Form<TwoFactorCode>,
+) -> Result<impl IntoResponse, AppError> {
+    auth_user.require_session()?;
+
+    if two_factor_required(&state, &auth_user.user).await? {
+        return Err(AppError::TwoFactorRequired);
+    }
+
+    match two_factor::verify_user_code(&state.db, auth_user.user.id, &input.code).await {
+        Ok(()) => {
+            two_factor::disable(&state.db, auth_user.user.id).await?;
+            Ok(Redirect::to("/account/two-factor").into_response())
+        }
+        Err(AppError::InvalidTwoFactorCode) => {
+            let mut template = two_factor_template(&state, &auth_user.user).await?;
+            template.error = Some("That code didn't work.".to_string());
+            Ok(Html(template.render()?).into_response())
+        }
+        Err(error) => Err(error),
+    }
+}
+
+/// The provider the player can sign in with again instead of giving their
+/// password, if their account is linked to one.
+async fn sso_provider(state: &AppState, user: &User) -> Result<Option<String>, AppError> {
+    let Some(oidc) = &state.config.oidc else {
+        return Ok(None);
+    };
+
+    Ok(account::uses_single_sign_on(&state.db, user.id)
+        .await?
+        .then(|| oidc.provider_name.clone()))
+}
+
+/// Checks the player is who they say before a sensitive change. Returns the
+/// error to show if not.
+async fn confirm_identity(state: &AppState, auth_user: &AuthUser, current_password: &str) -> Result<Option<String>, AppError> {
+    if account::reauthenticated(&state.db, &auth_user.user, auth_user.session_id, current_password).await? {
+        return Ok(None);
+    }
+
+    let error = match sso_provider(state, &auth_user.user).await? {
+        Some(provider) => format!(
+            "Enter your current password, or sign in with {} again and try within {} minutes",
+            provider,
+            account::REAUTHENTICATE_MINUTES
+        ),
+        None => "Your current password wasn't right".to_string(),
+    };
+
+    Ok(Some(error))
+}
+
+async fn settings_template<'a>(
+    state: &AppState,
+    user: &'a User,
+    error: Option<String>,
+    success: Option<String>,
+) -> Result<AccountSettingsTemplate<'a>, AppError> {
+    let can_erase = account::can_erase(&state.db, user.id).await?;
+    let has_roles = !roles::user_roles(&state.db, user.id).await?.is_empty();
+    let sso_provider = sso_provider(state, user).await?;
+
+    Ok(AccountSettingsTemplate::new(user, can_erase, has_roles, sso_provider, error, success))
+}
+
+async fn render_settings(
+    state: &AppState,
+    user: &User,
+    error: Option<String>,
+    success: Option<String>,
+) -> Result<Response, AppError> {
+    let template = settings_template(state, user, error, success).await?;
+
+    Ok(Html(template.render()?).into_response())
+}
+
+pub async fn settings(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+) -> Result<impl IntoResponse, AppError> {
+    auth_user.require_session()?;
+
+    render_settings(&state, &auth_user.user, None, None).await
+}
+
+pub async fn update_profile(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+    client: ClientInfo,
+    Form(input): Form<UpdateUserNames>,
+) -> Result<impl IntoResponse, AppError> {
+    auth_user.require_session()?;
+
+    if input.validate().is_err() {
+        let error = "Names must be at least 2 characters, and display names at most 100".to_string();
+        return render_settings(&state, &auth_user.user, Some(error), None).await;
+    }
+
+    let mut tx = state.db.begin().await?;
+    user_admin::update_names(&mut *tx, auth_user.user.id, &input.name, &input.display_name).await?;
+    audit::record(&mut *tx, audit::ACCOUNT_UPDATED, Some(auth_user.user.id), client.ip_address.as_deref(), json!({
+        "name": input.name.trim(),
+        "display_name": input.display_name.trim(),
+    }))
+    .await?;
+    tx.commit().await?;
+
+    let mut user = auth_user.user;
+    user.name = input.name.trim().to_string();
+    user.display_name = input.display_name.trim().to_string();
+
+    render_settings(&state, &user, None, Some("Your name has been saved".to_string())).await
+}
+
+pub async fn change_email(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+    client: ClientInfo,
+    Form(input): Form<ChangeEmail>,
+) -> Result<impl IntoResponse, AppError> {
+    auth_user.require_session()?;
+
+    let user = &auth_user.user;
+    if input.validate().is_err() {
+        return render_settings(&state, user, Some("Please enter a valid email address".to_string()), None).await;
+    }
+    if let Some(error) = confirm_identity(&state, &auth_user, &input.current_password).await? {
+        return render_settings(&state, user, Some(error), None).await;
+    }
+
+    let email = input.email.trim();
+    let needs_verification = verification_required(&state.db).await?;
+
+    let mut tx = state.db.begin().await?;
+    let updated = match account::change_email(&mut tx, user.id, email, !needs_verification).await {
+        Ok(updated) => updated,
+        Err(AppError::EmailExists) => {
+            let error = "Another account already uses that email address".to_string();
+            return render_settings(&state, user, Some(error), None).await;
+        }
+        Err(error) => return Err(error),
+    };
+
+    // Neither address is kept, as the audit log outlives accounts
+    audit::record(&mut *tx, audit::ACCOUNT_EMAIL_CHANGED, Some(user.id), client.ip_address.as_deref(), json!({
+        "needs_verification": needs_verification,
+    }))
+    .await?;
+    tx.commit().await?;
+
+    let success = if needs_verification {
+        send_verification(&state, &updated).await?;
+        format!("We've sent a link to {}. Open it to confirm your new address before you next sign in.", email)
+    } else {
+        format!("You now sign in as {}", email)
+    };
+
+    render_settings(&state, &updated, None, Some(success)).await
+}
+
+pub async fn change_password(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+    client: ClientInfo,
+    Form(input): Form<ChangePassword>,
+) -> Result<impl IntoResponse, AppError> {
+    auth_user.require_session()?;
+
+    let user = &auth_user.user;
+    if input.validate().is_err() {
+        let error = "New passwords must be at least 8 characters and match".to_string();
+        return render_settings(&state, user, Some(error), None).await;
+    }
+    if let Some(error) = confirm_identity(&state, &auth_user, &input.current_password).await? {
+        return render_settings(&state, user, Some(error), None).await;
+    }
+
+    let mut tx = state.db.begin().await?;
+    account::change_password(&mut tx, user.id, &input.password, state.config.bcrypt_cost).await?;
+    audit::record(&mut *tx, audit::ACCOUNT_PASSWORD_CHANGED, Some(user.id), client.ip_address.as_deref(), json!({})).await?;
+    tx.commit().await?;
+
+    // Changing the hash signed out every session, this one included
+    let token = start_session(&state, user, &client).await?;
+    let template = settings_template(&state, user, None, Some("Your password has been changed".to_string())).await?;
+
+    Ok((CookieJar::new().add(session_cookie(token, &state.config)), Html(template.render()?)).into_response())
+}
+
+pub async fn export(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+) -> Result<impl IntoResponse, AppError> {
+    auth_user.require_session()?;
+
+    let export = account::export_data(&state.db, &auth_user.user).await?;
+    let body = serde_json::to_string_pretty(&export).map_err(|_| AppError::Internal)?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/json".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"superior6-{}.json\"", Utc::now().format("%Y-%m-%d")),
+            ),
+        ],
+        body,
+    ))
+}
+
+pub async fn delete_account(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+    client: ClientInfo,
+    Form(input): Form<DeleteAccount>,
+) -> Result<impl IntoResponse, AppError> {
+    auth_user.require_session()?;
+
+    let user = &auth_user.user;
+    let mode = match input.mode.parse::<DeletionMode>() {
+        Ok(mode) => mode,
+        Err(error) => return render_settings(&state, user, Some(error), None).await,
+    };
+    if let Some(error) = confirm_identity(&state, &auth_user, &input.current_password).await? {
+        return render_settings(&state, user, Some(error), None).await;
+    }
+
+    // Recorded first, so deleting the account strips this event's IP address too
+    let mut tx = state.db.begin().await?;
+    audit::record(&mut *tx, audit::ACCOUNT_DELETED, Some(user.id), client.ip_address.as_deref(), json!({
+        "user_id": user.id,
+        "mode": input.mode,
+    }))
+    .await?;
+
+    match account::delete_account(&mut tx, user.id, mode).await {
+        Ok(()) => {}
+        Err(AppError::HoldsRoles) => {
+            let error = "You're on the league's admin team. Ask a super-admin to remove your roles before deleting your account.".to_string();
+            return render_settings(&state, user, Some(error), None).await;
+        }
+        Err(AppError::Forbidden) => {
+            let error = "Your account can't be erased completely, but you can still anonymise it.".to_string();
+            return render_settings(&state, user, Some(error), None).await;
+        }
+        Err(error) => return Err(error),
+    }
+    tx.commit().await?;
+
+    Ok((CookieJar::new().add(clear_session_cookie()), Redirect::to("/")).into_response())
+}
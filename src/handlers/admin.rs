@@ -1,20 +1,32 @@
 // handlers/admin.rs
 
+use crate::achievements::backfill_achievements as backfill_all_achievements;
+use crate::audit::{self, Actor};
 use crate::auth::AdminUser;
 use crate::errors::AppError;
-use crate::models::{CreateFixture, CreateGameweek, Fixture, Gameweek, GameweekResults};
-use crate::scoring::calculate_gameweek_scores;
+use crate::email_verification::{pending_count, set_verification_required, verification_required};
+use crate::settings::REQUIRE_EMAIL_VERIFICATION;
+use crate::announcements::set_gameweek_note;
+use crate::gameweeks;
+use crate::models::{
+    CreateFixture, CreateGameweek, Fixture, Gameweek, GameweekResults, UpdateEmailVerification, UpdateGameweekNote
+};
+use crate::queries;
+use crate::roles::Permission;
+use crate::sessions::ClientInfo;
 use crate::templates::admin::{
     AdminDashboardTemplate, FixtureInfo, FixturesTemplate, GameweekInfo,
     GameweeksTemplate, ResultsTemplate
 };
 use crate::AppState;
 use askama::Template;
-use axum::extract::State;
+use axum::extract::{Path, State};
 use axum::response::{Html, IntoResponse, Redirect};
 use axum::Form;
 use chrono::Utc;
+use serde_json::{json, Value};
 use sqlx::{query, query_as};
+use uuid::Uuid;
 use validator::Validate;
 
 pub async fn dashboard(
@@ -37,6 +49,9 @@ pub async fn dashboard(
         .count
         .unwrap_or(0) as i32;
 
+    let require_email_verification = verification_required(&state.db).await?;
+    let pending_verifications = pending_count(&state.db).await?;
+
     // Get recent gameweeks
     let recent_gameweeks = query!(
         r#"
@@ -73,6 +88,9 @@ pub async fn dashboard(
         }),
         total_users,
         recent_gameweeks: gameweeks,
+        require_email_verification,
+        pending_verifications,
+        can: admin_user.permissions(),
     };
 
     Ok(Html(template.render()?))
@@ -82,6 +100,8 @@ pub async fn gameweeks(
     State(state): State<AppState>,
     admin_user: AdminUser,
 ) -> Result<impl IntoResponse, AppError> {
+    admin_user.require(Permission::ManageFixtures)?;
+
     let gameweeks = query_as::<_, Gameweek>(
         "SELECT * FROM gameweeks ORDER BY season DESC, week_number DESC"
     )
@@ -100,9 +120,12 @@ pub async fn gameweeks(
 
 pub async fn create_gameweek(
     State(state): State<AppState>,
-    _admin_user: AdminUser,
+    admin_user: AdminUser,
+    client: ClientInfo,
     Form(input): Form<CreateGameweek>,
 ) -> Result<impl IntoResponse, AppError> {
+    admin_user.require(Permission::ManageFixtures)?;
+
     input.validate()?;
 
     // Check if gameweek already exists
@@ -122,7 +145,7 @@ pub async fn create_gameweek(
             .await?;
 
         let template = GameweeksTemplate {
-            user: &_admin_user.user,
+            user: &admin_user.user,
             gameweeks,
             error: Some("Gameweek already exists for this season".to_string()),
             success: None,
@@ -130,24 +153,67 @@ pub async fn create_gameweek(
         return Ok(Html(template.render()?));
     }
 
+    let mut tx = state.db.begin().await?;
+
     // Deactivate all other gameweeks before creating new one
-    query!("UPDATE gameweeks SET is_active = false")
-        .execute(&state.db)
-        .await?;
+    let deactivated: Vec<_> = query!("UPDATE gameweeks SET is_active = false WHERE is_active = true RETURNING id")
+        .fetch_all(&mut *tx)
+        .await?
+        .into_iter()
+        .map(|row| row.id)
+        .collect();
 
     // Create new gameweek (it will be active by default)
-    query!(
+    let gameweek = query_as::<_, Gameweek>(
         r#"
         INSERT INTO gameweeks (week_number, season, deadline, is_active)
         VALUES ($1, $2, $3, true)
-        "#,
-        input.week_number,
-        input.season,
-        input.deadline
+        RETURNING *
+        "#
     )
-        .execute(&state.db)
+        .bind(input.week_number)
+        .bind(&input.season)
+        .bind(input.deadline)
+        .fetch_one(&mut *tx)
         .await?;
 
+    audit::Event::new(audit::GAMEWEEK_CREATED)
+        .by(admin_user.user.id, &client)
+        .details(json!({ "gameweek_id": gameweek.id, "deactivated": deactivated }))
+        .change(Value::Null, &gameweek)
+        .record(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+
+    Ok(Redirect::to("/admin/gameweeks"))
+}
+
+pub async fn update_gameweek_note(
+    State(state): State<AppState>,
+    admin_user: AdminUser,
+    client: ClientInfo,
+    Path(gameweek_id): Path<Uuid>,
+    Form(input): Form<UpdateGameweekNote>,
+) -> Result<impl IntoResponse, AppError> {
+    admin_user.require(Permission::ManageFixtures)?;
+
+    input.validate()?;
+
+    let mut tx = state.db.begin().await?;
+    let previous = set_gameweek_note(&mut *tx, gameweek_id, &input.note).await?;
+    let note = Some(input.note.trim()).filter(|note| !note.is_empty());
+
+    if previous.as_deref() != note {
+        audit::Event::new(audit::GAMEWEEK_NOTE_UPDATED)
+            .by(admin_user.user.id, &client)
+            .details(json!({ "gameweek_id": gameweek_id }))
+            .change(previous, note)
+            .record(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+
     Ok(Redirect::to("/admin/gameweeks"))
 }
 
@@ -155,6 +221,8 @@ pub async fn fixtures(
     State(state): State<AppState>,
     admin_user: AdminUser,
 ) -> Result<impl IntoResponse, AppError> {
+    admin_user.require(Permission::ManageFixtures)?;
+
     // Get current active gameweek
     let active_gameweek = query!(
         "SELECT id, week_number, season FROM gameweeks WHERE is_active = true LIMIT 1"
@@ -194,8 +262,11 @@ pub async fn fixtures(
 pub async fn create_fixtures(
     State(state): State<AppState>,
     admin_user: AdminUser,
+    client: ClientInfo,
     Form(fixtures): Form<Vec<CreateFixture>>,
 ) -> Result<impl IntoResponse, AppError> {
+    admin_user.require(Permission::ManageFixtures)?;
+
     // Get current active gameweek
     let active_gameweek = query!(
         "SELECT id FROM gameweeks WHERE is_active = true LIMIT 1"
@@ -235,30 +306,13 @@ pub async fn create_fixtures(
         fixture.validate()?;
     }
 
-    // Delete existing fixtures for this gameweek
-    query!(
-        "DELETE FROM fixtures WHERE gameweek_id = $1",
-        active_gameweek.id
+    gameweeks::replace_fixtures(
+        &state.db,
+        active_gameweek.id,
+        &fixtures,
+        Actor::Admin(admin_user.user.id, &client),
     )
-        .execute(&state.db)
-        .await?;
-
-    // Insert new fixtures
-    for fixture in fixtures {
-        query!(
-            r#"
-            INSERT INTO fixtures (gameweek_id, home_team, away_team, kickoff_time, fixture_order)
-            VALUES ($1, $2, $3, $4, $5)
-            "#,
-            active_gameweek.id,
-            fixture.home_team,
-            fixture.away_team,
-            fixture.kickoff_time,
-            fixture.fixture_order
-        )
-            .execute(&state.db)
-            .await?;
-    }
+    .await?;
 
     Ok(Redirect::to("/admin/fixtures"))
 }
@@ -267,6 +321,8 @@ pub async fn results(
     State(state): State<AppState>,
     admin_user: AdminUser,
 ) -> Result<impl IntoResponse, AppError> {
+    admin_user.require(Permission::EnterResults)?;
+
     // Get current active gameweek
     let active_gameweek = query!(
         "SELECT id, week_number, season FROM gameweeks WHERE is_active = true LIMIT 1"
@@ -315,20 +371,19 @@ pub async fn results(
 
 pub async fn submit_results(
     State(state): State<AppState>,
-    _admin_user: AdminUser,
+    admin_user: AdminUser,
+    client: ClientInfo,
     Form(input): Form<GameweekResults>,
 ) -> Result<impl IntoResponse, AppError> {
-    // Get current active gameweek
-    let active_gameweek = query!(
-        "SELECT id FROM gameweeks WHERE is_active = true LIMIT 1"
-    )
-        .fetch_optional(&state.db)
+    admin_user.require(Permission::EnterResults)?;
+
+    let active_gameweek = queries::active_gameweek(&state.db)
         .await?
         .ok_or(AppError::NotFound)?;
 
     // Validate we have exactly 6 results
     if input.results.len() != 6 {
-        return Err(AppError::InvalidPrediction);
+        return Err(AppError::InvalidResults);
     }
 
     // Validate all results
@@ -336,28 +391,47 @@ pub async fn submit_results(
         result.validate()?;
     }
 
-    // Update fixture results
-    for result in input.results {
-        query!(
-            "UPDATE fixtures SET home_score = $1, away_score = $2 WHERE id = $3",
-            result.home_score,
-            result.away_score,
-            result.fixture_id
-        )
-            .execute(&state.db)
-            .await?;
-    }
+    gameweeks::enter_results(
+        &state.db,
+        &active_gameweek,
+        &input.results,
+        Actor::Admin(admin_user.user.id, &client),
+    )
+    .await?;
+
+    Ok(Redirect::to("/admin/results"))
+}
+
+pub async fn backfill_achievements(
+    State(state): State<AppState>,
+    admin_user: AdminUser,
+) -> Result<impl IntoResponse, AppError> {
+    admin_user.require(Permission::ManageSettings)?;
 
-    // Calculate scores for this gameweek
-    calculate_gameweek_scores(&state.db, active_gameweek.id).await?;
+    backfill_all_achievements(&state.db).await?;
 
-    // Mark gameweek as completed
-    query!(
-        "UPDATE gameweeks SET is_completed = true WHERE id = $1",
-        active_gameweek.id
-    )
-        .execute(&state.db)
+    Ok(Redirect::to("/admin"))
+}
+
+pub async fn set_email_verification(
+    State(state): State<AppState>,
+    admin_user: AdminUser,
+    client: ClientInfo,
+    Form(input): Form<UpdateEmailVerification>,
+) -> Result<impl IntoResponse, AppError> {
+    admin_user.require(Permission::ManageSettings)?;
+
+    let mut tx = state.db.begin().await?;
+    let before = verification_required(&mut *tx).await?;
+    let confirmed = set_verification_required(&mut tx, input.required).await?;
+
+    audit::Event::new(audit::SETTING_CHANGED)
+        .by(admin_user.user.id, &client)
+        .details(json!({ "setting": REQUIRE_EMAIL_VERIFICATION, "accounts_confirmed": confirmed }))
+        .change(before, input.required)
+        .record(&mut *tx)
         .await?;
+    tx.commit().await?;
 
-    Ok(Redirect::to("/admin/results"))
-}
\ No newline at end of file
+    Ok(Redirect::to("/admin"))
+}
@@ -0,0 +1,136 @@
+// handlers/announcements.rs
+
+use askama::Template;
+use axum::extract::{Path, State};
+use axum::response::{Html, IntoResponse, Redirect, Response};
+use axum::Form;
+use chrono::Utc;
+use serde_json::{json, Value};
+use uuid::Uuid;
+use validator::Validate;
+use crate::AppState;
+use crate::announcements::{self, Severity};
+use crate::audit;
+use crate::auth::{AdminUser, AuthUser};
+use crate::errors::AppError;
+use crate::models::{CreateAnnouncement, DismissAnnouncement};
+use crate::roles::Permission;
+use crate::sessions::ClientInfo;
+use crate::templates::admin::AnnouncementsTemplate;
+
+async fn render(
+    state: &AppState,
+    admin_user: &AdminUser,
+    error: Option<String>,
+    success: Option<String>,
+) -> Result<Response, AppError> {
+    let all = announcements::all(&state.db).await?;
+    let template = AnnouncementsTemplate::new(&admin_user.user, all, error, success);
+
+    Ok(Html(template.render()?).into_response())
+}
+
+pub async fn announcements(
+    State(state): State<AppState>,
+    admin_user: AdminUser,
+) -> Result<impl IntoResponse, AppError> {
+    admin_user.require(Permission::ModerateLeague)?;
+
+    render(&state, &admin_user, None, None).await
+}
+
+pub async fn create_announcement(
+    State(state): State<AppState>,
+    admin_user: AdminUser,
+    client: ClientInfo,
+    Form(input): Form<CreateAnnouncement>,
+) -> Result<impl IntoResponse, AppError> {
+    admin_user.require(Permission::ModerateLeague)?;
+
+    if input.validate().is_err() {
+        let error = "Give the announcement a title of 3 to 200 characters and a message".to_string();
+        return render(&state, &admin_user, Some(error), None).await;
+    }
+    let severity: Severity = input.severity.parse().map_err(AppError::InvalidRequestBody)?;
+
+    let schedule = announcements::parse_schedule_time(&input.starts_at)
+        .and_then(|starts_at| Ok((starts_at, announcements::parse_schedule_time(&input.ends_at)?)));
+    let (starts_at, ends_at) = match schedule {
+        Ok((starts_at, ends_at)) => (starts_at.unwrap_or_else(Utc::now), ends_at),
+        Err(error) => return render(&state, &admin_user, Some(error), None).await,
+    };
+
+    if ends_at.is_some_and(|ends_at| ends_at <= starts_at) {
+        let error = "An announcement has to end after it starts".to_string();
+        return render(&state, &admin_user, Some(error), None).await;
+    }
+
+    let mut tx = state.db.begin().await?;
+    let announcement = announcements::create(
+        &mut *tx,
+        &input.title,
+        &input.body,
+        severity,
+        starts_at,
+        ends_at,
+        admin_user.user.id,
+    )
+    .await?;
+
+    audit::Event::new(audit::ANNOUNCEMENT_CREATED)
+        .by(admin_user.user.id, &client)
+        .details(json!({ "announcement_id": announcement.id }))
+        .change(Value::Null, &announcement)
+        .record(&mut *tx)
+        .await?;
+    tx.commit().await?;
+
+    render(&state, &admin_user, None, Some(format!("\"{}\" has been scheduled", announcement.title))).await
+}
+
+pub async fn end_announcement(
+    State(state): State<AppState>,
+    admin_user: AdminUser,
+    client: ClientInfo,
+    Path(announcement_id): Path<Uuid>,
+) -> Result<impl IntoResponse, AppError> {
+    admin_user.require(Permission::ModerateLeague)?;
+
+    let mut tx = state.db.begin().await?;
+    let announcement = announcements::end(&mut *tx, announcement_id).await?;
+
+    audit::record(
+        &mut *tx,
+        audit::ANNOUNCEMENT_ENDED,
+        Some(admin_user.user.id),
+        client.ip_address.as_deref(),
+        json!({
+            "announcement_id": announcement.id,
+            "title": announcement.title,
+            "cancelled": announcement.cancelled_at.is_some(),
+        }),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(Redirect::to("/admin/announcements"))
+}
+
+/// Only ever sends the player back to a page that shows announcements.
+fn return_to(path: Option<&str>) -> &str {
+    match path {
+        Some("/") => "/",
+        _ => "/dashboard",
+    }
+}
+
+pub async fn dismiss(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+    Path(announcement_id): Path<Uuid>,
+    Form(input): Form<DismissAnnouncement>,
+) -> Result<impl IntoResponse, AppError> {
+    announcements::dismiss(&state.db, announcement_id, auth_user.user.id).await?;
+
+    Ok(Redirect::to(return_to(input.return_to.as_deref())))
+}
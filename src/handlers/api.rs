@@ -0,0 +1,275 @@
+// handlers/api.rs
+//
+// JSON endpoints served under /api/v1. Errors use the `ErrorBody` schema
+// from errors.rs.
+
+use axum::extract::rejection::JsonRejection;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::Deserialize;
+use utoipa::{IntoParams, OpenApi};
+use uuid::Uuid;
+use crate::AppState;
+use crate::api_tokens::TokenScope;
+use crate::auth::AuthUser;
+use crate::errors::AppError;
+use crate::models::{
+    CurrentUser, Fixture, FixtureWithPrediction, Gameweek, GameweekPredictions, SeasonLeaderboard,
+    WeeklyLeaderboard
+};
+use crate::openapi::ApiDoc;
+use crate::queries;
+use crate::throttle;
+
+/// Where `routes` is nested.
+pub const PREFIX: &str = "/api/v1";
+
+/// Declares the routes once, so the router and the spec checks in
+/// openapi.rs always see the same list.
+macro_rules! api_routes {
+    ($($method:ident $path:literal => $handler:ident,)*) => {
+        pub fn routes() -> Router<AppState> {
+            Router::new()
+                $(.route($path, $method($handler)))*
+        }
+
+        /// Each route with the request and response bodies its handler's
+        /// signature says it takes and returns.
+        #[cfg(test)]
+        pub(crate) fn route_table() -> Vec<crate::openapi::contract::Route> {
+            vec![
+                $(crate::openapi::contract::Route::new(stringify!($method), $path, stringify!($handler), &$handler),)*
+            ]
+        }
+    };
+}
+
+api_routes! {
+    get "/me" => me,
+    get "/gameweeks" => gameweeks,
+    get "/gameweeks/current" => current_gameweek,
+    get "/gameweeks/:id" => gameweek,
+    get "/gameweeks/:id/fixtures" => fixtures,
+    get "/gameweeks/:id/predictions" => predictions,
+    post "/predictions" => submit_predictions,
+    get "/leaderboard/season" => season_leaderboard,
+    get "/leaderboard/weekly" => weekly_leaderboard,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct SeasonQuery {
+    /// Season such as `2024-25`; defaults to the active gameweek's season.
+    pub season: Option<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct WeeklyQuery {
+    /// Defaults to the active gameweek.
+    pub gameweek_id: Option<Uuid>,
+}
+
+async fn season_or_current(state: &AppState, season: Option<String>) -> Result<String, AppError> {
+    match season {
+        Some(season) => Ok(season),
+        None => queries::current_season(&state.db).await,
+    }
+}
+
+async fn find_gameweek(state: &AppState, gameweek_id: Uuid) -> Result<Gameweek, AppError> {
+    queries::gameweek_by_id(&state.db, gameweek_id)
+        .await?
+        .ok_or(AppError::NotFound)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/me",
+    tag = "users",
+    responses(
+        (status = 200, description = "The signed-in user", body = CurrentUser),
+        (status = 401, description = "Not signed in", body = ErrorBody),
+    ),
+    security(("cookie" = []), ("bearer" = []))
+)]
+pub async fn me(auth_user: AuthUser) -> Json<CurrentUser> {
+    Json(auth_user.user.into())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/gameweeks",
+    tag = "gameweeks",
+    params(SeasonQuery),
+    responses(
+        (status = 200, description = "Gameweeks in the season, in order", body = [Gameweek]),
+    )
+)]
+pub async fn gameweeks(
+    State(state): State<AppState>,
+    Query(params): Query<SeasonQuery>,
+) -> Result<Json<Vec<Gameweek>>, AppError> {
+    let season = season_or_current(&state, params.season).await?;
+
+    Ok(Json(queries::season_gameweeks(&state.db, &season).await?))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/gameweeks/current",
+    tag = "gameweeks",
+    responses(
+        (status = 200, description = "The active gameweek", body = Gameweek),
+        (status = 404, description = "No gameweek is active", body = ErrorBody),
+    )
+)]
+pub async fn current_gameweek(
+    State(state): State<AppState>,
+) -> Result<Json<Gameweek>, AppError> {
+    let gameweek = queries::active_gameweek(&state.db)
+        .await?
+        .ok_or(AppError::NotFound)?;
+
+    Ok(Json(gameweek))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/gameweeks/{id}",
+    tag = "gameweeks",
+    params(("id" = Uuid, Path, description = "Gameweek id")),
+    responses(
+        (status = 200, description = "The gameweek", body = Gameweek),
+        (status = 404, description = "Unknown gameweek", body = ErrorBody),
+    )
+)]
+pub async fn gameweek(
+    State(state): State<AppState>,
+    Path(gameweek_id): Path<Uuid>,
+) -> Result<Json<Gameweek>, AppError> {
+    Ok(Json(find_gameweek(&state, gameweek_id).await?))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/gameweeks/{id}/fixtures",
+    tag = "gameweeks",
+    params(("id" = Uuid, Path, description = "Gameweek id")),
+    responses(
+        (status = 200, description = "The gameweek's fixtures in order", body = [Fixture]),
+        (status = 404, description = "Unknown gameweek", body = ErrorBody),
+    )
+)]
+pub async fn fixtures(
+    State(state): State<AppState>,
+    Path(gameweek_id): Path<Uuid>,
+) -> Result<Json<Vec<Fixture>>, AppError> {
+    let gameweek = find_gameweek(&state, gameweek_id).await?;
+
+    Ok(Json(queries::gameweek_fixtures(&state.db, gameweek.id).await?))
+}
+
+/// The current user's predictions for a gameweek, one entry per fixture.
+#[utoipa::path(
+    get,
+    path = "/api/v1/gameweeks/{id}/predictions",
+    tag = "predictions",
+    params(("id" = Uuid, Path, description = "Gameweek id")),
+    responses(
+        (status = 200, description = "Each fixture with the user's prediction, if any", body = [FixtureWithPrediction]),
+        (status = 401, description = "Not signed in", body = ErrorBody),
+        (status = 404, description = "Unknown gameweek", body = ErrorBody),
+    ),
+    security(("cookie" = []), ("bearer" = []))
+)]
+pub async fn predictions(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+    Path(gameweek_id): Path<Uuid>,
+) -> Result<Json<Vec<FixtureWithPrediction>>, AppError> {
+    let gameweek = find_gameweek(&state, gameweek_id).await?;
+    let predictions = queries::fixtures_with_predictions(&state.db, gameweek.id, auth_user.user.id).await?;
+
+    Ok(Json(predictions))
+}
+
+/// Submits predictions for the active gameweek, with the same rules as the
+/// predictions page.
+#[utoipa::path(
+    post,
+    path = "/api/v1/predictions",
+    tag = "predictions",
+    request_body = GameweekPredictions,
+    responses(
+        (status = 201, description = "Predictions saved", body = [FixtureWithPrediction]),
+        (status = 400, description = "Invalid predictions, deadline passed or already submitted", body = ErrorBody),
+        (status = 401, description = "Not signed in", body = ErrorBody),
+        (status = 403, description = "API token lacks the predict scope", body = ErrorBody),
+        (status = 404, description = "No gameweek is active", body = ErrorBody),
+        (status = 429, description = "Too many submissions; see the Retry-After header", body = ErrorBody),
+    ),
+    security(("cookie" = []), ("bearer" = []))
+)]
+pub async fn submit_predictions(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+    payload: Result<Json<GameweekPredictions>, JsonRejection>,
+) -> Result<(StatusCode, Json<Vec<FixtureWithPrediction>>), AppError> {
+    auth_user.require_scope(TokenScope::Predict)?;
+    let Json(input) = payload?;
+    let user_id = auth_user.user.id;
+
+    throttle::limit_prediction_submit(&state.db, user_id).await?;
+    let gameweek_id = queries::submit_predictions(&state.db, user_id, &input.predictions).await?;
+    let predictions = queries::fixtures_with_predictions(&state.db, gameweek_id, user_id).await?;
+
+    Ok((StatusCode::CREATED, Json(predictions)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/leaderboard/season",
+    tag = "leaderboards",
+    params(SeasonQuery),
+    responses(
+        (status = 200, description = "Season standings", body = SeasonLeaderboard),
+    )
+)]
+pub async fn season_leaderboard(
+    State(state): State<AppState>,
+    Query(params): Query<SeasonQuery>,
+) -> Result<Json<SeasonLeaderboard>, AppError> {
+    let season = season_or_current(&state, params.season).await?;
+    let standings = queries::season_leaderboard(&state.db, &season).await?;
+
+    Ok(Json(SeasonLeaderboard { season, standings }))
+}
+
+/// Weekly standings for the given gameweek, or the active one.
+#[utoipa::path(
+    get,
+    path = "/api/v1/leaderboard/weekly",
+    tag = "leaderboards",
+    params(WeeklyQuery),
+    responses(
+        (status = 200, description = "Weekly standings", body = WeeklyLeaderboard),
+        (status = 404, description = "Unknown or no active gameweek", body = ErrorBody),
+    )
+)]
+pub async fn weekly_leaderboard(
+    State(state): State<AppState>,
+    Query(params): Query<WeeklyQuery>,
+) -> Result<Json<WeeklyLeaderboard>, AppError> {
+    let gameweek = match params.gameweek_id {
+        Some(gameweek_id) => find_gameweek(&state, gameweek_id).await?,
+        None => queries::active_gameweek(&state.db).await?.ok_or(AppError::NotFound)?,
+    };
+    let standings = queries::weekly_leaderboard(&state.db, gameweek.id).await?;
+
+    Ok(Json(WeeklyLeaderboard { gameweek, standings }))
+}
+
+pub async fn openapi() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
@@ -0,0 +1,27 @@
+// handlers/audit.rs
+
+use askama::Template;
+use axum::extract::{Query, State};
+use axum::response::{Html, IntoResponse};
+use crate::AppState;
+use crate::audit::{self, AuditQuery};
+use crate::auth::AdminUser;
+use crate::errors::AppError;
+use crate::roles::Permission;
+use crate::templates::admin::AuditTemplate;
+
+pub async fn audit_log(
+    State(state): State<AppState>,
+    admin_user: AdminUser,
+    Query(params): Query<AuditQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    admin_user.require(Permission::ViewAudit)?;
+
+    let results = audit::search_events(&state.db, &params.filter(), params.page.unwrap_or(1)).await?;
+    // Carried through the pagination links
+    let query = serde_urlencoded::to_string(&params).unwrap_or_default();
+
+    let template = AuditTemplate::new(&admin_user.user, params, query, results);
+
+    Ok(Html(template.render()?))
+}
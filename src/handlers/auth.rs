@@ -2,19 +2,126 @@
 
 use askama::Template;
 use askama_axum::IntoResponse;
-use axum::extract::State;
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
 use axum::Form;
-use axum::response::{Html, Redirect};
-use axum_extra::extract::cookie::Cookie;
+use axum::response::{Html, Redirect, Response};
 use axum_extra::extract::{CookieJar};
+use chrono::Utc;
+use serde::Deserialize;
+use serde_json::json;
 use sqlx::{query, query_as};
-use time::Duration;
 use validator::Validate;
 use crate::AppState;
+use crate::audit;
 use crate::auth::{hash_password, verify_password, Claims, OptionalAuthUser};
 use crate::errors::AppError;
-use crate::models::{CreateUser, LoginUser, User};
-use crate::templates::auth::{LoginTemplate, RegisterTemplate};
+use crate::metrics;
+use crate::email_verification::{resend_verification, send_verification, verification_required, verify_email as confirm_email};
+use crate::oidc::{self, PendingLogin, SignOnAccount};
+use crate::models::{CreateUser, ForgotPassword, LoginUser, ResendVerification, ResetPassword, TwoFactorCode, User};
+use crate::password_reset::{request_reset, reset_password as reset_user_password};
+use crate::roles;
+use crate::sessions::{clear_session_cookie, revoke_session, session_cookie, start_session, ClientInfo, SESSION_COOKIE};
+use crate::templates::auth::{
+    ConfirmEmailTemplate, ForgotPasswordTemplate, LoginTemplate, RegisterTemplate, ResetPasswordTemplate,
+    TwoFactorLoginTemplate, VerifyEmailTemplate
+};
+use crate::throttle::{self, LoginKeys};
+use crate::two_factor;
+
+/// Starts a session once the user has proved who they are, or sends them to
+/// the second step first if they use two-factor authentication. `method` is
+/// how they proved it, for the audit log.
+async fn sign_in(state: &AppState, user: &User, client: &ClientInfo, method: &str) -> Result<Response, AppError> {
+    if two_factor::is_enabled(&state.db, user.id).await? {
+        let pending = two_factor::pending_login_token(user.id, &state.config.jwt_secret)?;
+        let jar = CookieJar::new().add(two_factor::pending_login_cookie(pending, &state.config.cookies));
+        return Ok((jar, Redirect::to("/login/two-factor")).into_response());
+    }
+
+    let token = start_session(state, user, client).await?;
+    record_sign_in(state, user, client, method).await?;
+
+    // Staff who must use 2FA are sent straight to set it up
+    let staff = !roles::user_roles(&state.db, user.id).await?.is_empty();
+    let next = if staff && state.config.require_admin_two_factor {
+        "/account/two-factor"
+    } else {
+        "/dashboard"
+    };
+
+    Ok((CookieJar::new().add(session_cookie(token, &state.config)), Redirect::to(next)).into_response())
+}
+
+/// The login page, with the single sign-on button when it's configured.
+fn login_page(state: &AppState, error: Option<String>) -> LoginTemplate {
+    let mut template = LoginTemplate::new(error);
+    template.sso_provider = state.config.oidc.as_ref().map(|oidc| oidc.provider_name.clone());
+    template
+}
+
+fn suspended() -> Result<Response, AppError> {
+    let template = LoginTemplate::new(Some("This account has been suspended. Please contact the league admin.".to_string()));
+
+    Ok((StatusCode::FORBIDDEN, Html(template.render()?)).into_response())
+}
+
+async fn record_sign_in(state: &AppState, user: &User, client: &ClientInfo, method: &str) -> Result<(), AppError> {
+    audit::Event::new(audit::LOGIN_SUCCEEDED)
+        .by(user.id, client)
+        .details(json!({ "method": method }))
+        .record(&state.db)
+        .await
+}
+
+fn locked_out(secs: i64) -> Result<Response, AppError> {
+    let template = LoginTemplate::new(Some(format!(
+        "Too many failed attempts. Please try again in {}.",
+        throttle::describe_wait(secs)
+    )));
+
+    Ok((StatusCode::TOO_MANY_REQUESTS, Html(template.render()?)).into_response())
+}
+
+/// Refuses to go any further while either key is locked out.
+async fn check_login_throttle(state: &AppState, keys: &LoginKeys) -> Result<Option<Response>, AppError> {
+    match throttle::check_locked(&state.db, &[&keys.ip, &keys.account]).await {
+        Ok(()) => Ok(None),
+        Err(AppError::RateLimited(secs)) => Ok(Some(locked_out(secs)?)),
+        Err(error) => Err(error),
+    }
+}
+
+/// Counts a failed attempt and audits it, locking the account or IP out if
+/// it has had too many. Returns the lockout page if it did.
+async fn record_failed_login(
+    state: &AppState,
+    keys: &LoginKeys,
+    client: &ClientInfo,
+    action: &str,
+    details: serde_json::Value,
+) -> Result<Option<Response>, AppError> {
+    let ip_address = client.ip_address.as_deref();
+    audit::record(&state.db, action, None, ip_address, details.clone()).await?;
+    metrics::login_failed(if action == audit::TWO_FACTOR_FAILED { "two_factor" } else { "password" });
+
+    match throttle::record_login_failure(&state.db, keys).await? {
+        Some(secs) => {
+            audit::record(
+                &state.db,
+                audit::LOGIN_LOCKED_OUT,
+                None,
+                ip_address,
+                json!({ "attempt": details, "locked_for_secs": secs }),
+            )
+            .await?;
+
+            Ok(Some(locked_out(secs)?))
+        }
+        None => Ok(None),
+    }
+}
 
 pub async fn register_form(
     State(_state): State<AppState>,
@@ -33,10 +140,22 @@ pub async fn register_form(
 
 pub async fn register(
     State(state): State<AppState>,
+    client: ClientInfo,
     Form(input): Form<CreateUser>,
 ) -> Result<impl IntoResponse, AppError> {
     input.validate()?;
 
+    match throttle::limit_registration(&state.db, client.ip_address.as_deref()).await {
+        Ok(()) => {}
+        Err(AppError::RateLimited(_)) => {
+            let template = RegisterTemplate::new(Some(
+                "Too many accounts have been created from your network recently. Please try again later.".to_string()
+            ));
+            return Ok((StatusCode::TOO_MANY_REQUESTS, Html(template.render()?)).into_response());
+        }
+        Err(error) => return Err(error),
+    }
+
     let existing_user = query!(
         "SELECT id FROM users WHERE email = $1",
         input.email
@@ -51,12 +170,13 @@ pub async fn register(
         return Ok(Html(template.render()?).into_response());
     }
 
-    let password_hash = hash_password(&input.password)?;
+    let password_hash = hash_password(&input.password, state.config.bcrypt_cost)?;
+    let verification_required = verification_required(&state.db).await?;
 
     let user = query_as::<_, User>(
         r#"
-        INSERT INTO users (name, display_name, email, password_hash)
-        VALUES ($1, $2, $3, $4)
+        INSERT INTO users (name, display_name, email, password_hash, email_verified_at)
+        VALUES ($1, $2, $3, $4, $5)
         RETURNING *
         "#
     )
@@ -64,40 +184,49 @@ pub async fn register(
     .bind(&input.display_name)
     .bind(&input.email)
     .bind(&password_hash)
+    .bind((!verification_required).then(Utc::now))
     .fetch_one(&state.db)
     .await?;
 
-    let token = Claims::new(&user, &state.config.jwt_secret)?;
-
-    let cookie = Cookie::build(("auth_token", token))
-        .path("/")
-        .http_only(true)
-        .build();
+    // The account stays pending, and can't sign in, until the link is clicked
+    if verification_required {
+        send_verification(&state, &user).await?;
+        let template = VerifyEmailTemplate::new(
+            user.email,
+            Some("We've sent you a link to confirm your email address.".to_string()),
+            None,
+        );
+        return Ok(Html(template.render()?).into_response());
+    }
 
-    Ok((CookieJar::new().add(cookie), Redirect::to("/dashboard")))
+    sign_in(&state, &user, &client, "registration").await
 }
 
 pub async fn login_form(
-    State(_state): State<AppState>,
+    State(state): State<AppState>,
     auth_user: OptionalAuthUser
 ) -> Result<impl IntoResponse, AppError> {
     if auth_user.user.is_some() {
         return Ok(Redirect::to("/dashboard").into_response())
     }
 
-    let template = LoginTemplate {
-        error: None,
-    };
+    let template = login_page(&state, None);
 
     Ok(Html(template.render()?).into_response())
 }
 
 pub async fn login(
     State(state): State<AppState>,
+    client: ClientInfo,
     Form(input): Form<LoginUser>,
 ) -> Result<impl IntoResponse, AppError> {
     input.validate()?;
 
+    let keys = LoginKeys::new(client.ip_address.as_deref(), &input.email);
+    if let Some(response) = check_login_throttle(&state, &keys).await? {
+        return Ok(response);
+    }
+
     let user = query_as::<_, User>(
         "SELECT * FROM users WHERE email = $1"
     )
@@ -105,38 +234,341 @@ pub async fn login(
     .fetch_optional(&state.db)
     .await?;
 
-    let user = match user {
-        Some(user) => user,
-        None => {
-            let template = LoginTemplate {
-                error: Some("Invalid email or password".to_string()),
-            };
-            return Ok(Html(template.render()?));
+    let failure = match &user {
+        // The address typed isn't kept, as the audit log outlives accounts
+        None => Some(json!({ "reason": "unknown_email" })),
+        Some(user) if !verify_password(&input.password, &user.password_hash)? => {
+            Some(json!({ "user_id": user.id, "reason": "wrong_password" }))
         }
+        Some(_) => None,
+    };
+
+    if let Some(details) = failure {
+        if let Some(response) = record_failed_login(&state, &keys, &client, audit::LOGIN_FAILED, details).await? {
+            return Ok(response);
+        }
+
+        let template = login_page(&state, Some("Invalid email or password".to_string()));
+        return Ok(Html(template.render()?).into_response());
+    }
+
+    let user = user.ok_or(AppError::InvalidCredentials)?;
+    throttle::clear_login_failures(&state.db, &keys).await?;
+
+    if user.suspended_at.is_some() {
+        return suspended();
+    }
+
+    if user.email_verified_at.is_none() {
+        let template = VerifyEmailTemplate::new(
+            user.email,
+            None,
+            Some("Please confirm your email address before signing in.".to_string()),
+        );
+        return Ok(Html(template.render()?).into_response());
+    }
+
+    sign_in(&state, &user, &client, "password").await
+}
+
+pub async fn two_factor_form(
+    State(state): State<AppState>,
+    jar: CookieJar,
+) -> Result<impl IntoResponse, AppError> {
+    let pending = jar
+        .get(two_factor::PENDING_LOGIN_COOKIE)
+        .and_then(|cookie| two_factor::pending_login_user(cookie.value(), &state.config.jwt_secret).ok());
+
+    if pending.is_none() {
+        return Ok(Redirect::to("/login").into_response());
+    }
+
+    Ok(Html(TwoFactorLoginTemplate::new(None).render()?).into_response())
+}
+
+pub async fn two_factor_login(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    jar: CookieJar,
+    Form(input): Form<TwoFactorCode>,
+) -> Result<impl IntoResponse, AppError> {
+    let pending = jar
+        .get(two_factor::PENDING_LOGIN_COOKIE)
+        .and_then(|cookie| two_factor::pending_login_user(cookie.value(), &state.config.jwt_secret).ok());
+
+    let Some(user_id) = pending else {
+        let template = login_page(&state, Some("That took too long. Please sign in again.".to_string()));
+        return Ok(Html(template.render()?).into_response());
+    };
+
+    let keys = LoginKeys::two_factor(client.ip_address.as_deref(), user_id);
+    if let Some(response) = check_login_throttle(&state, &keys).await? {
+        return Ok(response);
+    }
+
+    match two_factor::verify_user_code(&state.db, user_id, &input.code).await {
+        Ok(()) => throttle::clear_login_failures(&state.db, &keys).await?,
+        Err(AppError::InvalidTwoFactorCode) => {
+            let details = json!({ "user_id": user_id });
+            if let Some(response) = record_failed_login(&state, &keys, &client, audit::TWO_FACTOR_FAILED, details).await? {
+                return Ok(response);
+            }
+
+            let template = TwoFactorLoginTemplate::new(Some("That code didn't work. Please try again.".to_string()));
+            return Ok(Html(template.render()?).into_response());
+        }
+        Err(error) => return Err(error),
+    }
+
+    let user = query_as::<_, User>(
+        "SELECT * FROM users WHERE id = $1"
+    )
+    .bind(user_id)
+    .fetch_optional(&state.db)
+    .await?
+    .ok_or(AppError::UserNotFound)?;
+
+    let token = start_session(&state, &user, &client).await?;
+    record_sign_in(&state, &user, &client, "two_factor").await?;
+    let jar = CookieJar::new()
+        .add(two_factor::clear_pending_login_cookie())
+        .add(session_cookie(token, &state.config));
+
+    Ok((jar, Redirect::to("/dashboard")).into_response())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OidcCallback {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    /// Set instead of `code` when the provider refused, e.g. `access_denied`.
+    pub error: Option<String>,
+}
+
+/// Sends the player to the identity provider to sign in.
+pub async fn oidc_login(State(state): State<AppState>) -> Result<impl IntoResponse, AppError> {
+    let config = state.config.oidc.as_ref().ok_or(AppError::NotFound)?;
+
+    let http = oidc::http_client()?;
+    let provider = oidc::discover(&http, &config.issuer_url).await?;
+    let pending = PendingLogin::new();
+    let url = oidc::authorization_url(&provider, config, &oidc::redirect_uri(&state.config.app_url), &pending)?;
+
+    let jar = CookieJar::new().add(oidc::pending_login_cookie(
+        pending.to_token(&state.config.jwt_secret)?,
+        &state.config.cookies,
+    ));
+
+    Ok((jar, Redirect::to(&url)))
+}
+
+/// Where the identity provider sends the player back, with a code to trade
+/// for their identity.
+pub async fn oidc_callback(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    jar: CookieJar,
+    Query(params): Query<OidcCallback>,
+) -> Result<impl IntoResponse, AppError> {
+    let config = state.config.oidc.as_ref().ok_or(AppError::NotFound)?;
+    let clear = CookieJar::new().add(oidc::clear_pending_login_cookie());
+
+    let pending = jar
+        .get(oidc::PENDING_LOGIN_COOKIE)
+        .and_then(|cookie| PendingLogin::from_token(cookie.value(), &state.config.jwt_secret).ok());
+
+    // The state must match the login this browser started, or anyone could
+    // sign a victim in to the attacker's account with their own code
+    let code = match (pending, params.code, params.state) {
+        (Some(pending), Some(code), Some(returned)) if returned == pending.state => Some((pending, code)),
+        _ => None,
     };
 
-    if !verify_password(&input.password, &user.password_hash)? {
-        let template = LoginTemplate {
-            error: Some("Invalid email or password".to_string()),
+    let Some((pending, code)) = code else {
+        let error = match params.error {
+            Some(_) => format!("Signing in with {} was cancelled.", config.provider_name),
+            None => "That took too long. Please sign in again.".to_string(),
         };
+        let template = login_page(&state, Some(error));
+        return Ok((clear, Html(template.render()?)).into_response());
+    };
+
+    let http = oidc::http_client()?;
+    let redirect_uri = oidc::redirect_uri(&state.config.app_url);
+    let claims = match oidc::discover(&http, &config.issuer_url).await {
+        Ok(provider) => oidc::exchange_code(&http, &provider, config, &redirect_uri, &code, &pending).await,
+        Err(error) => Err(error),
+    };
+
+    // A new or newly linked account is committed along with its audit entry
+    let mut tx = state.db.begin().await?;
+    let signed_on = match claims {
+        Ok(claims) => oidc::find_or_link_user(&mut tx, &claims).await,
+        Err(error) => Err(error),
+    };
+
+    let account = match signed_on {
+        Ok(account) => account,
+        Err(AppError::SingleSignOn(reason)) => {
+            let ip_address = client.ip_address.as_deref();
+            tracing::warn!("single sign-on failed: {}", reason);
+            metrics::login_failed("sso");
+            audit::record(&state.db, audit::SSO_FAILED, None, ip_address, json!({ "reason": reason })).await?;
+
+            // The reason can describe our accounts, so the player only hears that it failed
+            let error = format!(
+                "Signing in with {} didn't work. Please try again or sign in with your password.",
+                config.provider_name
+            );
+            let template = login_page(&state, Some(error));
+            return Ok((clear, Html(template.render()?)).into_response());
+        }
+        Err(error) => return Err(error),
+    };
+
+    let user = account.user();
+    let event = match &account {
+        SignOnAccount::Existing(_) => None,
+        SignOnAccount::Linked(_) => Some(audit::SSO_LINKED),
+        SignOnAccount::Created(_) => Some(audit::SSO_REGISTERED),
+    };
+    if let Some(event) = event {
+        audit::record(&mut *tx, event, Some(user.id), client.ip_address.as_deref(), json!({
+            "issuer": config.issuer_url,
+        }))
+        .await?;
+    }
+    tx.commit().await?;
+
+    if user.suspended_at.is_some() {
+        return Ok((clear, suspended()?).into_response());
+    }
+
+    let response = sign_in(&state, user, &client, "sso").await?;
+
+    Ok((clear, response).into_response())
+}
+
+pub async fn logout(
+    State(state): State<AppState>,
+    jar: CookieJar,
+) -> Result<impl IntoResponse, AppError> {
+    let claims = jar
+        .get(SESSION_COOKIE)
+        .and_then(|cookie| Claims::from_token(cookie.value(), &state.config.jwt_secret).ok());
+
+    if let Some(claims) = claims {
+        revoke_session(&state.db, claims.sub, claims.sid).await?;
+    }
+
+    Ok((CookieJar::new().add(clear_session_cookie()), Redirect::to("/")))
+}
+
+pub async fn forgot_password_form() -> Result<impl IntoResponse, AppError> {
+    Ok(Html(ForgotPasswordTemplate::new(false, None).render()?))
+}
+
+pub async fn forgot_password(
+    State(state): State<AppState>,
+    Form(input): Form<ForgotPassword>,
+) -> Result<impl IntoResponse, AppError> {
+    if input.validate().is_err() {
+        let template = ForgotPasswordTemplate::new(false, Some("Please enter a valid email address".to_string()));
         return Ok(Html(template.render()?));
     }
 
-    let token = Claims::new(&user, &state.config.jwt_secret)?;
+    request_reset(&state, input.email.trim());
+
+    Ok(Html(ForgotPasswordTemplate::new(true, None).render()?))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResetQuery {
+    pub token: String,
+}
+
+pub async fn reset_password_form(
+    Query(params): Query<ResetQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    Ok(Html(ResetPasswordTemplate::new(params.token, None).render()?))
+}
+
+pub async fn reset_password(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    Form(input): Form<ResetPassword>,
+) -> Result<impl IntoResponse, AppError> {
+    if input.validate().is_err() {
+        let template = ResetPasswordTemplate::new(
+            input.token,
+            Some("Passwords must match and be at least 8 characters".to_string()),
+        );
+        return Ok(Html(template.render()?).into_response());
+    }
+
+    let user = match reset_user_password(&state, &input.token, &input.password).await {
+        Ok(user) => user,
+        Err(AppError::InvalidResetToken) => {
+            let template = ResetPasswordTemplate::new(
+                input.token,
+                Some("This reset link is invalid, has already been used or has expired.".to_string()),
+            );
+            return Ok(Html(template.render()?).into_response());
+        }
+        Err(error) => return Err(error),
+    };
+
+    sign_in(&state, &user, &client, "password_reset").await
+}
 
-    let cookie = Cookie::build(("auth_token", token))
-        .path("/")
-        .http_only(true)
-        .build();
+#[derive(Debug, Deserialize)]
+pub struct VerifyToken {
+    pub token: String,
+}
 
-    Ok((CookieJar::new().add(cookie), Redirect::to("/dashboard")))
+pub async fn verify_email_form(
+    Query(params): Query<VerifyToken>,
+) -> Result<impl IntoResponse, AppError> {
+    Ok(Html(ConfirmEmailTemplate::new(params.token).render()?))
 }
 
-pub async fn logout() -> impl IntoResponse {
-    let cookie = Cookie::build(("auth_token", ""))
-        .path("/")
-        .max_age(Duration::seconds(0))
-        .build();
+pub async fn verify_email(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    Form(params): Form<VerifyToken>,
+) -> Result<impl IntoResponse, AppError> {
+    let user = match confirm_email(&state.db, &params.token).await {
+        Ok(user) => user,
+        Err(AppError::InvalidVerificationToken) => {
+            let template = VerifyEmailTemplate::new(
+                String::new(),
+                None,
+                Some("This confirmation link is invalid, has already been used or has expired.".to_string()),
+            );
+            return Ok(Html(template.render()?).into_response());
+        }
+        Err(error) => return Err(error),
+    };
 
-    (CookieJar::new().add(cookie), Redirect::to("/"))
-}
\ No newline at end of file
+    sign_in(&state, &user, &client, "email_verification").await
+}
+
+pub async fn resend_verification_email(
+    State(state): State<AppState>,
+    Form(input): Form<ResendVerification>,
+) -> Result<impl IntoResponse, AppError> {
+    if input.validate().is_err() {
+        let template = VerifyEmailTemplate::new(input.email, None, Some("Please enter a valid email address".to_string()));
+        return Ok(Html(template.render()?));
+    }
+
+    resend_verification(&state, input.email.trim()).await?;
+
+    let template = VerifyEmailTemplate::new(
+        input.email,
+        Some("If that account is waiting for confirmation, we've sent it a new link.".to_string()),
+        None,
+    );
+
+    Ok(Html(template.render()?))
+}
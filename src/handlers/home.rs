@@ -5,6 +5,7 @@ use axum::extract::State;
 use axum::response::{Html, IntoResponse};
 use sqlx::query;
 use crate::AppState;
+use crate::announcements;
 use crate::auth::OptionalAuthUser;
 use crate::errors::AppError;
 use crate::templates::home::{CurrentGameweek, HomeTemplate, TopPlayer};
@@ -31,6 +32,8 @@ pub async fn index(
         FROM season_scores ss
         JOIN users u ON ss.user_id = u.id
         WHERE ss.season = (SELECT season FROM gameweeks WHERE is_active = true LIMIT 1)
+          AND u.email_verified_at IS NOT NULL
+          AND u.suspended_at IS NULL
         ORDER BY ss.total_points DESC, ss.total_exact_scores DESC
         LIMIT 5
         "#
@@ -45,11 +48,9 @@ pub async fn index(
         season: row.season,
     }).collect();
 
-    let template = HomeTemplate {
-        user: auth_user.user.as_ref(),
-        current_gameweek,
-        top_players,
-    };
+    let announcements = announcements::current(&state.db, auth_user.user.as_ref().map(|user| user.id)).await?;
+
+    let template = HomeTemplate::new(auth_user.user.as_ref(), current_gameweek, top_players, announcements);
 
     Ok(Html(template.render()?))
 }
\ No newline at end of file
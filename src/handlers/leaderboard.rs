@@ -1,67 +1,91 @@
 // handlers/leaderboard.rs
 
+use std::collections::HashMap;
 use askama::Template;
-use axum::extract::State;
+use axum::extract::{Query, State};
 use axum::response::{Html, IntoResponse};
-use chrono::Utc;
+use serde::Deserialize;
 use sqlx::query;
 use crate::AppState;
 use crate::auth::OptionalAuthUser;
 use crate::errors::AppError;
-use crate::models::UserWithScore;
-use crate::templates::leaderboard::{SeasonLeaderboardTemplate, WeeklyLeaderboardTemplate};
+use crate::prizes::roll_of_honour;
+use crate::queries::{active_gameweek, current_season, gameweek_by_week, season_leaderboard, weekly_leaderboard};
+use crate::templates::leaderboard::{
+    FormWeek, RollOfHonourTemplate, SeasonLeaderboardTemplate, SeasonStanding, WeeklyLeaderboardTemplate,
+    FORM_GUIDE_WEEKS
+};
 
 pub async fn season(
     State(state): State<AppState>,
     auth_user: OptionalAuthUser,
 ) -> Result<impl IntoResponse, AppError> {
-    // Get current season
-    let current_season = query!(
-        "SELECT season FROM gameweeks WHERE is_active = true LIMIT 1"
-    )
-        .fetch_optional(&state.db)
-        .await?;
-
-    let season = current_season
-        .map(|s| s.season)
-        .unwrap_or_else(|| "2024-25".to_string());
+    let season = current_season(&state.db).await?;
+    let leaderboard = season_leaderboard(&state.db, &season).await?;
 
-    // Get season leaderboard
-    let leaderboard_data = query!(
+    // Positions from the snapshot before the most recent one, for movement arrows
+    let previous_positions: HashMap<_, _> = query!(
         r#"
-        SELECT
-            u.id, u.name, u.display_name, u.email, u.password_hash, u.is_admin, u.created_at, u.updated_at,
-            COALESCE(ss.total_points, 0) as total_points,
-            COALESCE(ss.total_exact_scores, 0) as exact_scores,
-            COALESCE(ss.total_correct_results, 0) as correct_results,
-            ROW_NUMBER() OVER (ORDER BY COALESCE(ss.total_points, 0) DESC, COALESCE(ss.total_exact_scores, 0) DESC) as position
-        FROM users u
-        LEFT JOIN season_scores ss ON u.id = ss.user_id AND ss.season = $1
-        WHERE u.is_admin = false
-        ORDER BY total_points DESC, exact_scores DESC, u.display_name ASC
+        SELECT sp.user_id, sp.position
+        FROM season_positions sp
+        WHERE sp.season = $1
+        AND sp.week_number = (
+            SELECT MAX(week_number) FROM season_positions
+            WHERE season = $1
+            AND week_number < (SELECT MAX(week_number) FROM season_positions WHERE season = $1)
+        )
         "#,
         season
+    )
+        .fetch_all(&state.db)
+        .await?
+        .into_iter()
+        .map(|row| (row.user_id, row.position))
+        .collect();
+
+    // Last few completed gameweeks, oldest first, for the form guide
+    let mut form_weeks = query!(
+        r#"
+        SELECT id, week_number
+        FROM gameweeks
+        WHERE season = $1 AND is_completed = true
+        ORDER BY week_number DESC
+        LIMIT $2
+        "#,
+        season,
+        FORM_GUIDE_WEEKS
     )
         .fetch_all(&state.db)
         .await?;
+    form_weeks.reverse();
 
-    let leaderboard: Vec<UserWithScore> = leaderboard_data
+    let form_week_ids: Vec<_> = form_weeks.iter().map(|gw| gw.id).collect();
+    let form_points: HashMap<_, _> = query!(
+        "SELECT user_id, gameweek_id, total_points FROM gameweek_scores WHERE gameweek_id = ANY($1)",
+        &form_week_ids
+    )
+        .fetch_all(&state.db)
+        .await?
         .into_iter()
-        .map(|row| UserWithScore {
-            user: crate::models::User {
-                id: row.id,
-                name: row.name,
-                display_name: row.display_name,
-                email: row.email,
-                password_hash: row.password_hash,
-                is_admin: row.is_admin.unwrap_or(false),
-                created_at: row.created_at.unwrap_or(Utc::now()),
-                updated_at: row.updated_at.unwrap_or(Utc::now()),
-            },
-            score: row.total_points.unwrap_or(0),
-            exact_scores: row.exact_scores.unwrap_or(0),
-            correct_results: row.correct_results.unwrap_or(0),
-            position: row.position.unwrap_or(0) as i32,
+        .map(|row| ((row.user_id, row.gameweek_id), row.total_points.unwrap_or(0)))
+        .collect();
+
+    let leaderboard: Vec<SeasonStanding> = leaderboard
+        .into_iter()
+        .map(|player| {
+            let movement = previous_positions
+                .get(&player.user.id)
+                .map(|previous| previous - player.position);
+
+            let form = form_weeks
+                .iter()
+                .map(|gw| FormWeek {
+                    week_number: gw.week_number,
+                    points: form_points.get(&(player.user.id, gw.id)).copied(),
+                })
+                .collect();
+
+            SeasonStanding { player, movement, form }
         })
         .collect();
 
@@ -74,77 +98,105 @@ pub async fn season(
     Ok(Html(template.render()?))
 }
 
+#[derive(Debug, Deserialize)]
+pub struct WeeklyQuery {
+    /// A past gameweek to show instead of the active one.
+    pub week: Option<i32>,
+    /// The past gameweek's season, defaulting to the current one.
+    pub season: Option<String>,
+}
+
 pub async fn weekly(
     State(state): State<AppState>,
     auth_user: OptionalAuthUser,
+    Query(params): Query<WeeklyQuery>,
 ) -> Result<impl IntoResponse, AppError> {
-    // Get current active gameweek
-    let current_gameweek = query!(
-        "SELECT id, week_number, season FROM gameweeks WHERE is_active = true LIMIT 1"
-    )
-        .fetch_optional(&state.db)
-        .await?;
+    let gameweek = match params.week {
+        Some(week_number) => {
+            let season = match params.season {
+                Some(season) => season,
+                None => current_season(&state.db).await?,
+            };
+            gameweek_by_week(&state.db, &season, week_number).await?
+        }
+        None => active_gameweek(&state.db).await?,
+    };
 
-    let (gameweek_id, week_number, season) = match current_gameweek {
-        Some(gw) => (gw.id, gw.week_number, gw.season),
+    let (gameweek_id, week_number, season, note) = match gameweek {
+        Some(gw) => (gw.id, gw.week_number, gw.season, gw.note),
         None => {
-            let template = WeeklyLeaderboardTemplate {
-                user: auth_user.user.as_ref(),
-                week_number: 0,
-                season: "No active gameweek",
-                leaderboard: vec![],
-                error: Some("No active gameweek found".to_string()),
+            let error = match params.week {
+                Some(week_number) => format!("Gameweek {} wasn't found", week_number),
+                None => "No active gameweek found".to_string(),
             };
+            let template = WeeklyLeaderboardTemplate::new(
+                auth_user.user.as_ref(),
+                0,
+                "No active gameweek",
+                vec![],
+                Some(error),
+                None,
+            );
             return Ok(Html(template.render()?));
         }
     };
 
-    // Get weekly leaderboard
-    let leaderboard_data = query!(
-        r#"
-        SELECT
-            u.id, u.name, u.display_name, u.email, u.password_hash, u.is_admin, u.created_at, u.updated_at,
-            COALESCE(gs.total_points, 0) as total_points,
-            COALESCE(gs.exact_scores, 0) as exact_scores,
-            COALESCE(gs.correct_results, 0) as correct_results,
-            ROW_NUMBER() OVER (ORDER BY COALESCE(gs.total_points, 0) DESC, COALESCE(gs.exact_scores, 0) DESC) as position
-        FROM users u
-        LEFT JOIN gameweek_scores gs ON u.id = gs.user_id AND gs.gameweek_id = $1
-        WHERE u.is_admin = false
-        ORDER BY total_points DESC, exact_scores DESC, u.display_name ASC
-        "#,
-        gameweek_id
+    let leaderboard = weekly_leaderboard(&state.db, gameweek_id).await?;
+
+    let template = WeeklyLeaderboardTemplate::new(auth_user.user.as_ref(), week_number, &season, leaderboard, None, note);
+
+    Ok(Html(template.render()?))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HonoursQuery {
+    pub season: Option<String>,
+}
+
+pub async fn honours(
+    State(state): State<AppState>,
+    auth_user: OptionalAuthUser,
+    Query(params): Query<HonoursQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    let seasons: Vec<String> = query!(
+        "SELECT DISTINCT season FROM gameweeks WHERE is_completed = true ORDER BY season DESC"
     )
         .fetch_all(&state.db)
-        .await?;
-
-    let leaderboard: Vec<UserWithScore> = leaderboard_data
+        .await?
         .into_iter()
-        .map(|row| UserWithScore {
-            user: crate::models::User {
-                id: row.id,
-                name: row.name,
-                display_name: row.display_name,
-                email: row.email,
-                password_hash: row.password_hash,
-                is_admin: row.is_admin.unwrap_or(false),
-                created_at: row.created_at.unwrap_or(Utc::now()),
-                updated_at: row.updated_at.unwrap_or(Utc::now()),
-            },
-            score: row.total_points.unwrap_or(0),
-            exact_scores: row.exact_scores.unwrap_or(0),
-            correct_results: row.correct_results.unwrap_or(0),
-            position: row.position.unwrap_or(0) as i32,
-        })
+        .map(|row| row.season)
         .collect();
 
-    let template = WeeklyLeaderboardTemplate {
-        user: auth_user.user.as_ref(),
-        week_number,
-        season: &season,
-        leaderboard,
-        error: None,
+    let season = match params.season {
+        Some(season) => season,
+        None => match seasons.first() {
+            Some(latest) => latest.clone(),
+            None => {
+                let template = RollOfHonourTemplate::new(
+                    auth_user.user.as_ref(),
+                    None,
+                    seasons,
+                    Some("No gameweeks have been completed yet".to_string()),
+                );
+                return Ok(Html(template.render()?));
+            }
+        },
     };
 
+    let honours = roll_of_honour(
+        &state.db,
+        &season,
+        &state.config.prize_months,
+        state.config.prize_tie_split,
+    )
+    .await?;
+
+    let template = RollOfHonourTemplate::new(
+        auth_user.user.as_ref(),
+        Some(honours),
+        seasons,
+        None,
+    );
+
     Ok(Html(template.render()?))
-}
\ No newline at end of file
+}
@@ -0,0 +1,187 @@
+// handlers/ledger.rs
+
+use std::collections::HashSet;
+use askama::Template;
+use axum::extract::{Query, State};
+use axum::http::header;
+use axum::response::{Html, IntoResponse, Redirect};
+use axum::Form;
+use serde::Deserialize;
+use sqlx::query;
+use validator::Validate;
+use crate::AppState;
+use crate::auth::AdminUser;
+use crate::errors::AppError;
+use crate::ledger::{
+    derive_payouts, ledger_csv, ledger_rows, parse_pounds, player_balances, pot_pence, record_entry,
+    DerivedPayout, LedgerKind, LedgerRow, NewLedgerEntry, PlayerBalance
+};
+use crate::models::CreateLedgerEntry;
+use crate::prizes::roll_of_honour;
+use crate::roles::Permission;
+use crate::templates::admin::LedgerTemplate;
+
+#[derive(Debug, Deserialize)]
+pub struct LedgerQuery {
+    pub season: Option<String>,
+}
+
+async fn resolve_season(state: &AppState, requested: Option<String>) -> Result<String, AppError> {
+    if let Some(season) = requested {
+        return Ok(season);
+    }
+
+    let season = query!(
+        "SELECT season FROM gameweeks ORDER BY is_active DESC, season DESC LIMIT 1"
+    )
+        .fetch_optional(&state.db)
+        .await?
+        .map(|row| row.season)
+        .unwrap_or_else(|| "2024-25".to_string());
+
+    Ok(season)
+}
+
+fn ledger_url(season: &str) -> Result<String, AppError> {
+    if !season.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '/') {
+        return Err(AppError::InvalidLedgerEntry);
+    }
+
+    Ok(format!("/admin/ledger?season={}", season))
+}
+
+/// Balances, entries and any derived prize payouts not yet in the ledger.
+async fn season_ledger(
+    state: &AppState,
+    season: &str,
+) -> Result<(Vec<PlayerBalance>, Vec<LedgerRow>, Vec<DerivedPayout>), AppError> {
+    let balances = player_balances(&state.db, season).await?;
+    let entries = ledger_rows(&state.db, season).await?;
+
+    let honours = roll_of_honour(
+        &state.db,
+        season,
+        &state.config.prize_months,
+        state.config.prize_tie_split,
+    )
+    .await?;
+
+    let recorded: HashSet<_> = entries
+        .iter()
+        .filter(|e| e.kind == LedgerKind::Payout.as_str())
+        .filter_map(|e| e.reference.as_ref().map(|r| (e.user_id, r.clone())))
+        .collect();
+
+    let outstanding = derive_payouts(
+        &honours,
+        pot_pence(&balances),
+        &state.config.pot_split,
+        &state.config.prize_months,
+    )
+        .into_iter()
+        .filter(|p| !recorded.contains(&(p.user_id, p.reference.clone())))
+        .collect();
+
+    Ok((balances, entries, outstanding))
+}
+
+pub async fn ledger(
+    State(state): State<AppState>,
+    admin_user: AdminUser,
+    Query(params): Query<LedgerQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    admin_user.require(Permission::ModerateLeague)?;
+
+    let season = resolve_season(&state, params.season).await?;
+    let (balances, entries, outstanding) = season_ledger(&state, &season).await?;
+
+    let template = LedgerTemplate::new(
+        &admin_user.user,
+        season,
+        state.config.entry_fee_pence,
+        balances,
+        entries,
+        outstanding,
+    );
+
+    Ok(Html(template.render()?))
+}
+
+pub async fn create_entry(
+    State(state): State<AppState>,
+    admin_user: AdminUser,
+    Form(input): Form<CreateLedgerEntry>,
+) -> Result<impl IntoResponse, AppError> {
+    admin_user.require(Permission::ModerateLeague)?;
+
+    input.validate()?;
+
+    let kind: LedgerKind = input.kind.parse()?;
+    let amount_pence = parse_pounds(&input.amount)
+        .filter(|amount| *amount > 0)
+        .ok_or(AppError::InvalidLedgerEntry)?;
+    let note = input.note.as_deref().map(str::trim).filter(|n| !n.is_empty());
+    let redirect = ledger_url(&input.season)?;
+
+    record_entry(&state.db, NewLedgerEntry {
+        user_id: input.user_id,
+        season: &input.season,
+        kind,
+        amount_pence,
+        reference: None,
+        note,
+        recorded_by: admin_user.user.id,
+    })
+    .await?;
+
+    Ok(Redirect::to(&redirect))
+}
+
+pub async fn record_payouts(
+    State(state): State<AppState>,
+    admin_user: AdminUser,
+    Form(params): Form<LedgerQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    admin_user.require(Permission::ModerateLeague)?;
+
+    let season = resolve_season(&state, params.season).await?;
+    let redirect = ledger_url(&season)?;
+    let (_, _, outstanding) = season_ledger(&state, &season).await?;
+
+    for payout in &outstanding {
+        record_entry(&state.db, NewLedgerEntry {
+            user_id: payout.user_id,
+            season: &season,
+            kind: LedgerKind::Payout,
+            amount_pence: payout.amount_pence,
+            reference: Some(&payout.reference),
+            note: Some(&payout.label),
+            recorded_by: admin_user.user.id,
+        })
+        .await?;
+    }
+
+    Ok(Redirect::to(&redirect))
+}
+
+pub async fn export_csv(
+    State(state): State<AppState>,
+    admin_user: AdminUser,
+    Query(params): Query<LedgerQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    admin_user.require(Permission::ModerateLeague)?;
+
+    let season = resolve_season(&state, params.season).await?;
+    ledger_url(&season)?;
+
+    let entries = ledger_rows(&state.db, &season).await?;
+    let csv = ledger_csv(&season, &entries);
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/csv; charset=utf-8".to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"ledger-{}.csv\"", season.replace('/', "-"))),
+        ],
+        csv,
+    ))
+}
@@ -0,0 +1,83 @@
+// handlers/metrics.rs
+//
+// The Prometheus scrape endpoint. Open unless `METRICS_TOKEN` is set, in
+// which case the scraper sends it as a bearer token. Production refuses to
+// start without one.
+
+use axum::extract::State;
+use axum::http::header::{AUTHORIZATION, CONTENT_TYPE};
+use axum::http::HeaderMap;
+use axum::response::IntoResponse;
+use crate::AppState;
+use crate::api_tokens::hash_token;
+use crate::errors::AppError;
+use crate::metrics;
+
+/// Lets the request through if it carries `expected` as a bearer token, or
+/// if there's no token to check.
+fn check_token(expected: Option<&str>, headers: &HeaderMap) -> Result<(), AppError> {
+    let Some(expected) = expected else {
+        return Ok(());
+    };
+
+    let submitted = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .ok_or(AppError::MissingToken)?;
+
+    // Hashed first so the comparison takes the same time however close
+    // the guess was
+    if hash_token(submitted.trim()) != hash_token(expected) {
+        return Err(AppError::InvalidToken);
+    }
+
+    Ok(())
+}
+
+pub async fn metrics(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, AppError> {
+    check_token(state.config.metrics_token.as_deref(), &headers)?;
+
+    let body = metrics::render(&state.metrics, &state.db).await?;
+
+    Ok(([(CONTENT_TYPE, "text/plain; version=0.0.4")], body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use metrics_exporter_prometheus::PrometheusBuilder;
+
+    fn bearer(token: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, format!("Bearer {}", token).parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_check_token() {
+        assert!(check_token(None, &HeaderMap::new()).is_ok());
+        assert!(matches!(check_token(Some("scraper"), &HeaderMap::new()), Err(AppError::MissingToken)));
+        assert!(matches!(check_token(Some("scraper"), &bearer("scrapes")), Err(AppError::InvalidToken)));
+        assert!(check_token(Some("scraper"), &bearer("scraper")).is_ok());
+    }
+
+    #[test]
+    fn test_render_login_failures() {
+        let recorder = PrometheusBuilder::new().build_recorder();
+        let handle = recorder.handle();
+
+        ::metrics::with_local_recorder(&recorder, || {
+            metrics::login_failed("sso");
+            metrics::login_failed("sso");
+            metrics::login_failed("password");
+        });
+
+        let body = handle.render();
+        assert!(body.contains("superior6_login_failures_total{step=\"sso\"} 2"));
+        assert!(body.contains("superior6_login_failures_total{step=\"password\"} 1"));
+    }
+}
@@ -3,7 +3,15 @@
 pub mod home;
 pub mod auth;
 pub mod user;
+pub mod account;
 pub mod admin;
+pub mod ledger;
+pub mod roles;
+pub mod audit;
+pub mod announcements;
+pub mod user_admin;
 // pub mod fixtures;
 pub mod leaderboard;
-pub mod predictions;
\ No newline at end of file
+pub mod predictions;
+pub mod api;
+pub mod metrics;
\ No newline at end of file
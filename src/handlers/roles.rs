@@ -0,0 +1,122 @@
+// handlers/roles.rs
+
+use askama::Template;
+use axum::extract::State;
+use axum::response::{Html, IntoResponse, Redirect, Response};
+use axum::Form;
+use serde_json::json;
+use sqlx::query;
+use validator::Validate;
+use crate::AppState;
+use crate::audit;
+use crate::auth::AdminUser;
+use crate::errors::AppError;
+use crate::models::{GrantRole, RevokeRole};
+use crate::roles::{self, Permission, Role};
+use crate::sessions::ClientInfo;
+use crate::templates::admin::RolesTemplate;
+
+async fn render(state: &AppState, admin_user: &AdminUser, error: Option<String>) -> Result<Response, AppError> {
+    let staff = roles::staff(&state.db).await?;
+    let template = RolesTemplate::new(&admin_user.user, staff, error);
+
+    Ok(Html(template.render()?).into_response())
+}
+
+/// Only ever sends the admin back to another admin page.
+fn return_to(path: Option<&str>) -> &str {
+    match path {
+        Some(path) if path.starts_with("/admin/") && !path.contains("//") => path,
+        _ => "/admin/roles",
+    }
+}
+
+fn role_names(roles: &[Role]) -> Vec<&'static str> {
+    roles.iter().map(Role::as_str).collect()
+}
+
+fn parse_role(role: &str) -> Result<Role, AppError> {
+    role.parse().map_err(AppError::InvalidRequestBody)
+}
+
+pub async fn roles(
+    State(state): State<AppState>,
+    admin_user: AdminUser,
+) -> Result<impl IntoResponse, AppError> {
+    admin_user.require(Permission::ManageRoles)?;
+
+    render(&state, &admin_user, None).await
+}
+
+pub async fn grant_role(
+    State(state): State<AppState>,
+    admin_user: AdminUser,
+    client: ClientInfo,
+    Form(input): Form<GrantRole>,
+) -> Result<impl IntoResponse, AppError> {
+    admin_user.require(Permission::ManageRoles)?;
+
+    if input.validate().is_err() {
+        return render(&state, &admin_user, Some("Please enter a valid email address".to_string())).await;
+    }
+    let role = parse_role(&input.role)?;
+
+    let user = query!("SELECT id FROM users WHERE LOWER(email) = LOWER($1)", input.email.trim())
+        .fetch_optional(&state.db)
+        .await?;
+
+    let Some(user) = user else {
+        return render(&state, &admin_user, Some(format!("Nobody has registered as {}", input.email.trim()))).await;
+    };
+
+    let mut tx = state.db.begin().await?;
+    let before = roles::user_roles(&mut *tx, user.id).await?;
+    if roles::grant_role(&mut tx, user.id, role, Some(admin_user.user.id)).await? {
+        let after = roles::user_roles(&mut *tx, user.id).await?;
+        audit::Event::new(audit::ROLE_GRANTED)
+            .by(admin_user.user.id, &client)
+            .details(json!({ "user_id": user.id, "role": role.as_str() }))
+            .change(role_names(&before), role_names(&after))
+            .record(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+
+    Ok(Redirect::to(return_to(input.return_to.as_deref())).into_response())
+}
+
+pub async fn revoke_role(
+    State(state): State<AppState>,
+    admin_user: AdminUser,
+    client: ClientInfo,
+    Form(input): Form<RevokeRole>,
+) -> Result<impl IntoResponse, AppError> {
+    admin_user.require(Permission::ManageRoles)?;
+    let role = parse_role(&input.role)?;
+
+    let mut tx = state.db.begin().await?;
+    let before = roles::user_roles(&mut *tx, input.user_id).await?;
+    let revoked = match roles::revoke_role(&mut tx, input.user_id, role).await {
+        Ok(revoked) => revoked,
+        Err(AppError::LastSuperAdmin) => {
+            // Rolls back, letting go of the lock on user_roles
+            drop(tx);
+            let error = "There must always be at least one super-admin. Grant the role to someone else first.";
+            return render(&state, &admin_user, Some(error.to_string())).await;
+        }
+        Err(error) => return Err(error),
+    };
+
+    if revoked {
+        let after = roles::user_roles(&mut *tx, input.user_id).await?;
+        audit::Event::new(audit::ROLE_REVOKED)
+            .by(admin_user.user.id, &client)
+            .details(json!({ "user_id": input.user_id, "role": role.as_str() }))
+            .change(role_names(&before), role_names(&after))
+            .record(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+
+    Ok(Redirect::to(return_to(input.return_to.as_deref())).into_response())
+}
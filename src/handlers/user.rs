@@ -1,12 +1,58 @@
 // handlers/user.rs
 
-use axum::extract::State;
+use askama::Template;
+use axum::extract::{Path, State};
 use axum::response::{Html, IntoResponse};
-use sqlx::query;
+use sqlx::{query, query_as, PgPool};
+use uuid::Uuid;
 use crate::AppState;
-use crate::auth::AuthUser;
+use crate::announcements;
+use crate::achievements::Badge;
+use crate::auth::{AuthUser, OptionalAuthUser};
 use crate::errors::AppError;
-use crate::templates::user::{DashboardTemplate, RecentGameweek, UserStats};
+use crate::models::{SeasonPosition, SeasonScore, User};
+use crate::templates::user::{
+    BadgeInfo, DashboardTemplate, PositionChart, ProfileTemplate, RecentGameweek, UserStats
+};
+
+/// Number of badges shown on the dashboard; profiles show them all.
+const DASHBOARD_BADGES: i64 = 5;
+
+async fn user_badges(
+    db: &PgPool,
+    user_id: Uuid,
+    limit: Option<i64>,
+) -> Result<Vec<BadgeInfo>, AppError> {
+    let rows = query!(
+        r#"
+        SELECT a.badge, a.season, a.created_at, gw.week_number as "week_number?"
+        FROM achievements a
+        LEFT JOIN gameweeks gw ON a.gameweek_id = gw.id
+        WHERE a.user_id = $1
+        ORDER BY a.created_at DESC, gw.week_number DESC
+        LIMIT $2
+        "#,
+        user_id,
+        limit
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .filter_map(|row| {
+            let badge = Badge::from_key(&row.badge)?;
+            Some(BadgeInfo {
+                icon: badge.icon(),
+                name: badge.name(),
+                description: badge.description(),
+                season: row.season,
+                week_number: row.week_number,
+                awarded_at: row.created_at.unwrap_or_default(),
+            })
+        })
+        .collect())
+}
 
 pub async fn dashboard(
     State(state): State<AppState>,
@@ -106,13 +152,79 @@ pub async fn dashboard(
         false
     };
 
-    let template = DashboardTemplate {
-        user: &auth_user.user,
+    let badges = user_badges(&state.db, user_id, Some(DASHBOARD_BADGES)).await?;
+    let announcements = announcements::current(&state.db, Some(user_id)).await?;
+
+    let template = DashboardTemplate::new(
+        &auth_user.user,
         user_stats,
         recent_gameweeks,
         current_gameweek,
-        has_predictions
-    };
+        has_predictions,
+        badges,
+        announcements,
+    );
+
+    Ok(Html(template.render()?))
+}
+
+pub async fn profile(
+    State(state): State<AppState>,
+    auth_user: OptionalAuthUser,
+    Path(user_id): Path<Uuid>,
+) -> Result<impl IntoResponse, AppError> {
+    let profile = query_as::<_, User>(
+        "SELECT * FROM users WHERE id = $1"
+    )
+    .bind(user_id)
+    .fetch_optional(&state.db)
+    .await?
+    .ok_or(AppError::UserNotFound)?;
+
+    let season = query!(
+        "SELECT season FROM gameweeks WHERE is_active = true LIMIT 1"
+    )
+    .fetch_optional(&state.db)
+    .await?
+    .map(|s| s.season)
+    .unwrap_or_else(|| "2024-25".to_string());
+
+    let season_score = query_as::<_, SeasonScore>(
+        "SELECT * FROM season_scores WHERE user_id = $1 AND season = $2"
+    )
+    .bind(user_id)
+    .bind(&season)
+    .fetch_optional(&state.db)
+    .await?;
+
+    let position_history = query_as::<_, SeasonPosition>(
+        "SELECT * FROM season_positions WHERE user_id = $1 AND season = $2 ORDER BY week_number"
+    )
+    .bind(user_id)
+    .bind(&season)
+    .fetch_all(&state.db)
+    .await?;
+
+    let max_position = query!(
+        "SELECT MAX(position) as max_position FROM season_positions WHERE season = $1",
+        season
+    )
+    .fetch_one(&state.db)
+    .await?
+    .max_position
+    .unwrap_or(1);
+
+    let position_chart = PositionChart::new(&position_history, max_position);
+    let badges = user_badges(&state.db, user_id, None).await?;
+
+    let template = ProfileTemplate::new(
+        auth_user.user.as_ref(),
+        profile,
+        season,
+        season_score,
+        position_chart,
+        badges,
+    );
 
     Ok(Html(template.render()?))
-}
\ No newline at end of file
+}
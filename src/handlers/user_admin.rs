@@ -0,0 +1,353 @@
+// handlers/user_admin.rs
+
+use askama::Template;
+use axum::extract::{Path, Query, State};
+use axum::response::{Html, IntoResponse, Redirect, Response};
+use axum::Form;
+use axum_extra::extract::CookieJar;
+use serde::Deserialize;
+use serde_json::json;
+use sqlx::{query, query_as, PgConnection};
+use uuid::Uuid;
+use validator::Validate;
+use crate::AppState;
+use crate::audit;
+use crate::auth::AdminUser;
+use crate::errors::AppError;
+use crate::impersonation;
+use crate::models::{MergeUser, StartImpersonation, UpdateUserNames, User};
+use crate::password_reset::force_reset;
+use crate::roles::{user_roles, Permission};
+use crate::auth::Claims;
+use crate::sessions::{ClientInfo, SESSION_COOKIE};
+use crate::templates::admin::{UserDetailTemplate, UsersTemplate};
+use crate::user_admin;
+
+#[derive(Debug, Deserialize)]
+pub struct UserSearchQuery {
+    pub q: Option<String>,
+    pub page: Option<i64>,
+}
+
+async fn find_user(state: &AppState, user_id: Uuid) -> Result<User, AppError> {
+    query_as::<_, User>("SELECT * FROM users WHERE id = $1")
+        .bind(user_id)
+        .fetch_optional(&state.db)
+        .await?
+        .ok_or(AppError::UserNotFound)
+}
+
+async fn render_user(
+    state: &AppState,
+    admin_user: &AdminUser,
+    user_id: Uuid,
+    error: Option<String>,
+    success: Option<String>,
+) -> Result<Response, AppError> {
+    let member = find_user(state, user_id).await?;
+    let member_roles = user_roles(&state.db, user_id).await?;
+    let predictions = query!("SELECT COUNT(*) AS count FROM predictions WHERE user_id = $1", user_id)
+        .fetch_one(&state.db)
+        .await?
+        .count
+        .unwrap_or(0);
+    let can_impersonate = impersonation::can_impersonate(&state.db, admin_user.user.id, &member).await?;
+
+    let template = UserDetailTemplate::new(
+        &admin_user.user,
+        member,
+        member_roles,
+        predictions,
+        can_impersonate,
+        admin_user.permissions(),
+        error,
+        success,
+    );
+
+    Ok(Html(template.render()?).into_response())
+}
+
+async fn record(
+    db: &mut PgConnection,
+    admin_user: &AdminUser,
+    client: &ClientInfo,
+    action: &str,
+    details: serde_json::Value,
+) -> Result<(), AppError> {
+    audit::record(db, action, Some(admin_user.user.id), client.ip_address.as_deref(), details).await
+}
+
+/// Admins can't suspend, reset or merge away their own account from here.
+fn require_other(admin_user: &AdminUser, user_id: Uuid) -> Result<(), AppError> {
+    if admin_user.user.id == user_id {
+        return Err(AppError::Forbidden);
+    }
+
+    Ok(())
+}
+
+/// Another admin's account can only be managed by someone who could take
+/// their roles away, so a moderator can't act against a super-admin.
+async fn require_outranks(state: &AppState, admin_user: &AdminUser, user_id: Uuid) -> Result<(), AppError> {
+    if admin_user.user.id != user_id && !user_roles(&state.db, user_id).await?.is_empty() {
+        admin_user.require(Permission::ManageRoles)?;
+    }
+
+    Ok(())
+}
+
+pub async fn users(
+    State(state): State<AppState>,
+    admin_user: AdminUser,
+    Query(params): Query<UserSearchQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    admin_user.require(Permission::ModerateLeague)?;
+
+    let search = params.q.unwrap_or_default();
+    let results = user_admin::search_users(&state.db, &search, params.page.unwrap_or(1)).await?;
+
+    let template = UsersTemplate::new(&admin_user.user, search, results);
+
+    Ok(Html(template.render()?))
+}
+
+pub async fn user(
+    State(state): State<AppState>,
+    admin_user: AdminUser,
+    Path(user_id): Path<Uuid>,
+) -> Result<impl IntoResponse, AppError> {
+    admin_user.require(Permission::ModerateLeague)?;
+
+    render_user(&state, &admin_user, user_id, None, None).await
+}
+
+pub async fn update_names(
+    State(state): State<AppState>,
+    admin_user: AdminUser,
+    client: ClientInfo,
+    Path(user_id): Path<Uuid>,
+    Form(input): Form<UpdateUserNames>,
+) -> Result<impl IntoResponse, AppError> {
+    admin_user.require(Permission::ModerateLeague)?;
+    require_outranks(&state, &admin_user, user_id).await?;
+
+    if input.validate().is_err() {
+        let error = "Names must be at least 2 characters, and display names at most 100".to_string();
+        return render_user(&state, &admin_user, user_id, Some(error), None).await;
+    }
+
+    let mut tx = state.db.begin().await?;
+    user_admin::update_names(&mut *tx, user_id, &input.name, &input.display_name).await?;
+    record(&mut tx, &admin_user, &client, audit::USER_UPDATED, json!({
+        "user_id": user_id,
+        "name": input.name.trim(),
+        "display_name": input.display_name.trim(),
+    }))
+    .await?;
+    tx.commit().await?;
+
+    render_user(&state, &admin_user, user_id, None, Some("Names saved".to_string())).await
+}
+
+pub async fn suspend(
+    State(state): State<AppState>,
+    admin_user: AdminUser,
+    client: ClientInfo,
+    Path(user_id): Path<Uuid>,
+) -> Result<impl IntoResponse, AppError> {
+    admin_user.require(Permission::ModerateLeague)?;
+    require_other(&admin_user, user_id)?;
+    require_outranks(&state, &admin_user, user_id).await?;
+
+    let mut tx = state.db.begin().await?;
+    match user_admin::suspend(&mut tx, user_id).await {
+        Ok(()) => {}
+        Err(AppError::LastSuperAdmin) => {
+            // Rolls back, letting go of the lock on user_roles
+            drop(tx);
+            let error = "This is the only active super-admin. Grant the role to someone else first.".to_string();
+            return render_user(&state, &admin_user, user_id, Some(error), None).await;
+        }
+        Err(error) => return Err(error),
+    }
+    record(&mut tx, &admin_user, &client, audit::USER_SUSPENDED, json!({ "user_id": user_id })).await?;
+    tx.commit().await?;
+
+    Ok(Redirect::to(&format!("/admin/users/{}", user_id)).into_response())
+}
+
+pub async fn reinstate(
+    State(state): State<AppState>,
+    admin_user: AdminUser,
+    client: ClientInfo,
+    Path(user_id): Path<Uuid>,
+) -> Result<impl IntoResponse, AppError> {
+    admin_user.require(Permission::ModerateLeague)?;
+    require_outranks(&state, &admin_user, user_id).await?;
+
+    let mut tx = state.db.begin().await?;
+    user_admin::reinstate(&mut *tx, user_id).await?;
+    record(&mut tx, &admin_user, &client, audit::USER_REINSTATED, json!({ "user_id": user_id })).await?;
+    tx.commit().await?;
+
+    Ok(Redirect::to(&format!("/admin/users/{}", user_id)))
+}
+
+pub async fn force_password_reset(
+    State(state): State<AppState>,
+    admin_user: AdminUser,
+    client: ClientInfo,
+    Path(user_id): Path<Uuid>,
+) -> Result<impl IntoResponse, AppError> {
+    admin_user.require(Permission::ModerateLeague)?;
+    require_other(&admin_user, user_id)?;
+    require_outranks(&state, &admin_user, user_id).await?;
+
+    let member = find_user(&state, user_id).await?;
+    let mut tx = state.db.begin().await?;
+    match force_reset(&state, &mut tx, &member).await {
+        Ok(()) => {}
+        Err(AppError::MailDelivery(error)) => {
+            tracing::error!(error = %error, "couldn't send a forced password reset link");
+            let error = "The reset email couldn't be sent, so their password hasn't been changed. Try again later.".to_string();
+            return render_user(&state, &admin_user, user_id, Some(error), None).await;
+        }
+        Err(error) => return Err(error),
+    }
+    record(&mut tx, &admin_user, &client, audit::USER_PASSWORD_RESET_FORCED, json!({ "user_id": user_id })).await?;
+    tx.commit().await?;
+
+    let success = format!("{} has been signed out and emailed a link to choose a new password", member.display_name);
+    render_user(&state, &admin_user, user_id, None, Some(success)).await
+}
+
+pub async fn merge(
+    State(state): State<AppState>,
+    admin_user: AdminUser,
+    client: ClientInfo,
+    Path(user_id): Path<Uuid>,
+    Form(input): Form<MergeUser>,
+) -> Result<impl IntoResponse, AppError> {
+    admin_user.require(Permission::ModerateLeague)?;
+    require_other(&admin_user, user_id)?;
+
+    if input.validate().is_err() {
+        return render_user(&state, &admin_user, user_id, Some("Please enter a valid email address".to_string()), None).await;
+    }
+
+    let keep = query!("SELECT id FROM users WHERE LOWER(email) = LOWER($1)", input.keep_email.trim())
+        .fetch_optional(&state.db)
+        .await?;
+
+    let keep_id = match keep {
+        Some(keep) if keep.id != user_id => keep.id,
+        Some(_) => {
+            let error = "That's this account's own email. Enter the email of the account to keep.".to_string();
+            return render_user(&state, &admin_user, user_id, Some(error), None).await;
+        }
+        None => {
+            let error = format!("Nobody has registered as {}", input.keep_email.trim());
+            return render_user(&state, &admin_user, user_id, Some(error), None).await;
+        }
+    };
+
+    let mut tx = state.db.begin().await?;
+    match user_admin::merge_users(&mut tx, user_id, keep_id).await {
+        Ok(()) => {}
+        Err(AppError::Forbidden) => {
+            let error = "Remove this account's admin roles before merging it.".to_string();
+            return render_user(&state, &admin_user, user_id, Some(error), None).await;
+        }
+        Err(error) => return Err(error),
+    }
+
+    record(&mut tx, &admin_user, &client, audit::USER_MERGED, json!({
+        "merged_user_id": user_id,
+        "kept_user_id": keep_id,
+    }))
+    .await?;
+    tx.commit().await?;
+
+    Ok(Redirect::to(&format!("/admin/users/{}", keep_id)).into_response())
+}
+
+pub async fn impersonate(
+    State(state): State<AppState>,
+    admin_user: AdminUser,
+    client: ClientInfo,
+    Path(user_id): Path<Uuid>,
+    Form(input): Form<StartImpersonation>,
+) -> Result<impl IntoResponse, AppError> {
+    admin_user.require(Permission::ModerateLeague)?;
+    if input.allow_writes {
+        admin_user.require(Permission::ActAsUser)?;
+    }
+
+    if input.validate().is_err() {
+        let error = format!(
+            "Give a reason, and a time between 1 and {} minutes",
+            impersonation::MAX_MINUTES
+        );
+        return render_user(&state, &admin_user, user_id, Some(error), None).await;
+    }
+
+    let member = find_user(&state, user_id).await?;
+    if !impersonation::can_impersonate(&state.db, admin_user.user.id, &member).await? {
+        return Err(AppError::Forbidden);
+    }
+
+    let mut tx = state.db.begin().await?;
+    let started = impersonation::start(
+        &mut tx,
+        admin_user.session_id,
+        admin_user.user.id,
+        member.id,
+        &input.reason,
+        input.minutes,
+        input.allow_writes,
+    )
+    .await?;
+
+    record(&mut tx, &admin_user, &client, audit::IMPERSONATION_STARTED, json!({
+        "impersonation_id": started.id,
+        "user_id": member.id,
+        "reason": started.reason,
+        "allow_writes": started.allow_writes,
+        "expires_at": started.expires_at,
+    }))
+    .await?;
+    tx.commit().await?;
+
+    Ok(Redirect::to("/dashboard").into_response())
+}
+
+/// The banner's exit button. This is the admin's own session, so it goes by
+/// the cookie rather than `AdminUser`, which is refused while impersonating.
+pub async fn exit_impersonation(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    jar: CookieJar,
+) -> Result<impl IntoResponse, AppError> {
+    let token = jar
+        .get(SESSION_COOKIE)
+        .map(|cookie| cookie.value())
+        .ok_or(AppError::MissingToken)?;
+    let claims = Claims::from_token(token, &state.config.jwt_secret)?;
+
+    let mut tx = state.db.begin().await?;
+    let Some(ended) = impersonation::end(&mut *tx, claims.sid).await? else {
+        return Ok(Redirect::to("/admin"));
+    };
+
+    audit::record(
+        &mut *tx,
+        audit::IMPERSONATION_ENDED,
+        Some(ended.admin_id),
+        client.ip_address.as_deref(),
+        json!({ "impersonation_id": ended.id, "user_id": ended.user_id }),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(Redirect::to(&format!("/admin/users/{}", ended.user_id)))
+}
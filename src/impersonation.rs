@@ -0,0 +1,272 @@
+// impersonation.rs
+//
+// Admins viewing the site as a player, to see what they see. It hangs off
+// the admin's own session: while it lasts, every request from that session
+// is treated as the player's, and when it ends or times out the admin is
+// simply themselves again. It's read-only unless the admin chose otherwise,
+// and pages that manage the account itself stay out of reach either way.
+
+use std::net::SocketAddr;
+use axum::extract::{ConnectInfo, Request, State};
+use axum::http::Method;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum_extra::extract::CookieJar;
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use serde_json::json;
+use sqlx::{query, query_as, Connection, FromRow, PgConnection, PgExecutor, PgPool};
+use uuid::Uuid;
+use crate::AppState;
+use crate::audit;
+use crate::auth::Claims;
+use crate::errors::AppError;
+use crate::models::User;
+use crate::sessions::SESSION_COOKIE;
+
+pub const DEFAULT_MINUTES: i64 = 30;
+pub const MAX_MINUTES: i64 = 120;
+/// Where the banner's exit button posts.
+pub const EXIT_PATH: &str = "/impersonation/exit";
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Impersonation {
+    pub id: Uuid,
+    /// The admin's session.
+    pub session_id: Uuid,
+    pub admin_id: Uuid,
+    /// The player being viewed as.
+    pub user_id: Uuid,
+    pub reason: String,
+    pub allow_writes: bool,
+    pub expires_at: DateTime<Utc>,
+    pub ended_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A live impersonation and the player it's for, attached to each request
+/// from the admin's session.
+#[derive(Debug, Clone)]
+pub struct Impersonating {
+    pub impersonation: Impersonation,
+    pub user: User,
+}
+
+/// What the banner at the top of every page shows.
+#[derive(Debug, Clone)]
+pub struct Banner {
+    pub display_name: String,
+    pub allow_writes: bool,
+    pub expires_at: DateTime<Utc>,
+}
+
+tokio::task_local! {
+    static BANNER: Option<Banner>;
+}
+
+/// The banner for the current request, if the admin is viewing the site as
+/// someone else. `base.html` calls this.
+pub fn banner() -> Option<Banner> {
+    BANNER.try_with(Clone::clone).ok().flatten()
+}
+
+/// Whether a request may go ahead while impersonating. Reading is always
+/// allowed, as are leaving and signing out.
+pub fn allows(method: &Method, path: &str, allow_writes: bool) -> bool {
+    let reads = matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS);
+
+    reads || allow_writes || path == EXIT_PATH || path == "/logout"
+}
+
+/// Only players can be viewed as: not staff, whose view would come with
+/// their permissions, and not suspended or deleted accounts.
+pub async fn can_impersonate(db: &PgPool, admin_id: Uuid, user: &User) -> Result<bool, AppError> {
+    if user.id == admin_id || user.is_admin || user.suspended_at.is_some() {
+        return Ok(false);
+    }
+
+    let account = query!(
+        r#"
+        SELECT deleted_at IS NOT NULL AS "deleted!",
+            EXISTS (SELECT 1 FROM user_roles WHERE user_id = $1) AS "staff!"
+        FROM users WHERE id = $1
+        "#,
+        user.id
+    )
+    .fetch_one(db)
+    .await?;
+
+    Ok(!account.deleted && !account.staff)
+}
+
+/// Starts viewing the site as `user_id` from the admin's session, ending any
+/// impersonation the session already had.
+pub async fn start(
+    db: &mut PgConnection,
+    session_id: Uuid,
+    admin_id: Uuid,
+    user_id: Uuid,
+    reason: &str,
+    minutes: i64,
+    allow_writes: bool,
+) -> Result<Impersonation, AppError> {
+    let expires_at = Utc::now() + Duration::minutes(minutes.clamp(1, MAX_MINUTES));
+
+    let mut tx = db.begin().await?;
+
+    query!(
+        "UPDATE impersonations SET ended_at = NOW() WHERE session_id = $1 AND ended_at IS NULL",
+        session_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    let impersonation = query_as::<_, Impersonation>(
+        r#"
+        INSERT INTO impersonations (session_id, admin_id, user_id, reason, allow_writes, expires_at)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        RETURNING *
+        "#
+    )
+    .bind(session_id)
+    .bind(admin_id)
+    .bind(user_id)
+    .bind(reason.trim())
+    .bind(allow_writes)
+    .bind(expires_at)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(impersonation)
+}
+
+/// Ends the session's impersonation, returning it if there was one.
+pub async fn end(db: impl PgExecutor<'_>, session_id: Uuid) -> Result<Option<Impersonation>, AppError> {
+    let ended = query_as::<_, Impersonation>(
+        r#"
+        UPDATE impersonations SET ended_at = NOW()
+        WHERE session_id = $1 AND ended_at IS NULL
+        RETURNING *
+        "#
+    )
+    .bind(session_id)
+    .fetch_optional(db)
+    .await?;
+
+    Ok(ended)
+}
+
+/// The session's impersonation, if it has one that hasn't ended or expired.
+pub async fn active(db: &PgPool, claims: &Claims) -> Result<Option<Impersonating>, AppError> {
+    let impersonation = query_as::<_, Impersonation>(
+        r#"
+        SELECT i.* FROM impersonations i
+        JOIN sessions s ON i.session_id = s.id
+        WHERE i.session_id = $1 AND i.admin_id = $2
+          AND i.ended_at IS NULL AND i.expires_at > NOW()
+          AND s.revoked_at IS NULL AND s.expires_at > NOW()
+        "#
+    )
+    .bind(claims.sid)
+    .bind(claims.sub)
+    .fetch_optional(db)
+    .await?;
+
+    let Some(impersonation) = impersonation else {
+        return Ok(None);
+    };
+
+    let user = query_as::<_, User>("SELECT * FROM users WHERE id = $1")
+        .bind(impersonation.user_id)
+        .fetch_optional(db)
+        .await?;
+
+    Ok(user.map(|user| Impersonating { impersonation, user }))
+}
+
+/// Middleware that looks up the session's impersonation once per request,
+/// for the `AuthUser` extractor and the banner, and turns away changes the
+/// admin hasn't allowed. Changes they have allowed are audited one by one.
+pub async fn track(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let claims = jar
+        .get(SESSION_COOKIE)
+        .and_then(|cookie| Claims::from_token(cookie.value(), &state.config.jwt_secret).ok());
+
+    let impersonating = match claims {
+        Some(claims) => match active(&state.db, &claims).await {
+            Ok(impersonating) => impersonating,
+            Err(error) => return error.into_response(),
+        },
+        None => None,
+    };
+
+    let Some(impersonating) = impersonating else {
+        return BANNER.scope(None, next.run(request)).await;
+    };
+
+    let impersonation = &impersonating.impersonation;
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+
+    if !allows(&method, &path, impersonation.allow_writes) {
+        return AppError::Impersonating.into_response();
+    }
+
+    if !allows(&method, &path, false) {
+        let ip_address = request
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip().to_string());
+
+        let recorded = audit::record(
+            &state.db,
+            audit::IMPERSONATION_REQUEST,
+            Some(impersonation.admin_id),
+            ip_address.as_deref(),
+            json!({
+                "impersonation_id": impersonation.id,
+                "user_id": impersonation.user_id,
+                "method": method.as_str(),
+                "path": path,
+            }),
+        )
+        .await;
+
+        if let Err(error) = recorded {
+            return error.into_response();
+        }
+    }
+
+    let banner = Banner {
+        display_name: impersonating.user.display_name.clone(),
+        allow_writes: impersonation.allow_writes,
+        expires_at: impersonation.expires_at,
+    };
+
+    request.extensions_mut().insert(impersonating);
+
+    BANNER.scope(Some(banner), next.run(request)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_only_by_default() {
+        assert!(allows(&Method::GET, "/predictions", false));
+        assert!(!allows(&Method::POST, "/predictions/submit", false));
+        assert!(allows(&Method::POST, "/predictions/submit", true));
+
+        // The admin can always get out
+        assert!(allows(&Method::POST, EXIT_PATH, false));
+        assert!(allows(&Method::POST, "/logout", false));
+    }
+}
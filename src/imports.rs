@@ -0,0 +1,170 @@
+// imports.rs
+//
+// Fixture lists and results read from CSV files by `superior6-admin`.
+// Fixtures are numbered in the order they're listed. Results are matched to
+// the gameweek's fixtures by team names, so they can come in any order.
+
+use std::io::Read;
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use validator::Validate;
+use crate::models::{CreateFixture, Fixture, FixtureResult};
+use crate::queries::FIXTURES_PER_GAMEWEEK;
+
+/// A line of a fixtures file: `home_team,away_team,kickoff_time`, with the
+/// kickoff as RFC 3339, e.g. `2024-08-17T14:00:00Z`.
+#[derive(Debug, Deserialize)]
+struct FixtureLine {
+    home_team: String,
+    away_team: String,
+    kickoff_time: DateTime<Utc>,
+}
+
+/// A line of a results file: `home_team,away_team,home_score,away_score`.
+#[derive(Debug, Deserialize)]
+pub struct ResultLine {
+    pub home_team: String,
+    pub away_team: String,
+    pub home_score: i32,
+    pub away_score: i32,
+}
+
+fn read_lines<T: DeserializeOwned>(reader: impl Read) -> Result<Vec<T>, String> {
+    csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(reader)
+        .deserialize()
+        .map(|line| line.map_err(|error| error.to_string()))
+        .collect()
+}
+
+/// Reads a gameweek's fixtures, which must be exactly six different matches.
+pub fn read_fixtures(reader: impl Read) -> Result<Vec<CreateFixture>, String> {
+    let lines: Vec<FixtureLine> = read_lines(reader)?;
+
+    if lines.len() != FIXTURES_PER_GAMEWEEK {
+        return Err(format!(
+            "expected {} fixtures, found {}",
+            FIXTURES_PER_GAMEWEEK,
+            lines.len()
+        ));
+    }
+
+    let mut fixtures = Vec::with_capacity(lines.len());
+    for (index, line) in lines.into_iter().enumerate() {
+        let fixture = CreateFixture {
+            home_team: line.home_team,
+            away_team: line.away_team,
+            kickoff_time: line.kickoff_time,
+            fixture_order: index as i32 + 1,
+        };
+
+        if fixture.validate().is_err() || fixture.home_team.eq_ignore_ascii_case(&fixture.away_team) {
+            return Err(format!(
+                "fixture {} ({} v {}) needs two different team names of 2 to 255 characters",
+                fixture.fixture_order, fixture.home_team, fixture.away_team
+            ));
+        }
+
+        if fixtures.iter().any(|other: &CreateFixture| same_match(other, &fixture.home_team, &fixture.away_team)) {
+            return Err(format!("{} v {} is listed twice", fixture.home_team, fixture.away_team));
+        }
+
+        fixtures.push(fixture);
+    }
+
+    Ok(fixtures)
+}
+
+pub fn read_results(reader: impl Read) -> Result<Vec<ResultLine>, String> {
+    read_lines(reader)
+}
+
+fn same_match(fixture: &CreateFixture, home_team: &str, away_team: &str) -> bool {
+    fixture.home_team.eq_ignore_ascii_case(home_team) && fixture.away_team.eq_ignore_ascii_case(away_team)
+}
+
+/// Pairs each result with its fixture. Every fixture needs exactly one result.
+pub fn match_results(fixtures: &[Fixture], lines: &[ResultLine]) -> Result<Vec<FixtureResult>, String> {
+    let mut results: Vec<FixtureResult> = Vec::with_capacity(fixtures.len());
+
+    for line in lines {
+        let fixture = fixtures
+            .iter()
+            .find(|f| f.home_team.eq_ignore_ascii_case(&line.home_team) && f.away_team.eq_ignore_ascii_case(&line.away_team))
+            .ok_or_else(|| format!("{} v {} isn't one of this gameweek's fixtures", line.home_team, line.away_team))?;
+
+        if results.iter().any(|result| result.fixture_id == fixture.id) {
+            return Err(format!("{} v {} has more than one result", fixture.home_team, fixture.away_team));
+        }
+
+        let result = FixtureResult {
+            fixture_id: fixture.id,
+            home_score: line.home_score,
+            away_score: line.away_score,
+        };
+        if result.validate().is_err() {
+            return Err(format!("{} v {} has a negative score", fixture.home_team, fixture.away_team));
+        }
+
+        results.push(result);
+    }
+
+    if let Some(missing) = fixtures.iter().find(|f| !results.iter().any(|r| r.fixture_id == f.id)) {
+        return Err(format!("{} v {} has no result", missing.home_team, missing.away_team));
+    }
+
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_read_fixtures() {
+        let csv = "home_team,away_team,kickoff_time\n\
+            Arsenal,Wolves,2024-08-17T14:00:00Z\n\
+            Everton,Brighton,2024-08-17T14:00:00Z\n\
+            Newcastle,Southampton,2024-08-17T14:00:00Z\n\
+            Nottingham Forest,Bournemouth,2024-08-17T14:00:00Z\n\
+            West Ham,Aston Villa,2024-08-17T16:30:00Z\n\
+            Chelsea, Man City ,2024-08-18T16:30:00Z\n";
+
+        let fixtures = read_fixtures(csv.as_bytes()).unwrap();
+        assert_eq!(fixtures.len(), 6);
+        assert_eq!(fixtures[5].away_team, "Man City");
+        assert_eq!(fixtures[5].fixture_order, 6);
+
+        let short = "home_team,away_team,kickoff_time\nArsenal,Wolves,2024-08-17T14:00:00Z\n";
+        assert!(read_fixtures(short.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_match_results() {
+        let now = Utc::now();
+        let fixture = |home: &str, away: &str| Fixture {
+            id: Uuid::new_v4(),
+            gameweek_id: Uuid::nil(),
+            home_team: home.to_string(),
+            away_team: away.to_string(),
+            kickoff_time: now,
+            home_score: None,
+            away_score: None,
+            fixture_order: 1,
+            created_at: now,
+            updated_at: now,
+        };
+        let fixtures = [fixture("Arsenal", "Wolves"), fixture("Chelsea", "Man City")];
+
+        let lines = read_results("home_team,away_team,home_score,away_score\nchelsea,man city,0,2\nArsenal,Wolves,2,0\n".as_bytes()).unwrap();
+        let results = match_results(&fixtures, &lines).unwrap();
+        assert_eq!(results[0].fixture_id, fixtures[1].id);
+        assert_eq!((results[0].home_score, results[0].away_score), (0, 2));
+
+        // Every fixture needs a result
+        assert!(match_results(&fixtures, &lines[..1]).is_err());
+    }
+}
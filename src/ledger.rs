@@ -0,0 +1,415 @@
+// ledger.rs
+//
+// Money for paid leagues. This only ever reads results through the prizes
+// module and never writes to any scoring table.
+
+use std::str::FromStr;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{query, query_as, FromRow, PgPool};
+use uuid::Uuid;
+use crate::errors::AppError;
+use crate::prizes::{MonthDefinition, PeriodKind, PrizePeriod, RollOfHonour};
+use crate::scoring::GAMEWEEKS_PER_SEASON;
+
+/// Used to size monthly prizes when months follow the calendar (August to May).
+pub const CALENDAR_MONTHS_PER_SEASON: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LedgerKind {
+    Payment,
+    Refund,
+    Payout,
+}
+
+impl LedgerKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LedgerKind::Payment => "payment",
+            LedgerKind::Refund => "refund",
+            LedgerKind::Payout => "payout",
+        }
+    }
+}
+
+impl FromStr for LedgerKind {
+    type Err = AppError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "payment" => Ok(LedgerKind::Payment),
+            "refund" => Ok(LedgerKind::Refund),
+            "payout" => Ok(LedgerKind::Payout),
+            _ => Err(AppError::InvalidLedgerEntry),
+        }
+    }
+}
+
+/// Percentages of the pot set aside for each kind of prize.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PotSplit {
+    /// Shared equally across every gameweek of the season.
+    pub weekly_percent: u32,
+    /// Shared equally across every month of the season.
+    pub monthly_percent: u32,
+    /// One entry per paid season place, first place first.
+    pub season_percents: Vec<u32>,
+}
+
+impl PotSplit {
+    pub fn new(weekly_percent: u32, monthly_percent: u32, season_percents: Vec<u32>) -> Result<Self, String> {
+        let total = weekly_percent + monthly_percent + season_percents.iter().sum::<u32>();
+        if total > 100 {
+            return Err(format!("pot split adds up to {}%", total));
+        }
+
+        Ok(Self {
+            weekly_percent,
+            monthly_percent,
+            season_percents,
+        })
+    }
+}
+
+impl Default for PotSplit {
+    fn default() -> Self {
+        Self {
+            weekly_percent: 20,
+            monthly_percent: 20,
+            season_percents: vec![35, 15, 10],
+        }
+    }
+}
+
+/// Parses an amount typed in pounds ("10", "12.5", "£12.50") into pence.
+pub fn parse_pounds(input: &str) -> Option<i64> {
+    let input = input.trim().trim_start_matches('£');
+    let (pounds, pence) = match input.split_once('.') {
+        Some((pounds, pence)) => (pounds, pence),
+        None => (input, ""),
+    };
+
+    if pounds.is_empty() || pence.len() > 2 || !pounds.chars().chain(pence.chars()).all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    let pounds: i64 = pounds.parse().ok()?;
+    let pence: i64 = match pence.len() {
+        0 => 0,
+        1 => pence.parse::<i64>().ok()? * 10,
+        _ => pence.parse().ok()?,
+    };
+
+    pounds.checked_mul(100)?.checked_add(pence)
+}
+
+pub fn format_pounds(pence: i64) -> String {
+    let sign = if pence < 0 { "-" } else { "" };
+    format!("{}£{}.{:02}", sign, pence.abs() / 100, pence.abs() % 100)
+}
+
+#[derive(Debug, Clone)]
+pub struct DerivedPayout {
+    pub user_id: Uuid,
+    pub display_name: String,
+    /// Stable key for the prize, so it can only be recorded once.
+    pub reference: String,
+    pub label: String,
+    pub amount_pence: i64,
+}
+
+fn period_reference(period: &PrizePeriod) -> String {
+    match period.kind {
+        PeriodKind::Weekly => format!("gameweek:{}", period.gameweek_ids.first().copied().unwrap_or_default()),
+        PeriodKind::Monthly => format!("month:{}", period.label),
+        PeriodKind::Season => "season".to_string(),
+    }
+}
+
+fn single_prize_payouts(period: &PrizePeriod, prize_pence: i64) -> Vec<DerivedPayout> {
+    if !period.is_final || period.winners.is_empty() {
+        return vec![];
+    }
+
+    let share = prize_pence / period.winners.len() as i64;
+    period.winners
+        .iter()
+        .filter(|_| share > 0)
+        .map(|winner| DerivedPayout {
+            user_id: winner.user_id,
+            display_name: winner.display_name.clone(),
+            reference: period_reference(period),
+            label: period.label.clone(),
+            amount_pence: share,
+        })
+        .collect()
+}
+
+/// Works out who is owed what from settled prize periods. Tied players split
+/// the combined money for the places they span; odd pence stay in the pot.
+pub fn derive_payouts(
+    honours: &RollOfHonour,
+    pot_pence: i64,
+    split: &PotSplit,
+    months: &MonthDefinition,
+) -> Vec<DerivedPayout> {
+    let months_in_season = match months {
+        MonthDefinition::Calendar => CALENDAR_MONTHS_PER_SEASON,
+        MonthDefinition::GameweekRanges(ranges) => ranges.len().max(1),
+    };
+
+    let weekly_prize = pot_pence * split.weekly_percent as i64 / 100 / GAMEWEEKS_PER_SEASON as i64;
+    let monthly_prize = pot_pence * split.monthly_percent as i64 / 100 / months_in_season as i64;
+
+    let mut payouts: Vec<DerivedPayout> = honours.weekly
+        .iter()
+        .chain(honours.monthly.iter())
+        .flat_map(|period| {
+            let prize = match period.kind {
+                PeriodKind::Weekly => weekly_prize,
+                _ => monthly_prize,
+            };
+            single_prize_payouts(period, prize)
+        })
+        .collect();
+
+    let season = &honours.season_places;
+    if season.is_final {
+        let place_prizes: Vec<i64> = split.season_percents
+            .iter()
+            .map(|percent| pot_pence * *percent as i64 / 100)
+            .collect();
+
+        for winner in &season.winners {
+            let start = (winner.place - 1).min(place_prizes.len());
+            let end = (start + winner.tie_size).min(place_prizes.len());
+            let amount = place_prizes[start..end].iter().sum::<i64>() / winner.tie_size as i64;
+
+            if amount > 0 {
+                payouts.push(DerivedPayout {
+                    user_id: winner.user_id,
+                    display_name: winner.display_name.clone(),
+                    reference: period_reference(season),
+                    label: season.label.clone(),
+                    amount_pence: amount,
+                });
+            }
+        }
+    }
+
+    payouts
+}
+
+#[derive(Debug, Clone, FromRow)]
+pub struct PlayerBalance {
+    pub user_id: Uuid,
+    pub display_name: String,
+    pub email: String,
+    pub paid_pence: i64,
+    pub refunded_pence: i64,
+    pub payouts_pence: i64,
+}
+
+impl PlayerBalance {
+    pub fn contributed_pence(&self) -> i64 {
+        self.paid_pence - self.refunded_pence
+    }
+
+    pub fn has_paid(&self, entry_fee_pence: i64) -> bool {
+        self.contributed_pence() >= entry_fee_pence
+    }
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct LedgerRow {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub display_name: String,
+    pub email: String,
+    pub kind: String,
+    pub amount_pence: i64,
+    pub reference: Option<String>,
+    pub note: Option<String>,
+    pub recorded_by_name: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+pub async fn player_balances(db: &PgPool, season: &str) -> Result<Vec<PlayerBalance>, AppError> {
+    let balances = query_as::<_, PlayerBalance>(
+        r#"
+        SELECT
+            u.id as user_id,
+            u.display_name,
+            u.email,
+            COALESCE(SUM(le.amount_pence) FILTER (WHERE le.kind = 'payment'), 0)::BIGINT as paid_pence,
+            COALESCE(SUM(le.amount_pence) FILTER (WHERE le.kind = 'refund'), 0)::BIGINT as refunded_pence,
+            COALESCE(SUM(le.amount_pence) FILTER (WHERE le.kind = 'payout'), 0)::BIGINT as payouts_pence
+        FROM users u
+        LEFT JOIN ledger_entries le ON le.user_id = u.id AND le.season = $1
+        WHERE u.is_admin = false
+        GROUP BY u.id, u.display_name, u.email
+        ORDER BY u.display_name
+        "#,
+    )
+    .bind(season)
+    .fetch_all(db)
+    .await?;
+
+    Ok(balances)
+}
+
+pub async fn ledger_rows(db: &PgPool, season: &str) -> Result<Vec<LedgerRow>, AppError> {
+    let rows = query_as::<_, LedgerRow>(
+        r#"
+        SELECT
+            le.id,
+            le.user_id,
+            u.display_name,
+            u.email,
+            le.kind,
+            le.amount_pence,
+            le.reference,
+            le.note,
+            rb.display_name as recorded_by_name,
+            COALESCE(le.created_at, NOW()) as created_at
+        FROM ledger_entries le
+        JOIN users u ON le.user_id = u.id
+        LEFT JOIN users rb ON le.recorded_by = rb.id
+        WHERE le.season = $1
+        ORDER BY le.created_at DESC
+        "#,
+    )
+    .bind(season)
+    .fetch_all(db)
+    .await?;
+
+    Ok(rows)
+}
+
+/// Money paid in, less refunds. Payouts come out of this.
+pub fn pot_pence(balances: &[PlayerBalance]) -> i64 {
+    balances.iter().map(|b| b.contributed_pence()).sum()
+}
+
+pub struct NewLedgerEntry<'a> {
+    pub user_id: Uuid,
+    pub season: &'a str,
+    pub kind: LedgerKind,
+    pub amount_pence: i64,
+    pub reference: Option<&'a str>,
+    pub note: Option<&'a str>,
+    pub recorded_by: Uuid,
+}
+
+/// Returns false if the entry was a payout that had already been recorded.
+pub async fn record_entry(db: &PgPool, entry: NewLedgerEntry<'_>) -> Result<bool, AppError> {
+    let result = query(
+        r#"
+        INSERT INTO ledger_entries (user_id, season, kind, amount_pence, reference, note, recorded_by)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        ON CONFLICT DO NOTHING
+        "#
+    )
+    .bind(entry.user_id)
+    .bind(entry.season)
+    .bind(entry.kind.as_str())
+    .bind(entry.amount_pence)
+    .bind(entry.reference)
+    .bind(entry.note)
+    .bind(entry.recorded_by)
+    .execute(db)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub(crate) fn csv_field(value: &str) -> String {
+    // Stop spreadsheets treating names like "=SUM(...)" as formulas
+    let value = if value.starts_with(['=', '+', '-', '@']) {
+        format!("'{}", value)
+    } else {
+        value.to_string()
+    };
+
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
+
+pub fn ledger_csv(season: &str, rows: &[LedgerRow]) -> String {
+    let mut csv = String::from("date,season,player,email,kind,amount,reference,note,recorded_by\n");
+    for row in rows {
+        let fields = [
+            row.created_at.to_rfc3339(),
+            season.to_string(),
+            row.display_name.clone(),
+            row.email.clone(),
+            row.kind.clone(),
+            format!("{}.{:02}", row.amount_pence / 100, row.amount_pence % 100),
+            row.reference.clone().unwrap_or_default(),
+            row.note.clone().unwrap_or_default(),
+            row.recorded_by_name.clone().unwrap_or_default(),
+        ];
+        let line: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+        csv.push_str(&line.join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prizes::PrizeWinner;
+
+    fn winner(name: &str, place: usize, tie_size: usize) -> PrizeWinner {
+        PrizeWinner {
+            user_id: Uuid::new_v4(),
+            display_name: name.to_string(),
+            place,
+            tie_size,
+            points: 100,
+        }
+    }
+
+    #[test]
+    fn test_parse_pounds() {
+        assert_eq!(parse_pounds("10"), Some(1000));
+        assert_eq!(parse_pounds("£12.5"), Some(1250));
+        assert_eq!(parse_pounds("0.05"), Some(5));
+        assert_eq!(parse_pounds("1.234"), None);
+        assert_eq!(parse_pounds("-3"), None);
+        assert_eq!(parse_pounds(""), None);
+    }
+
+    #[test]
+    fn test_tied_season_places_share_combined_prizes() {
+        let honours = RollOfHonour {
+            season: "2024-25".to_string(),
+            weekly: vec![],
+            monthly: vec![],
+            season_places: PrizePeriod {
+                kind: PeriodKind::Season,
+                label: "2024-25 Season".to_string(),
+                gameweek_ids: vec![],
+                is_final: true,
+                winners: vec![winner("a", 1, 2), winner("b", 1, 2), winner("c", 3, 1)],
+            },
+        };
+        let split = PotSplit::new(0, 0, vec![50, 30, 20]).unwrap();
+
+        let payouts = derive_payouts(&honours, 10_000, &split, &MonthDefinition::Calendar);
+        let amounts: Vec<i64> = payouts.iter().map(|p| p.amount_pence).collect();
+
+        assert_eq!(amounts, vec![4000, 4000, 2000]);
+    }
+
+    #[test]
+    fn test_csv_escaping() {
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field("a, \"b\""), "\"a, \"\"b\"\"\"");
+        assert_eq!(csv_field("=1+1"), "'=1+1");
+    }
+}
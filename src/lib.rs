@@ -0,0 +1,53 @@
+// lib.rs
+//
+// Everything the web server and the `superior6-admin` tool share.
+
+use metrics_exporter_prometheus::PrometheusHandle;
+use sqlx::PgPool;
+use std::sync::Arc;
+
+pub mod config;
+pub mod models;
+pub mod handlers;
+pub mod auth;
+pub mod api_tokens;
+pub mod sessions;
+pub mod mailer;
+pub mod password_reset;
+pub mod email_verification;
+pub mod settings;
+pub mod two_factor;
+pub mod oidc;
+pub mod throttle;
+pub mod audit;
+pub mod announcements;
+pub mod csrf;
+pub mod impersonation;
+pub mod roles;
+pub mod user_admin;
+pub mod account;
+pub mod scoring;
+pub mod achievements;
+pub mod prizes;
+pub mod ledger;
+pub mod crowd;
+pub mod queries;
+pub mod gameweeks;
+pub mod imports;
+pub mod exports;
+pub mod openapi;
+pub mod telemetry;
+pub mod metrics;
+pub mod templates;
+pub mod errors;
+
+use config::Config;
+use mailer::Mailer;
+
+#[derive(Clone)]
+pub struct AppState {
+    pub db: PgPool,
+    pub config: Arc<Config>,
+    pub mailer: Arc<dyn Mailer>,
+    pub metrics: PrometheusHandle,
+}
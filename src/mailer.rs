@@ -0,0 +1,397 @@
+// mailer.rs
+
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use axum::async_trait;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use chrono::Utc;
+use thiserror::Error;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio_rustls::rustls::{ClientConfig, OwnedTrustAnchor, RootCertStore, ServerName};
+use tokio_rustls::TlsConnector;
+use uuid::Uuid;
+use crate::config::Config;
+
+/// Give up on an SMTP conversation that takes longer than this.
+const SMTP_TIMEOUT_SECS: u64 = 30;
+
+/// A plain-text email to a single recipient.
+#[derive(Debug, Clone)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+#[derive(Debug, Error)]
+pub enum MailError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("SMTP server replied {code}: {message}")]
+    Rejected { code: u16, message: String },
+
+    #[error("SMTP server timed out")]
+    Timeout,
+
+    #[error("'{0}' can't be used as a TLS server name")]
+    InvalidHost(String),
+}
+
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, email: &Email) -> Result<(), MailError>;
+}
+
+/// Which `Mailer` the server uses, from `MAIL_TRANSPORT`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MailTransport {
+    Smtp,
+    File,
+    Stdout,
+}
+
+impl FromStr for MailTransport {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "smtp" => Ok(MailTransport::Smtp),
+            "file" => Ok(MailTransport::File),
+            "stdout" => Ok(MailTransport::Stdout),
+            other => Err(format!("unknown mail transport '{}'", other)),
+        }
+    }
+}
+
+/// How the SMTP connection is secured, from `SMTP_TLS`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmtpTls {
+    /// Plain text throughout, for a local relay or Mailpit.
+    None,
+    /// Upgraded with STARTTLS before anything else is sent, usually port 587.
+    /// Delivery fails rather than carrying on in plain text if the server
+    /// won't upgrade.
+    StartTls,
+    /// TLS from the first byte, usually port 465.
+    Tls,
+}
+
+impl FromStr for SmtpTls {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "none" => Ok(SmtpTls::None),
+            "starttls" => Ok(SmtpTls::StartTls),
+            "tls" => Ok(SmtpTls::Tls),
+            other => Err(format!("unknown SMTP TLS mode '{}'", other)),
+        }
+    }
+}
+
+pub fn from_config(config: &Config) -> Arc<dyn Mailer> {
+    let from = config.mail_from.clone();
+
+    match config.mail_transport {
+        MailTransport::Smtp => Arc::new(SmtpMailer {
+            host: config.smtp_host.clone(),
+            port: config.smtp_port,
+            tls: config.smtp_tls,
+            credentials: config.smtp_username.clone().zip(config.smtp_password.clone()),
+            from,
+        }),
+        MailTransport::File => Arc::new(FileMailer { dir: PathBuf::from(&config.mail_dir), from }),
+        MailTransport::Stdout => Arc::new(StdoutMailer { from }),
+    }
+}
+
+/// The full RFC 5322 message with CRLF line endings.
+fn format_message(from: &str, email: &Email) -> String {
+    let domain = from.rsplit('@').next().unwrap_or("localhost");
+    let headers = [
+        format!("From: Superior 6 <{}>", from),
+        format!("To: <{}>", email.to),
+        format!("Subject: {}", email.subject),
+        format!("Date: {}", Utc::now().to_rfc2822()),
+        format!("Message-ID: <{}@{}>", Uuid::new_v4(), domain),
+        "MIME-Version: 1.0".to_string(),
+        "Content-Type: text/plain; charset=utf-8".to_string(),
+        "Content-Transfer-Encoding: 8bit".to_string(),
+    ];
+
+    let body = email.body.lines().collect::<Vec<_>>().join("\r\n");
+
+    format!("{}\r\n\r\n{}\r\n", headers.join("\r\n"), body)
+}
+
+/// Delivers over SMTP, optionally with AUTH PLAIN, in plain text or over TLS
+/// as `tls` says. Server certificates are checked against the Mozilla roots.
+pub struct SmtpMailer {
+    pub host: String,
+    pub port: u16,
+    pub tls: SmtpTls,
+    pub credentials: Option<(String, String)>,
+    pub from: String,
+}
+
+impl SmtpMailer {
+    async fn deliver(&self, email: &Email) -> Result<(), MailError> {
+        let stream = TcpStream::connect((self.host.as_str(), self.port)).await?;
+
+        match self.tls {
+            SmtpTls::None => {
+                let mut stream = BufReader::new(stream);
+                expect_reply(&mut stream, 220).await?;
+                self.converse(&mut stream, email).await
+            }
+            SmtpTls::StartTls => {
+                let mut stream = BufReader::new(stream);
+                expect_reply(&mut stream, 220).await?;
+                command(&mut stream, &format!("EHLO {}", self.domain()), 250).await?;
+                command(&mut stream, "STARTTLS", 220).await?;
+
+                let mut stream = BufReader::new(self.secure(stream.into_inner()).await?);
+                self.converse(&mut stream, email).await
+            }
+            SmtpTls::Tls => {
+                let mut stream = BufReader::new(self.secure(stream).await?);
+                expect_reply(&mut stream, 220).await?;
+                self.converse(&mut stream, email).await
+            }
+        }
+    }
+
+    /// Everything after the greeting (and STARTTLS, if used).
+    async fn converse<S>(&self, stream: &mut S, email: &Email) -> Result<(), MailError>
+    where
+        S: AsyncBufRead + AsyncWrite + Unpin,
+    {
+        command(stream, &format!("EHLO {}", self.domain()), 250).await?;
+
+        if let Some((username, password)) = &self.credentials {
+            let auth = BASE64.encode(format!("\0{}\0{}", username, password));
+            command(stream, &format!("AUTH PLAIN {}", auth), 235).await?;
+        }
+
+        command(stream, &format!("MAIL FROM:<{}>", self.from), 250).await?;
+        command(stream, &format!("RCPT TO:<{}>", email.to), 250).await?;
+        command(stream, "DATA", 354).await?;
+
+        // Lines starting with a dot are escaped so they can't end the message early
+        let message = format_message(&self.from, email)
+            .split("\r\n")
+            .map(|line| if line.starts_with('.') { format!(".{}", line) } else { line.to_string() })
+            .collect::<Vec<_>>()
+            .join("\r\n");
+        stream.write_all(message.as_bytes()).await?;
+        command(stream, ".", 250).await?;
+
+        command(stream, "QUIT", 221).await
+    }
+
+    fn domain(&self) -> &str {
+        self.from.rsplit('@').next().unwrap_or("localhost")
+    }
+
+    async fn secure<S>(&self, stream: S) -> Result<tokio_rustls::client::TlsStream<S>, MailError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let mut roots = RootCertStore::empty();
+        roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|anchor| {
+            OwnedTrustAnchor::from_subject_spki_name_constraints(anchor.subject, anchor.spki, anchor.name_constraints)
+        }));
+        let config = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+
+        let name = ServerName::try_from(self.host.as_str()).map_err(|_| MailError::InvalidHost(self.host.clone()))?;
+
+        Ok(TlsConnector::from(Arc::new(config)).connect(name, stream).await?)
+    }
+}
+
+async fn command<S>(stream: &mut S, line: &str, expected: u16) -> Result<(), MailError>
+where
+    S: AsyncBufRead + AsyncWrite + Unpin,
+{
+    stream.write_all(format!("{}\r\n", line).as_bytes()).await?;
+    stream.flush().await?;
+    expect_reply(stream, expected).await
+}
+
+/// Reads a possibly multi-line reply ("250-..." lines ending with "250 ...").
+async fn expect_reply<R: AsyncBufReadExt + Unpin>(reader: &mut R, expected: u16) -> Result<(), MailError> {
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).await? == 0 {
+            return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+        }
+
+        let code = line.get(..3).and_then(|code| code.parse::<u16>().ok()).unwrap_or(0);
+        let last = line.as_bytes().get(3) != Some(&b'-');
+
+        if !last {
+            continue;
+        }
+        // 251 "user not local; will forward" is as good as 250
+        if code == expected || (expected == 250 && code == 251) {
+            return Ok(());
+        }
+        return Err(MailError::Rejected { code, message: line.get(4..).unwrap_or("").trim().to_string() });
+    }
+}
+
+#[async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(&self, email: &Email) -> Result<(), MailError> {
+        tokio::time::timeout(std::time::Duration::from_secs(SMTP_TIMEOUT_SECS), self.deliver(email))
+            .await
+            .map_err(|_| MailError::Timeout)?
+    }
+}
+
+/// Writes each message to its own `.eml` file, for local development.
+pub struct FileMailer {
+    pub dir: PathBuf,
+    pub from: String,
+}
+
+#[async_trait]
+impl Mailer for FileMailer {
+    async fn send(&self, email: &Email) -> Result<(), MailError> {
+        tokio::fs::create_dir_all(&self.dir).await?;
+
+        let name = format!("{}-{}.eml", Utc::now().format("%Y%m%dT%H%M%S"), Uuid::new_v4());
+        tokio::fs::write(self.dir.join(name), format_message(&self.from, email)).await?;
+
+        Ok(())
+    }
+}
+
+/// Prints messages to the server's output. The default, so a fresh checkout
+/// never tries to send real mail.
+pub struct StdoutMailer {
+    pub from: String,
+}
+
+#[async_trait]
+impl Mailer for StdoutMailer {
+    async fn send(&self, email: &Email) -> Result<(), MailError> {
+        println!("----- email -----\n{}-----------------", format_message(&self.from, email).replace("\r\n", "\n"));
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+    use super::*;
+
+    /// Just enough of an SMTP server to accept one message and hand back
+    /// everything the client sent.
+    async fn stand_in_server(listener: TcpListener) -> String {
+        let (stream, _) = listener.accept().await.unwrap();
+        let (reader, mut writer) = stream.into_split();
+        let mut reader = BufReader::new(reader);
+        let mut transcript = String::new();
+        let mut in_data = false;
+
+        writer.write_all(b"220 stand-in ready\r\n").await.unwrap();
+
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line).await.unwrap() == 0 {
+                break;
+            }
+            transcript.push_str(&line);
+
+            let reply: &[u8] = if in_data {
+                if line != ".\r\n" {
+                    continue;
+                }
+                in_data = false;
+                b"250 queued\r\n"
+            } else if line.starts_with("EHLO") {
+                b"250-stand-in\r\n250 AUTH PLAIN\r\n"
+            } else if line.starts_with("AUTH") {
+                b"235 ok\r\n"
+            } else if line.starts_with("DATA") {
+                in_data = true;
+                b"354 go ahead\r\n"
+            } else if line.starts_with("QUIT") {
+                writer.write_all(b"221 bye\r\n").await.unwrap();
+                break;
+            } else {
+                b"250 ok\r\n"
+            };
+            writer.write_all(reply).await.unwrap();
+        }
+
+        transcript
+    }
+
+    #[tokio::test]
+    async fn test_smtp_mailer_delivers_to_stand_in_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(stand_in_server(listener));
+
+        let mailer = SmtpMailer {
+            host: "127.0.0.1".to_string(),
+            port,
+            tls: SmtpTls::None,
+            credentials: Some(("user".to_string(), "secret".to_string())),
+            from: "noreply@superior6.test".to_string(),
+        };
+        let email = Email {
+            to: "alex@example.com".to_string(),
+            subject: "Hello".to_string(),
+            body: "First line\n.hidden dot\nLast line".to_string(),
+        };
+
+        mailer.send(&email).await.unwrap();
+        let transcript = server.await.unwrap();
+
+        assert!(transcript.contains("EHLO superior6.test\r\n"));
+        assert!(transcript.contains(&format!("AUTH PLAIN {}\r\n", BASE64.encode("\0user\0secret"))));
+        assert!(transcript.contains("MAIL FROM:<noreply@superior6.test>\r\n"));
+        assert!(transcript.contains("RCPT TO:<alex@example.com>\r\n"));
+        assert!(transcript.contains("Subject: Hello\r\n"));
+        assert!(transcript.contains("\r\n..hidden dot\r\n"));
+        assert!(transcript.ends_with("Last line\r\n.\r\nQUIT\r\n"));
+    }
+
+    #[tokio::test]
+    async fn test_smtp_mailer_wont_fall_back_to_plain_text() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(stand_in_server(listener));
+
+        let mailer = SmtpMailer {
+            host: "127.0.0.1".to_string(),
+            port,
+            tls: SmtpTls::StartTls,
+            credentials: Some(("user".to_string(), "secret".to_string())),
+            from: "noreply@superior6.test".to_string(),
+        };
+        let email = Email {
+            to: "alex@example.com".to_string(),
+            subject: "Hello".to_string(),
+            body: "Hi".to_string(),
+        };
+
+        // The stand-in answers STARTTLS with a plain "250 ok" rather than 220
+        assert!(matches!(mailer.send(&email).await, Err(MailError::Rejected { .. })));
+        let transcript = server.await.unwrap();
+
+        assert!(transcript.ends_with("STARTTLS\r\n"));
+        assert!(!transcript.contains("AUTH"));
+    }
+}
@@ -1,41 +1,66 @@
 // main.rs
 
 use axum::{
-    http::StatusCode,
+    http::{header, HeaderName, HeaderValue, Method, StatusCode},
+    middleware,
     response::IntoResponse,
     routing::get,
     routing::post,
     Router
 };
-use sqlx::{postgres::PgPoolOptions, PgPool};
+use clap::Parser;
+use sqlx::postgres::PgPoolOptions;
+use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::sync::Arc;
 use tower::ServiceBuilder;
-use tower_http::{cors::CorsLayer, services::ServeDir};
-
-mod config;
-mod models;
-mod handlers;
-mod auth;
-mod scoring;
-mod templates;
-mod errors;
-
-use config::Config;
-
-#[derive(Clone)]
-pub struct AppState {
-    pub db: PgPool,
-    pub config: Arc<Config>,
+use tower_http::{
+    cors::{AllowOrigin, CorsLayer},
+    request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer},
+    services::ServeDir,
+    trace::TraceLayer,
+};
+
+use superior6::config::{Config, Sources};
+use superior6::{csrf, handlers, impersonation, mailer, metrics, roles, sessions, telemetry, AppState};
+
+/// Flags override the same settings from the environment and config file.
+#[derive(Parser)]
+#[command(name = "superior6", about = "Superior 6 web server")]
+struct Args {
+    /// TOML config file. Defaults to $SUPERIOR6_CONFIG, then ./superior6.toml if present.
+    #[arg(long)]
+    config: Option<PathBuf>,
+    /// development or production (APP_ENV)
+    #[arg(long)]
+    env: Option<String>,
+    /// Address to listen on, e.g. 127.0.0.1:8080 (BIND_ADDRESS)
+    #[arg(long)]
+    bind: Option<String>,
+    /// Database connection pool size (DATABASE_MAX_CONNECTIONS)
+    #[arg(long)]
+    max_connections: Option<u32>,
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     dotenvy::dotenv().ok();
+    let args = Args::parse();
+
+    let sources = Sources::new(args.config.as_deref())?
+        .flag("APP_ENV", args.env)
+        .flag("BIND_ADDRESS", args.bind)
+        .flag("DATABASE_MAX_CONNECTIONS", args.max_connections);
+    let config = Arc::new(Config::load(&sources)?);
+    telemetry::init(&config);
+    let metrics = metrics::install()?;
 
-    let config = Arc::new(Config::from_env()?);
+    if config.uses_default_secret() {
+        tracing::warn!("JWT_SECRET isn't set, so sessions are signed with a well-known key");
+    }
 
     let db = PgPoolOptions::new()
-        .max_connections(10)
+        .max_connections(config.database_max_connections)
         .connect(&config.database_url)
         .await?;
 
@@ -43,20 +68,30 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .run(&db)
         .await?;
 
-    let app_state = AppState { db, config };
-    let app = create_app(app_state);
+    if let Some(email) = &config.initial_admin_email
+        && roles::bootstrap_super_admin(&db, email).await?
+    {
+        tracing::info!(email, "made the initial admin a super-admin");
+    }
 
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:6666").await?;
-    println!("Superior 6 server listening on: {}", listener.local_addr()?);
+    let bind_address = config.bind_address;
+    let mailer = mailer::from_config(&config);
+    let app_state = AppState { db, config, mailer, metrics };
+    let app = create_app(app_state)?;
 
-    axum::serve(listener, app)
+    let listener = tokio::net::TcpListener::bind(bind_address).await?;
+    tracing::info!(address = %listener.local_addr()?, "Superior 6 server listening");
+
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
         .await?;
 
     Ok(())
 }
 
-fn create_app(state: AppState) -> Router {
-    Router::new()
+fn create_app(state: AppState) -> Result<Router, String> {
+    let cors = cors_layer(&state.config)?;
+
+    let app = Router::new()
         // Static files (CSS, JS, images, etc.)
         .nest_service("/static", ServeDir::new("static"))
 
@@ -64,29 +99,118 @@ fn create_app(state: AppState) -> Router {
         .route("/", get(handlers::home::index))
         .route("/register", get(handlers::auth::register_form).post(handlers::auth::register))
         .route("/login", get(handlers::auth::login_form).post(handlers::auth::login))
+        .route("/login/oidc", get(handlers::auth::oidc_login))
+        .route("/login/oidc/callback", get(handlers::auth::oidc_callback))
+        .route("/login/two-factor", get(handlers::auth::two_factor_form).post(handlers::auth::two_factor_login))
         .route("/logout", post(handlers::auth::logout))
+        .route("/forgot-password", get(handlers::auth::forgot_password_form).post(handlers::auth::forgot_password))
+        .route("/reset-password", get(handlers::auth::reset_password_form).post(handlers::auth::reset_password))
+        .route("/verify-email", get(handlers::auth::verify_email_form).post(handlers::auth::verify_email))
+        .route("/verify-email/resend", post(handlers::auth::resend_verification_email))
 
         // Protected user routes
         .route("/dashboard", get(handlers::user::dashboard))
         .route("/predictions", get(handlers::predictions::current_gameweek))
         .route("/predictions/submit", post(handlers::predictions::submit))
+        .route("/announcements/:id/dismiss", post(handlers::announcements::dismiss))
         .route("/leaderboard", get(handlers::leaderboard::season))
         .route("/leaderboard/weekly", get(handlers::leaderboard::weekly))
+        .route("/leaderboard/honours", get(handlers::leaderboard::honours))
+        .route("/users/:id", get(handlers::user::profile))
+        .route("/account/settings", get(handlers::account::settings))
+        .route("/account/settings/profile", post(handlers::account::update_profile))
+        .route("/account/settings/email", post(handlers::account::change_email))
+        .route("/account/settings/password", post(handlers::account::change_password))
+        .route("/account/export.json", get(handlers::account::export))
+        .route("/account/delete", post(handlers::account::delete_account))
+        .route("/account/tokens", get(handlers::account::tokens).post(handlers::account::create_token))
+        .route("/account/tokens/:id/revoke", post(handlers::account::revoke_token))
+        .route("/account/sessions", get(handlers::account::sessions))
+        .route("/account/sessions/:id/revoke", post(handlers::account::revoke_session))
+        .route("/account/sessions/revoke-all", post(handlers::account::sign_out_everywhere))
+        .route("/account/two-factor", get(handlers::account::two_factor))
+        .route("/account/two-factor/setup", post(handlers::account::setup_two_factor))
+        .route("/account/two-factor/enable", post(handlers::account::enable_two_factor))
+        .route("/account/two-factor/recovery-codes", post(handlers::account::regenerate_recovery_codes))
+        .route("/account/two-factor/disable", post(handlers::account::disable_two_factor))
 
         // Admin routes
         .route("/admin", get(handlers::admin::dashboard))
         .route("/admin/gameweeks", get(handlers::admin::gameweeks).post(handlers::admin::create_gameweek))
+        .route("/admin/gameweeks/:id/note", post(handlers::admin::update_gameweek_note))
         .route("/admin/fixtures", get(handlers::admin::fixtures).post(handlers::admin::create_fixtures))
         .route("/admin/results", get(handlers::admin::results).post(handlers::admin::submit_results))
-
-        // Health check
+        .route("/admin/achievements/backfill", post(handlers::admin::backfill_achievements))
+        .route("/admin/settings/email-verification", post(handlers::admin::set_email_verification))
+        .route("/admin/users", get(handlers::user_admin::users))
+        .route("/admin/users/:id", get(handlers::user_admin::user))
+        .route("/admin/users/:id/names", post(handlers::user_admin::update_names))
+        .route("/admin/users/:id/suspend", post(handlers::user_admin::suspend))
+        .route("/admin/users/:id/reinstate", post(handlers::user_admin::reinstate))
+        .route("/admin/users/:id/force-reset", post(handlers::user_admin::force_password_reset))
+        .route("/admin/users/:id/merge", post(handlers::user_admin::merge))
+        .route("/admin/users/:id/impersonate", post(handlers::user_admin::impersonate))
+        .route("/impersonation/exit", post(handlers::user_admin::exit_impersonation))
+        .route("/admin/roles", get(handlers::roles::roles))
+        .route("/admin/roles/grant", post(handlers::roles::grant_role))
+        .route("/admin/roles/revoke", post(handlers::roles::revoke_role))
+        .route("/admin/audit", get(handlers::audit::audit_log))
+        .route("/admin/announcements", get(handlers::announcements::announcements).post(handlers::announcements::create_announcement))
+        .route("/admin/announcements/:id/end", post(handlers::announcements::end_announcement))
+        .route("/admin/ledger", get(handlers::ledger::ledger).post(handlers::ledger::create_entry))
+        .route("/admin/ledger/payouts", post(handlers::ledger::record_payouts))
+        .route("/admin/ledger/export.csv", get(handlers::ledger::export_csv))
+
+        // JSON API
+        .nest(handlers::api::PREFIX, handlers::api::routes())
+        .route("/api/openapi.json", get(handlers::api::openapi))
+
+        // Health check and Prometheus scrapes
         .route("/health", get(health_check))
+        .route("/metrics", get(handlers::metrics::metrics))
 
         .layer(
             ServiceBuilder::new()
-                .layer(CorsLayer::permissive())
+                .layer(SetRequestIdLayer::new(HeaderName::from_static(telemetry::REQUEST_ID_HEADER), MakeRequestUuid))
+                .layer(
+                    TraceLayer::new_for_http()
+                        .make_span_with(telemetry::request_span)
+                        .on_response(telemetry::record_response)
+                        // Server errors are logged by `AppError` with their cause
+                        .on_failure(())
+                )
+                .layer(PropagateRequestIdLayer::new(HeaderName::from_static(telemetry::REQUEST_ID_HEADER)))
+                .layer(middleware::from_fn(metrics::track_requests))
+                .layer(cors)
+                .layer(middleware::from_fn_with_state(state.config.cookies, csrf::protect))
+                .layer(middleware::from_fn_with_state(state.clone(), sessions::rotate_session_cookie))
+                .layer(middleware::from_fn_with_state(state.clone(), impersonation::track))
         )
-        .with_state(state)
+        .with_state(state);
+
+    Ok(app)
+}
+
+/// Only the configured origins may make cross-origin requests, and only to
+/// read or to call the JSON API. A wildcard isn't allowed, since requests are
+/// sent with credentials.
+fn cors_layer(config: &Config) -> Result<CorsLayer, String> {
+    let origins = config
+        .cors_allowed_origins
+        .iter()
+        .map(|origin| match origin.as_str() {
+            "*" => Err("CORS_ALLOWED_ORIGINS can't be '*', since cross-origin requests carry cookies".to_string()),
+            _ => origin
+                .parse::<HeaderValue>()
+                .map_err(|_| format!("CORS_ALLOWED_ORIGINS: '{}' isn't a valid origin", origin)),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(CorsLayer::new()
+        .allow_origin(AllowOrigin::list(origins))
+        .allow_methods([Method::GET, Method::POST])
+        .allow_headers([header::AUTHORIZATION, header::CONTENT_TYPE, HeaderName::from_static(csrf::CSRF_HEADER)])
+        .allow_credentials(true))
 }
 
 async fn health_check() -> impl IntoResponse {
@@ -0,0 +1,112 @@
+// metrics.rs
+//
+// Prometheus metrics, served at /metrics. Counters and histograms are
+// recorded as things happen; the database pool and active gameweek gauges
+// are read fresh on each scrape. Nothing is kept until `install` has been
+// called, so `superior6-admin` runs the same code without collecting anything.
+
+use std::time::{Duration, Instant};
+use ::metrics::{counter, gauge, histogram};
+use axum::extract::{MatchedPath, Request};
+use axum::middleware::Next;
+use axum::response::Response;
+use metrics_exporter_prometheus::{BuildError, Matcher, PrometheusBuilder, PrometheusHandle};
+use sqlx::{query, PgPool};
+use crate::errors::AppError;
+
+const HTTP_REQUESTS: &str = "superior6_http_requests_total";
+const HTTP_REQUEST_DURATION: &str = "superior6_http_request_duration_seconds";
+const DB_POOL_CONNECTIONS: &str = "superior6_db_pool_connections";
+const DB_POOL_MAX_CONNECTIONS: &str = "superior6_db_pool_max_connections";
+const PREDICTIONS_SUBMITTED: &str = "superior6_predictions_submitted_total";
+const LOGIN_FAILURES: &str = "superior6_login_failures_total";
+const SCORING_DURATION: &str = "superior6_scoring_duration_seconds";
+const PLAYERS_WITHOUT_PREDICTIONS: &str = "superior6_players_without_predictions";
+const DEADLINE: &str = "superior6_active_gameweek_deadline_timestamp_seconds";
+
+const HTTP_BUCKETS: [f64; 11] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+const SCORING_BUCKETS: [f64; 9] = [0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0];
+
+/// Starts collecting. Call once, at startup.
+pub fn install() -> Result<PrometheusHandle, BuildError> {
+    PrometheusBuilder::new()
+        .set_buckets_for_metric(Matcher::Full(HTTP_REQUEST_DURATION.to_string()), &HTTP_BUCKETS)?
+        .set_buckets_for_metric(Matcher::Full(SCORING_DURATION.to_string()), &SCORING_BUCKETS)?
+        .install_recorder()
+}
+
+/// Counts and times every request by its route pattern, e.g.
+/// `/users/:id`, so ids don't each get their own series.
+pub async fn track_requests(request: Request, next: Next) -> Response {
+    let started = Instant::now();
+    let method = request.method().to_string();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+
+    let response = next.run(request).await;
+
+    let status = response.status().as_u16().to_string();
+    counter!(HTTP_REQUESTS, "method" => method.clone(), "route" => route.clone(), "status" => status).increment(1);
+    histogram!(HTTP_REQUEST_DURATION, "method" => method, "route" => route).record(started.elapsed());
+
+    response
+}
+
+pub fn prediction_submitted(season: &str, week_number: i32) {
+    counter!(PREDICTIONS_SUBMITTED, "season" => season.to_string(), "gameweek" => week_number.to_string()).increment(1);
+}
+
+/// `step` is `password`, `two_factor` or `sso`.
+pub fn login_failed(step: &'static str) {
+    counter!(LOGIN_FAILURES, "step" => step).increment(1);
+}
+
+pub fn scoring_finished(elapsed: Duration) {
+    histogram!(SCORING_DURATION).record(elapsed);
+}
+
+/// The metrics in Prometheus' text format, after refreshing the gauges.
+pub async fn render(handle: &PrometheusHandle, db: &PgPool) -> Result<String, AppError> {
+    let idle = db.num_idle() as f64;
+    gauge!(DB_POOL_CONNECTIONS, "state" => "idle").set(idle);
+    gauge!(DB_POOL_CONNECTIONS, "state" => "in_use").set(f64::from(db.size()) - idle);
+    gauge!(DB_POOL_MAX_CONNECTIONS).set(f64::from(db.options().get_max_connections()));
+
+    // Players who haven't predicted the active gameweek. Once the deadline
+    // has passed, that's everyone who missed it.
+    let active = query!(
+        r#"
+        SELECT gw.deadline,
+            (SELECT COUNT(*) FROM users u
+             WHERE u.is_admin = false AND u.email_verified_at IS NOT NULL
+               AND u.suspended_at IS NULL AND u.deleted_at IS NULL
+               AND NOT EXISTS (
+                   SELECT 1 FROM predictions p
+                   JOIN fixtures f ON p.fixture_id = f.id
+                   WHERE f.gameweek_id = gw.id AND p.user_id = u.id
+               )
+            ) AS "missing!"
+        FROM gameweeks gw
+        WHERE gw.is_active = true
+        LIMIT 1
+        "#
+    )
+    .fetch_optional(db)
+    .await?;
+
+    match active {
+        Some(active) => {
+            gauge!(PLAYERS_WITHOUT_PREDICTIONS).set(active.missing as f64);
+            gauge!(DEADLINE).set(active.deadline.timestamp() as f64);
+        }
+        None => {
+            gauge!(PLAYERS_WITHOUT_PREDICTIONS).set(0.0);
+            gauge!(DEADLINE).set(0.0);
+        }
+    }
+
+    Ok(handle.render())
+}
@@ -0,0 +1,19 @@
+-- Season position snapshots, taken after each gameweek is scored
+
+CREATE TABLE season_positions (
+                                  id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+                                  user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+                                  gameweek_id UUID NOT NULL REFERENCES gameweeks(id) ON DELETE CASCADE,
+                                  season VARCHAR(20) NOT NULL,
+                                  week_number INTEGER NOT NULL,
+                                  position INTEGER NOT NULL,
+                                  total_points INTEGER NOT NULL DEFAULT 0,
+                                  created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+                                  updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+                                  UNIQUE(user_id, gameweek_id)
+);
+
+CREATE INDEX idx_season_positions_season ON season_positions(season, week_number);
+CREATE INDEX idx_season_positions_user ON season_positions(user_id, season);
+
+CREATE TRIGGER update_season_positions_updated_at BEFORE UPDATE ON season_positions FOR EACH ROW EXECUTE PROCEDURE update_updated_at_column();
@@ -0,0 +1,20 @@
+-- Automatically awarded badges
+
+CREATE TABLE achievements (
+                              id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+                              user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+                              badge VARCHAR(50) NOT NULL,
+                              season VARCHAR(20) NOT NULL,
+                              gameweek_id UUID REFERENCES gameweeks(id) ON DELETE CASCADE,
+                              created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+                              updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+-- Season-long badges have no gameweek, so treat NULL as a single value for uniqueness
+CREATE UNIQUE INDEX idx_achievements_unique ON achievements(
+    user_id, badge, season, COALESCE(gameweek_id, '00000000-0000-0000-0000-000000000000'::uuid)
+);
+CREATE INDEX idx_achievements_user ON achievements(user_id, created_at DESC);
+CREATE INDEX idx_achievements_season ON achievements(season);
+
+CREATE TRIGGER update_achievements_updated_at BEFORE UPDATE ON achievements FOR EACH ROW EXECUTE PROCEDURE update_updated_at_column();
@@ -0,0 +1,24 @@
+-- Entry fee and prize pot ledger for paid leagues
+
+CREATE TABLE ledger_entries (
+                                id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+                                user_id UUID NOT NULL REFERENCES users(id) ON DELETE RESTRICT,
+                                season VARCHAR(20) NOT NULL,
+                                kind VARCHAR(20) NOT NULL,
+                                amount_pence BIGINT NOT NULL,
+                                reference VARCHAR(255),
+                                note TEXT,
+                                recorded_by UUID REFERENCES users(id) ON DELETE SET NULL,
+                                created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+                                updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+                                CONSTRAINT valid_ledger_kind CHECK (kind IN ('payment', 'refund', 'payout')),
+                                CONSTRAINT positive_ledger_amount CHECK (amount_pence > 0)
+);
+
+-- A derived prize payout can only be recorded once per player
+CREATE UNIQUE INDEX idx_ledger_entries_payout_reference ON ledger_entries(user_id, season, reference)
+    WHERE kind = 'payout' AND reference IS NOT NULL;
+CREATE INDEX idx_ledger_entries_season ON ledger_entries(season, created_at DESC);
+CREATE INDEX idx_ledger_entries_user ON ledger_entries(user_id);
+
+CREATE TRIGGER update_ledger_entries_updated_at BEFORE UPDATE ON ledger_entries FOR EACH ROW EXECUTE PROCEDURE update_updated_at_column();
@@ -0,0 +1,19 @@
+-- Personal API tokens for scripts and bots
+
+CREATE TABLE api_tokens (
+                            id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+                            user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+                            name VARCHAR(100) NOT NULL,
+                            token_hash VARCHAR(64) UNIQUE NOT NULL,
+                            token_prefix VARCHAR(16) NOT NULL,
+                            scope VARCHAR(20) NOT NULL,
+                            last_used_at TIMESTAMP WITH TIME ZONE,
+                            revoked_at TIMESTAMP WITH TIME ZONE,
+                            created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+                            updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+                            CONSTRAINT valid_api_token_scope CHECK (scope IN ('read', 'predict'))
+);
+
+CREATE INDEX idx_api_tokens_user ON api_tokens(user_id);
+
+CREATE TRIGGER update_api_tokens_updated_at BEFORE UPDATE ON api_tokens FOR EACH ROW EXECUTE PROCEDURE update_updated_at_column();
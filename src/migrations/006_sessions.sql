@@ -0,0 +1,31 @@
+-- Server-side sessions so sign-ins can be listed and revoked
+
+CREATE TABLE sessions (
+                          id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+                          user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+                          device VARCHAR(100) NOT NULL,
+                          user_agent TEXT,
+                          ip_address VARCHAR(45),
+                          last_seen_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+                          expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+                          revoked_at TIMESTAMP WITH TIME ZONE,
+                          created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+                          updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+CREATE INDEX idx_sessions_user ON sessions(user_id) WHERE revoked_at IS NULL;
+
+CREATE TRIGGER update_sessions_updated_at BEFORE UPDATE ON sessions FOR EACH ROW EXECUTE PROCEDURE update_updated_at_column();
+
+-- Changing a password or admin rights signs the user out everywhere, however the change is made
+CREATE OR REPLACE FUNCTION revoke_sessions_on_credential_change()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF NEW.password_hash IS DISTINCT FROM OLD.password_hash OR NEW.is_admin IS DISTINCT FROM OLD.is_admin THEN
+        UPDATE sessions SET revoked_at = NOW() WHERE user_id = NEW.id AND revoked_at IS NULL;
+    END IF;
+    RETURN NEW;
+END;
+$$ language 'plpgsql';
+
+CREATE TRIGGER revoke_sessions_on_credential_change AFTER UPDATE ON users FOR EACH ROW EXECUTE PROCEDURE revoke_sessions_on_credential_change();
@@ -0,0 +1,12 @@
+-- Single-use password reset links; only a hash of each token is stored
+
+CREATE TABLE password_resets (
+                                 id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+                                 user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+                                 token_hash VARCHAR(64) UNIQUE NOT NULL,
+                                 expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+                                 used_at TIMESTAMP WITH TIME ZONE,
+                                 created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+CREATE INDEX idx_password_resets_user ON password_resets(user_id) WHERE used_at IS NULL;
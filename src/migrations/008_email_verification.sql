@@ -0,0 +1,28 @@
+-- Optional email verification, switched on and off by admins
+
+ALTER TABLE users ADD COLUMN email_verified_at TIMESTAMP WITH TIME ZONE;
+
+-- Accounts from before verification existed count as verified
+UPDATE users SET email_verified_at = COALESCE(created_at, NOW());
+
+CREATE TABLE email_verifications (
+                                     id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+                                     user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+                                     token_hash VARCHAR(64) UNIQUE NOT NULL,
+                                     expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+                                     used_at TIMESTAMP WITH TIME ZONE,
+                                     created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+CREATE INDEX idx_email_verifications_user ON email_verifications(user_id) WHERE used_at IS NULL;
+
+-- Site-wide settings that admins can change without a restart
+CREATE TABLE settings (
+                          key VARCHAR(100) PRIMARY KEY,
+                          value TEXT NOT NULL,
+                          updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+CREATE TRIGGER update_settings_updated_at BEFORE UPDATE ON settings FOR EACH ROW EXECUTE PROCEDURE update_updated_at_column();
+
+INSERT INTO settings (key, value) VALUES ('require_email_verification', 'false');
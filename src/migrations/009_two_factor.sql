@@ -0,0 +1,24 @@
+-- TOTP two-factor authentication and one-time recovery codes
+
+CREATE TABLE two_factor (
+                            user_id UUID PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+                            secret VARCHAR(64) NOT NULL,
+                            -- NULL while enrolment is waiting for a first valid code
+                            enabled_at TIMESTAMP WITH TIME ZONE,
+                            -- Last TOTP time step accepted, so a code can't be replayed
+                            last_used_step BIGINT,
+                            created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+                            updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+CREATE TRIGGER update_two_factor_updated_at BEFORE UPDATE ON two_factor FOR EACH ROW EXECUTE PROCEDURE update_updated_at_column();
+
+CREATE TABLE recovery_codes (
+                                id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+                                user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+                                code_hash VARCHAR(64) NOT NULL,
+                                used_at TIMESTAMP WITH TIME ZONE,
+                                created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+CREATE INDEX idx_recovery_codes_user ON recovery_codes(user_id) WHERE used_at IS NULL;
@@ -0,0 +1,26 @@
+-- Login throttling, rate limits and an audit trail of security events
+
+CREATE TABLE throttles (
+                           -- e.g. 'login:ip:203.0.113.7', 'login:account:sam@example.com'
+                           key VARCHAR(255) PRIMARY KEY,
+                           count INTEGER NOT NULL DEFAULT 0,
+                           window_started_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+                           locked_until TIMESTAMP WITH TIME ZONE,
+                           created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+                           updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+CREATE TRIGGER update_throttles_updated_at BEFORE UPDATE ON throttles FOR EACH ROW EXECUTE PROCEDURE update_updated_at_column();
+
+CREATE TABLE audit_events (
+                              id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+                              action VARCHAR(100) NOT NULL,
+                              -- The signed-in user responsible, if any
+                              actor_id UUID REFERENCES users(id) ON DELETE SET NULL,
+                              ip_address VARCHAR(45),
+                              details JSONB NOT NULL DEFAULT '{}',
+                              created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_audit_events_created_at ON audit_events(created_at DESC);
+CREATE INDEX idx_audit_events_action ON audit_events(action, created_at DESC);
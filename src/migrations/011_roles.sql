@@ -0,0 +1,16 @@
+-- Admin roles. users.is_admin is kept as "holds any role" for leaderboard
+-- exclusion and navigation.
+
+CREATE TABLE user_roles (
+                            user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+                            role VARCHAR(30) NOT NULL CHECK (role IN ('results_editor', 'fixture_manager', 'league_moderator', 'super_admin')),
+                            granted_by UUID REFERENCES users(id) ON DELETE SET NULL,
+                            created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+                            PRIMARY KEY (user_id, role)
+);
+
+CREATE INDEX idx_user_roles_role ON user_roles(role);
+
+-- Existing admins keep full access
+INSERT INTO user_roles (user_id, role)
+SELECT id, 'super_admin' FROM users WHERE is_admin = true;
@@ -0,0 +1,5 @@
+-- Suspended accounts can't sign in or use the API and are left off leaderboards
+
+ALTER TABLE users ADD COLUMN suspended_at TIMESTAMP WITH TIME ZONE;
+
+CREATE INDEX idx_users_display_name ON users(LOWER(display_name));
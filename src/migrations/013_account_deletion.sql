@@ -0,0 +1,4 @@
+-- Players who delete their account can keep their place in past league tables
+-- as an anonymous "Deleted player" instead of disappearing from them
+
+ALTER TABLE users ADD COLUMN deleted_at TIMESTAMP WITH TIME ZONE;
@@ -0,0 +1,19 @@
+-- Accounts signed in to through an OpenID Connect provider. A player is
+-- identified by the provider's issuer and subject, not their email, which
+-- can change
+
+CREATE TABLE oidc_identities (
+                                 id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+                                 user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+                                 issuer VARCHAR(255) NOT NULL,
+                                 subject VARCHAR(255) NOT NULL,
+                                 email VARCHAR(255),
+                                 last_login_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+                                 created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+                                 updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+                                 UNIQUE(issuer, subject)
+);
+
+CREATE INDEX idx_oidc_identities_user ON oidc_identities(user_id);
+
+CREATE TRIGGER update_oidc_identities_updated_at BEFORE UPDATE ON oidc_identities FOR EACH ROW EXECUTE PROCEDURE update_updated_at_column();
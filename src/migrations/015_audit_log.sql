@@ -0,0 +1,23 @@
+-- The audit log is a permanent record: rows can be added but never changed
+-- or removed, and outlive the accounts they mention
+
+-- A foreign key would null out the actor when their account is deleted,
+-- which is itself a change, so keep their email from the time instead
+ALTER TABLE audit_events DROP CONSTRAINT audit_events_actor_id_fkey;
+ALTER TABLE audit_events ADD COLUMN actor_email VARCHAR(255);
+ALTER TABLE audit_events ADD COLUMN before_values JSONB;
+ALTER TABLE audit_events ADD COLUMN after_values JSONB;
+
+UPDATE audit_events e SET actor_email = u.email FROM users u WHERE u.id = e.actor_id;
+
+CREATE INDEX idx_audit_events_actor ON audit_events(actor_id, created_at DESC);
+
+CREATE OR REPLACE FUNCTION forbid_audit_event_changes()
+RETURNS TRIGGER AS $$
+BEGIN
+    RAISE EXCEPTION 'audit_events is append-only';
+END;
+$$ language 'plpgsql';
+
+CREATE TRIGGER audit_events_append_only BEFORE UPDATE OR DELETE ON audit_events FOR EACH ROW EXECUTE PROCEDURE forbid_audit_event_changes();
+CREATE TRIGGER audit_events_no_truncate BEFORE TRUNCATE ON audit_events FOR EACH STATEMENT EXECUTE PROCEDURE forbid_audit_event_changes();
@@ -0,0 +1,19 @@
+-- Admins viewing the site as a player, tied to the admin's own session
+
+CREATE TABLE impersonations (
+                                id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+                                session_id UUID NOT NULL REFERENCES sessions(id) ON DELETE CASCADE,
+                                admin_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+                                user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+                                reason TEXT NOT NULL,
+                                allow_writes BOOLEAN NOT NULL DEFAULT false,
+                                expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+                                ended_at TIMESTAMP WITH TIME ZONE,
+                                created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+                                updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+-- A session views the site as at most one player at a time
+CREATE UNIQUE INDEX idx_impersonations_open ON impersonations(session_id) WHERE ended_at IS NULL;
+
+CREATE TRIGGER update_impersonations_updated_at BEFORE UPDATE ON impersonations FOR EACH ROW EXECUTE PROCEDURE update_updated_at_column();
@@ -0,0 +1,28 @@
+-- Notices shown to everyone on the home page and dashboard, and a note for
+-- each gameweek shown alongside its fixtures
+
+CREATE TABLE announcements (
+                               id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+                               title VARCHAR(200) NOT NULL,
+                               body TEXT NOT NULL,
+                               severity VARCHAR(20) NOT NULL DEFAULT 'info' CHECK (severity IN ('info', 'warning', 'critical')),
+                               starts_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+                               ends_at TIMESTAMP WITH TIME ZONE,
+                               created_by UUID REFERENCES users(id) ON DELETE SET NULL,
+                               created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+                               updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+                               CHECK (ends_at IS NULL OR ends_at > starts_at)
+);
+
+CREATE INDEX idx_announcements_schedule ON announcements(starts_at, ends_at);
+
+CREATE TRIGGER update_announcements_updated_at BEFORE UPDATE ON announcements FOR EACH ROW EXECUTE PROCEDURE update_updated_at_column();
+
+CREATE TABLE announcement_dismissals (
+                                         announcement_id UUID NOT NULL REFERENCES announcements(id) ON DELETE CASCADE,
+                                         user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+                                         dismissed_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+                                         PRIMARY KEY (announcement_id, user_id)
+);
+
+ALTER TABLE gameweeks ADD COLUMN note TEXT;
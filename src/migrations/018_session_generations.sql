@@ -0,0 +1,5 @@
+-- Each rotation of a session's token bumps its generation, retiring the
+-- tokens issued before it
+
+ALTER TABLE sessions ADD COLUMN generation INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE sessions ADD COLUMN rotated_at TIMESTAMP WITH TIME ZONE;
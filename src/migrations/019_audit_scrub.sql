@@ -0,0 +1,49 @@
+-- Deleting an account has to be able to take the player's personal details
+-- out of the audit log. A transaction that sets superior6.audit_scrub may
+-- clear an event's actor email and IP address and rewrite its JSON, but what
+-- happened, who did it and when stay fixed, and rows still can't be removed
+
+CREATE OR REPLACE FUNCTION forbid_audit_event_changes()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_OP = 'UPDATE' AND current_setting('superior6.audit_scrub', true) = 'on' THEN
+        IF NEW.id = OLD.id
+            AND NEW.action = OLD.action
+            AND NEW.actor_id IS NOT DISTINCT FROM OLD.actor_id
+            AND NEW.created_at = OLD.created_at
+            AND (NEW.actor_email IS NULL OR NEW.actor_email = OLD.actor_email)
+            AND (NEW.ip_address IS NULL OR NEW.ip_address = OLD.ip_address)
+        THEN
+            RETURN NEW;
+        END IF;
+    END IF;
+
+    RAISE EXCEPTION 'audit_events is append-only';
+END;
+$$ language 'plpgsql';
+
+-- Removes names and addresses from every object in `value` that belongs to
+-- the user, at any depth, e.g. their row in a list of gameweek points
+CREATE OR REPLACE FUNCTION audit_scrub_json(value JSONB, target TEXT)
+RETURNS JSONB AS $$
+BEGIN
+    CASE jsonb_typeof(value)
+    WHEN 'object' THEN
+        IF value->>'user_id' = target THEN
+            value := value - ARRAY['name', 'display_name', 'email'];
+        END IF;
+
+        RETURN (
+            SELECT COALESCE(jsonb_object_agg(key, audit_scrub_json(field, target)), '{}'::JSONB)
+            FROM jsonb_each(value) AS fields(key, field)
+        );
+    WHEN 'array' THEN
+        RETURN (
+            SELECT COALESCE(jsonb_agg(audit_scrub_json(element, target) ORDER BY position), '[]'::JSONB)
+            FROM jsonb_array_elements(value) WITH ORDINALITY AS elements(element, position)
+        );
+    ELSE
+        RETURN value;
+    END CASE;
+END;
+$$ language 'plpgsql' IMMUTABLE;
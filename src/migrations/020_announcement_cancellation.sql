@@ -0,0 +1,4 @@
+-- Announcements cancelled before they start are marked as such, rather than
+-- ended the moment they begin, so they never show to players
+
+ALTER TABLE announcements ADD COLUMN cancelled_at TIMESTAMP WITH TIME ZONE;
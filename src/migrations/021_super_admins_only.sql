@@ -0,0 +1,8 @@
+-- users.is_admin now only marks super-admins, who are left out of the
+-- league's tables. Staff with narrower roles go back to playing; this signs
+-- them out once, as any change to is_admin does
+
+UPDATE users u
+SET is_admin = false
+WHERE u.is_admin = true
+  AND NOT EXISTS (SELECT 1 FROM user_roles r WHERE r.user_id = u.id AND r.role = 'super_admin');
@@ -0,0 +1,4 @@
+-- Per-account login throttles are now keyed by a hash of the email typed in.
+-- Counts under the old keys, which held the address itself, are dropped
+
+DELETE FROM throttles WHERE key LIKE 'login:account:%@%';
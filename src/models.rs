@@ -3,17 +3,27 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
+use utoipa::ToSchema;
 use uuid::Uuid;
 use validator::Validate;
 
-#[derive(Debug, Clone, Serialize, FromRow)]
+#[derive(Debug, Clone, Serialize, FromRow, ToSchema)]
 pub struct User {
     pub id: Uuid,
     pub name: String,
     pub display_name: String,
+    // Leaderboards are public, so only the user's own email is ever serialized (see `CurrentUser`)
+    #[serde(skip_serializing)]
     pub email: String,
+    #[serde(skip_serializing)]
     pub password_hash: String,
     pub is_admin: bool,
+    /// `None` while the account is waiting for its email to be confirmed.
+    #[serde(skip_serializing)]
+    pub email_verified_at: Option<DateTime<Utc>>,
+    /// Set while an admin has blocked the account.
+    #[serde(skip_serializing)]
+    pub suspended_at: Option<DateTime<Utc>>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
@@ -37,7 +47,33 @@ pub struct LoginUser {
     pub password: String,
 }
 
-#[derive(Debug, Clone, Serialize, FromRow)]
+#[derive(Debug, Deserialize, Validate)]
+pub struct ForgotPassword {
+    #[validate(email)]
+    pub email: String,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct ResendVerification {
+    #[validate(email)]
+    pub email: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateEmailVerification {
+    pub required: bool,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct ResetPassword {
+    pub token: String,
+    #[validate(length(min = 8))]
+    pub password: String,
+    #[validate(must_match(other = "password"))]
+    pub confirm_password: String,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow, ToSchema)]
 pub struct Gameweek {
     pub id: Uuid,
     pub week_number: i32,
@@ -45,6 +81,8 @@ pub struct Gameweek {
     pub deadline: DateTime<Utc>,
     pub is_active: bool,
     pub is_completed: bool,
+    /// Shown to players with the gameweek's fixtures and results.
+    pub note: Option<String>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
@@ -57,7 +95,7 @@ pub struct CreateGameweek {
     pub deadline: DateTime<Utc>,
 }
 
-#[derive(Debug, Clone, Serialize, FromRow)]
+#[derive(Debug, Clone, Serialize, FromRow, ToSchema)]
 pub struct Fixture {
     pub id: Uuid,
     pub gameweek_id: Uuid,
@@ -82,7 +120,7 @@ pub struct CreateFixture {
     pub fixture_order: i32,
 }
 
-#[derive(Debug, Clone, Serialize, FromRow)]
+#[derive(Debug, Clone, Serialize, FromRow, ToSchema)]
 pub struct Prediction {
     pub id: Uuid,
     pub user_id: Uuid,
@@ -94,16 +132,18 @@ pub struct Prediction {
     pub updated_at: DateTime<Utc>,
 }
 
-#[derive(Debug, Deserialize, Validate)]
+#[derive(Debug, Deserialize, Validate, ToSchema)]
 pub struct CreatePrediction {
     pub fixture_id: Uuid,
     #[validate(range(min = 0, max = 20))]
+    #[schema(minimum = 0, maximum = 20)]
     pub home_score_prediction: i32,
     #[validate(range(min = 0, max = 20))]
+    #[schema(minimum = 0, maximum = 20)]
     pub away_score_prediction: i32,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct GameweekPredictions {
     pub predictions: Vec<CreatePrediction>
 }
@@ -133,8 +173,111 @@ pub struct SeasonScore {
     pub updated_at: DateTime<Utc>,
 }
 
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct SeasonPosition {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub gameweek_id: Uuid,
+    pub season: String,
+    pub week_number: i32,
+    pub position: i32,
+    pub total_points: i32,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Achievement {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub badge: String,
+    pub season: String,
+    pub gameweek_id: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct LedgerEntry {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub season: String,
+    pub kind: String,
+    pub amount_pence: i64,
+    pub reference: Option<String>,
+    pub note: Option<String>,
+    pub recorded_by: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct CreateLedgerEntry {
+    pub user_id: Uuid,
+    #[validate(length(min = 7, max = 20))]
+    pub season: String,
+    pub kind: String,
+    /// Amount in pounds as typed by the admin, e.g. "10" or "12.50".
+    pub amount: String,
+    #[validate(length(max = 1000))]
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Clone, FromRow)]
+pub struct ApiToken {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub name: String,
+    pub token_hash: String,
+    pub token_prefix: String,
+    pub scope: String,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct CreateApiToken {
+    #[validate(length(min = 1, max = 100))]
+    pub name: String,
+    pub scope: String,
+}
+
+#[derive(Debug, Clone, FromRow)]
+pub struct Session {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub device: String,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    pub last_seen_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    /// Bumped each time the session's token is rotated.
+    pub generation: i32,
+    pub rotated_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, FromRow)]
+pub struct TwoFactor {
+    pub user_id: Uuid,
+    pub secret: String,
+    pub enabled_at: Option<DateTime<Utc>>,
+    pub last_used_step: Option<i64>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TwoFactorCode {
+    pub code: String,
+}
+
 // DTOs for templates
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct UserWithScore {
     pub user: User,
     pub score: i32,
@@ -143,22 +286,153 @@ pub struct UserWithScore {
     pub position: i32,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct FixtureWithPrediction {
     pub fixture: Fixture,
     pub prediction: Option<Prediction>,
 }
 
-#[derive(Debug, Deserialize, Validate)]
+#[derive(Debug, Deserialize, Validate, ToSchema)]
 pub struct FixtureResult {
     pub fixture_id: Uuid,
     #[validate(range(min = 0))]
+    #[schema(minimum = 0)]
     pub home_score: i32,
     #[validate(range(min = 0))]
+    #[schema(minimum = 0)]
     pub away_score: i32,
 }
 
-#[derive(Debug, Deserialize, Validate)]
+#[derive(Debug, Deserialize, Validate, ToSchema)]
 pub struct GameweekResults {
     pub results: Vec<FixtureResult>,
-}
\ No newline at end of file
+}
+// DTOs for the JSON API
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CurrentUser {
+    pub id: Uuid,
+    pub name: String,
+    pub display_name: String,
+    pub email: String,
+    pub is_admin: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<User> for CurrentUser {
+    fn from(user: User) -> Self {
+        Self {
+            id: user.id,
+            name: user.name,
+            display_name: user.display_name,
+            email: user.email,
+            is_admin: user.is_admin,
+            created_at: user.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SeasonLeaderboard {
+    pub season: String,
+    pub standings: Vec<UserWithScore>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct WeeklyLeaderboard {
+    pub gameweek: Gameweek,
+    pub standings: Vec<UserWithScore>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct GrantRole {
+    #[validate(email)]
+    pub email: String,
+    pub role: String,
+    /// Admin page to go back to afterwards, instead of the roles page.
+    pub return_to: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RevokeRole {
+    pub user_id: Uuid,
+    pub role: String,
+    pub return_to: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct UpdateUserNames {
+    #[validate(length(min = 2, max = 255))]
+    pub name: String,
+    #[validate(length(min = 2, max = 100))]
+    pub display_name: String,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct MergeUser {
+    /// The account to keep.
+    #[validate(email)]
+    pub keep_email: String,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct UpdateGameweekNote {
+    /// Blank to remove the note.
+    #[validate(length(max = 2000))]
+    pub note: String,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct CreateAnnouncement {
+    #[validate(length(min = 3, max = 200))]
+    pub title: String,
+    #[validate(length(min = 1, max = 5000))]
+    pub body: String,
+    /// `info`, `warning` or `critical`.
+    pub severity: String,
+    /// From `datetime-local` inputs, in UTC. A blank start means now and a
+    /// blank end means until it's ended by hand.
+    #[serde(default)]
+    pub starts_at: String,
+    #[serde(default)]
+    pub ends_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DismissAnnouncement {
+    pub return_to: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct StartImpersonation {
+    /// Why, for the audit log, e.g. a support request.
+    #[validate(length(min = 3, max = 500))]
+    pub reason: String,
+    #[validate(range(min = 1, max = 120))]
+    pub minutes: i64,
+    /// Checkbox: absent unless ticked.
+    #[serde(default)]
+    pub allow_writes: bool,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct ChangeEmail {
+    #[validate(email)]
+    pub email: String,
+    pub current_password: String,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct ChangePassword {
+    pub current_password: String,
+    #[validate(length(min = 8))]
+    pub password: String,
+    #[validate(must_match(other = "password"))]
+    pub confirm_password: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeleteAccount {
+    /// `anonymise` or `erase`.
+    pub mode: String,
+    pub current_password: String,
+}
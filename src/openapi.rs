@@ -0,0 +1,460 @@
+// openapi.rs
+
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+use crate::errors::{ErrorBody, ErrorDetail};
+use crate::handlers;
+use crate::models::{
+    CreatePrediction, CurrentUser, Fixture, FixtureResult, FixtureWithPrediction, Gameweek, GameweekPredictions,
+    GameweekResults, Prediction, SeasonLeaderboard, User, UserWithScore, WeeklyLeaderboard
+};
+
+/// The OpenAPI 3 document served at `/api/openapi.json`. Every `/api/v1`
+/// handler must be listed in `paths`; the tests check this list, and each
+/// operation's bodies, against `handlers::api::routes`.
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Superior 6 API",
+        description = "Gameweeks, fixtures, predictions and leaderboards. Errors always use the `ErrorBody` schema."
+    ),
+    paths(
+        handlers::api::me,
+        handlers::api::gameweeks,
+        handlers::api::current_gameweek,
+        handlers::api::gameweek,
+        handlers::api::fixtures,
+        handlers::api::predictions,
+        handlers::api::submit_predictions,
+        handlers::api::season_leaderboard,
+        handlers::api::weekly_leaderboard,
+    ),
+    components(schemas(
+        CurrentUser, User, UserWithScore, Gameweek, Fixture, Prediction, FixtureWithPrediction,
+        CreatePrediction, GameweekPredictions, FixtureResult, GameweekResults,
+        SeasonLeaderboard, WeeklyLeaderboard, ErrorBody, ErrorDetail,
+    )),
+    modifiers(&SecurityAddon),
+    tags(
+        (name = "users", description = "The signed-in user"),
+        (name = "gameweeks", description = "Gameweeks and their fixtures"),
+        (name = "predictions", description = "Reading and submitting predictions"),
+        (name = "leaderboards", description = "Season and weekly standings"),
+    )
+)]
+pub struct ApiDoc;
+
+struct SecurityAddon;
+
+impl Modify for SecurityAddon {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "cookie",
+            SecurityScheme::ApiKey(ApiKey::Cookie(ApiKeyValue::new("auth_token"))),
+        );
+        components.add_security_scheme(
+            "bearer",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .description(Some("Personal API token with the read or predict scope"))
+                    .build(),
+            ),
+        );
+    }
+}
+
+/// Reads the request and response bodies off handler signatures, so tests
+/// can hold the spec to what the handlers really take and return.
+#[cfg(test)]
+pub(crate) mod contract {
+    use std::future::Future;
+    use axum::extract::rejection::JsonRejection;
+    use axum::extract::{Path, Query, State};
+    use axum::http::StatusCode;
+    use axum::Json;
+    use serde_json::{json, Value};
+    use utoipa::ToSchema;
+    use crate::auth::AuthUser;
+    use crate::errors::AppError;
+    use crate::models::{
+        CurrentUser, Fixture, FixtureWithPrediction, Gameweek, GameweekPredictions, SeasonLeaderboard,
+        WeeklyLeaderboard
+    };
+
+    pub(crate) struct Route {
+        pub method: &'static str,
+        /// As given to the router, e.g. `/gameweeks/:id`.
+        pub path: &'static str,
+        pub handler: &'static str,
+        /// The schema the spec should give, if the handler takes a JSON body.
+        pub request: Option<Value>,
+        /// The schema the spec should give its success response.
+        pub response: Value,
+    }
+
+    impl Route {
+        pub(crate) fn new<H: Signature<Args>, Args>(
+            method: &'static str,
+            path: &'static str,
+            handler: &'static str,
+            _: &H,
+        ) -> Self {
+            Self { method, path, handler, request: H::request(), response: H::response() }
+        }
+    }
+
+    pub(crate) trait Signature<Args> {
+        fn request() -> Option<Value>;
+        fn response() -> Value;
+    }
+
+    macro_rules! signature {
+        ($($arg:ident),*) => {
+            impl<F, Fut, $($arg),*> Signature<($($arg,)*)> for F
+            where
+                F: Fn($($arg),*) -> Fut,
+                Fut: Future,
+                Fut::Output: Body,
+                $($arg: Extractor,)*
+            {
+                fn request() -> Option<Value> {
+                    None$(.or($arg::body()))*
+                }
+
+                fn response() -> Value {
+                    <Fut::Output as Body>::schema()
+                }
+            }
+        };
+    }
+
+    signature!(A);
+    signature!(A, B);
+    signature!(A, B, C);
+
+    /// Handler arguments. Only a JSON body shows up in the spec's request.
+    pub(crate) trait Extractor {
+        fn body() -> Option<Value> {
+            None
+        }
+    }
+
+    impl<T> Extractor for State<T> {}
+    impl<T> Extractor for Query<T> {}
+    impl<T> Extractor for Path<T> {}
+    impl Extractor for AuthUser {}
+
+    impl<T: Schema> Extractor for Result<Json<T>, JsonRejection> {
+        fn body() -> Option<Value> {
+            Some(T::schema())
+        }
+    }
+
+    /// Handler return types.
+    pub(crate) trait Body {
+        fn schema() -> Value;
+    }
+
+    impl<T: Schema> Body for Json<T> {
+        fn schema() -> Value {
+            T::schema()
+        }
+    }
+
+    impl<T: Schema> Body for (StatusCode, Json<T>) {
+        fn schema() -> Value {
+            T::schema()
+        }
+    }
+
+    impl<T: Body> Body for Result<T, AppError> {
+        fn schema() -> Value {
+            T::schema()
+        }
+    }
+
+    /// How the spec refers to a type, as it writes `body = T` or `body = [T]`.
+    pub(crate) trait Schema {
+        fn schema() -> Value;
+    }
+
+    macro_rules! schemas {
+        ($($schema:ty),*) => {
+            $(
+                impl Schema for $schema {
+                    fn schema() -> Value {
+                        json!({ "$ref": format!("#/components/schemas/{}", <$schema as ToSchema>::schema().0) })
+                    }
+                }
+
+                impl Schema for Vec<$schema> {
+                    fn schema() -> Value {
+                        json!({ "type": "array", "items": <$schema as Schema>::schema() })
+                    }
+                }
+            )*
+        };
+    }
+
+    schemas!(
+        CurrentUser, Gameweek, Fixture, FixtureWithPrediction, GameweekPredictions, SeasonLeaderboard,
+        WeeklyLeaderboard
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use serde_json::{json, Value};
+    use uuid::Uuid;
+    use axum::response::IntoResponse;
+    use super::*;
+    use crate::errors::AppError;
+
+    fn resolve<'a>(spec: &'a Value, schema: &'a Value) -> &'a Value {
+        match schema.get("$ref").and_then(Value::as_str) {
+            Some(reference) => {
+                let name = reference.trim_start_matches("#/components/schemas/");
+                &spec["components"]["schemas"][name]
+            }
+            None => schema,
+        }
+    }
+
+    /// Fails if `value` has a field the schema doesn't document, lacks a
+    /// required one, or has a value of the wrong type.
+    fn assert_conforms(spec: &Value, schema: &Value, value: &Value, at: &str) {
+        let schema = resolve(spec, schema);
+
+        if let Some(all_of) = schema.get("allOf").and_then(Value::as_array) {
+            if value.is_null() {
+                assert_eq!(schema["nullable"], true, "{} is null but not nullable", at);
+                return;
+            }
+            for part in all_of {
+                assert_conforms(spec, part, value, at);
+            }
+            return;
+        }
+
+        if value.is_null() {
+            assert_eq!(schema["nullable"], true, "{} is null but not nullable", at);
+            return;
+        }
+
+        match schema["type"].as_str() {
+            Some("object") => {
+                let object = value.as_object().unwrap_or_else(|| panic!("{} should be an object", at));
+                let properties = schema["properties"].as_object();
+
+                if let Some(properties) = properties {
+                    for (key, field) in object {
+                        let field_schema = properties
+                            .get(key)
+                            .unwrap_or_else(|| panic!("{}.{} is not in the spec", at, key));
+                        assert_conforms(spec, field_schema, field, &format!("{}.{}", at, key));
+                    }
+                }
+                for required in schema["required"].as_array().into_iter().flatten() {
+                    let required = required.as_str().unwrap();
+                    assert!(object.contains_key(required), "{}.{} is required by the spec", at, required);
+                }
+            }
+            Some("array") => {
+                let items = value.as_array().unwrap_or_else(|| panic!("{} should be an array", at));
+                for (i, item) in items.iter().enumerate() {
+                    assert_conforms(spec, &schema["items"], item, &format!("{}[{}]", at, i));
+                }
+            }
+            Some("string") => assert!(value.is_string(), "{} should be a string", at),
+            Some("integer") => assert!(value.is_i64() || value.is_u64(), "{} should be an integer", at),
+            Some("number") => assert!(value.is_number(), "{} should be a number", at),
+            Some("boolean") => assert!(value.is_boolean(), "{} should be a boolean", at),
+            _ => {}
+        }
+    }
+
+    fn user() -> User {
+        User {
+            id: Uuid::new_v4(),
+            name: "Alex Smith".to_string(),
+            display_name: "Alex".to_string(),
+            email: "alex@example.com".to_string(),
+            password_hash: "hash".to_string(),
+            is_admin: false,
+            email_verified_at: Some(Utc::now()),
+            suspended_at: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    fn gameweek() -> Gameweek {
+        Gameweek {
+            id: Uuid::new_v4(),
+            week_number: 1,
+            season: "2024-25".to_string(),
+            deadline: Utc::now(),
+            is_active: true,
+            is_completed: false,
+            note: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    fn fixture_with_prediction(predicted: bool) -> FixtureWithPrediction {
+        let fixture = Fixture {
+            id: Uuid::new_v4(),
+            gameweek_id: Uuid::new_v4(),
+            home_team: "Arsenal".to_string(),
+            away_team: "Chelsea".to_string(),
+            kickoff_time: Utc::now(),
+            home_score: None,
+            away_score: None,
+            fixture_order: 1,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        };
+        let prediction = predicted.then(|| Prediction {
+            id: Uuid::new_v4(),
+            user_id: Uuid::new_v4(),
+            fixture_id: fixture.id,
+            home_score_prediction: 2,
+            away_score_prediction: 1,
+            points_awarded: 0,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        });
+
+        FixtureWithPrediction { fixture, prediction }
+    }
+
+    fn standings() -> Vec<UserWithScore> {
+        vec![UserWithScore { user: user(), score: 12, exact_scores: 1, correct_results: 3, position: 1 }]
+    }
+
+    fn to_json<T: serde::Serialize>(value: T) -> Value {
+        serde_json::to_value(value).unwrap()
+    }
+
+    /// What each documented operation actually returns, keyed the same way
+    /// as the spec's paths.
+    fn samples() -> Vec<(&'static str, &'static str, &'static str, Value)> {
+        vec![
+            ("/api/v1/me", "get", "200", to_json(CurrentUser::from(user()))),
+            ("/api/v1/gameweeks", "get", "200", to_json(vec![gameweek()])),
+            ("/api/v1/gameweeks/current", "get", "200", to_json(gameweek())),
+            ("/api/v1/gameweeks/{id}", "get", "200", to_json(gameweek())),
+            ("/api/v1/gameweeks/{id}/fixtures", "get", "200", to_json(vec![fixture_with_prediction(false).fixture])),
+            ("/api/v1/gameweeks/{id}/predictions", "get", "200", to_json(vec![fixture_with_prediction(true), fixture_with_prediction(false)])),
+            ("/api/v1/predictions", "post", "201", to_json(vec![fixture_with_prediction(true)])),
+            ("/api/v1/leaderboard/season", "get", "200", to_json(SeasonLeaderboard { season: "2024-25".to_string(), standings: standings() })),
+            ("/api/v1/leaderboard/weekly", "get", "200", to_json(WeeklyLeaderboard { gameweek: gameweek(), standings: standings() })),
+        ]
+    }
+
+    #[test]
+    fn test_responses_match_spec() {
+        let spec = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        let samples = samples();
+
+        let documented: usize = spec["paths"]
+            .as_object()
+            .unwrap()
+            .values()
+            .map(|operations| operations.as_object().unwrap().len())
+            .sum();
+        assert_eq!(documented, samples.len(), "every documented operation needs a sample");
+
+        for (path, method, status, sample) in &samples {
+            let response = &spec["paths"][path][method]["responses"][status];
+            let schema = &response["content"]["application/json"]["schema"];
+            assert!(!schema.is_null(), "{} {} {} is not documented", method, path, status);
+            assert_conforms(&spec, schema, sample, &format!("{} {}", method, path));
+        }
+
+        // Private fields must never reach a public response
+        let user = to_json(user());
+        assert!(user.get("password_hash").is_none());
+        assert!(user.get("email").is_none());
+        assert!(user.get("email_verified_at").is_none());
+    }
+
+    /// `/gameweeks/:id` as the spec writes it, `/api/v1/gameweeks/{id}`.
+    fn spec_path(path: &str) -> String {
+        let segments: Vec<String> = path
+            .split('/')
+            .map(|segment| match segment.strip_prefix(':') {
+                Some(name) => format!("{{{}}}", name),
+                None => segment.to_string(),
+            })
+            .collect();
+
+        format!("{}{}", handlers::api::PREFIX, segments.join("/"))
+    }
+
+    #[test]
+    fn test_routes_match_spec() {
+        let spec = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        let routes = handlers::api::route_table();
+
+        let documented: usize = spec["paths"]
+            .as_object()
+            .unwrap()
+            .values()
+            .map(|operations| operations.as_object().unwrap().len())
+            .sum();
+        assert_eq!(documented, routes.len(), "the spec documents operations that aren't routed");
+
+        for route in &routes {
+            let path = spec_path(route.path);
+            let at = format!("{} {}", route.method, path);
+            let operation = &spec["paths"][&path][route.method];
+            assert!(!operation.is_null(), "{} is routed but not documented", at);
+            assert_eq!(operation["operationId"], route.handler, "{} is documented on another handler", at);
+
+            for name in route.path.split('/').filter_map(|segment| segment.strip_prefix(':')) {
+                let documented = operation["parameters"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .any(|parameter| parameter["in"] == "path" && parameter["name"] == name);
+                assert!(documented, "{} doesn't document its {} parameter", at, name);
+            }
+
+            let request = &operation["requestBody"]["content"]["application/json"]["schema"];
+            assert_eq!(request, route.request.as_ref().unwrap_or(&Value::Null), "{} request body", at);
+
+            let successes: Vec<&Value> = operation["responses"]
+                .as_object()
+                .unwrap()
+                .iter()
+                .filter(|(status, _)| status.starts_with('2'))
+                .map(|(_, response)| &response["content"]["application/json"]["schema"])
+                .collect();
+            assert_eq!(successes, [&route.response], "{} success response", at);
+        }
+    }
+
+    #[test]
+    fn test_error_body_matches_spec() {
+        let spec = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        let schema = json!({ "$ref": "#/components/schemas/ErrorBody" });
+
+        let response = AppError::DeadlinePassed.into_response();
+        let body = body_json(response);
+        assert_eq!(body["error"]["code"], "deadline_passed");
+        assert_conforms(&spec, &schema, &body, "error");
+    }
+
+    fn body_json(response: axum::response::Response) -> Value {
+        let bytes = tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(axum::body::to_bytes(response.into_body(), usize::MAX))
+            .unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+}
@@ -0,0 +1,161 @@
+// password_reset.rs
+
+use chrono::{Duration, Utc};
+use sqlx::{query, query_as, PgConnection};
+use crate::AppState;
+use crate::api_tokens::{hash_token, random_secret};
+use crate::auth::{hash_password, unusable_password_hash};
+use crate::errors::AppError;
+use crate::mailer::Email;
+use crate::models::User;
+
+/// How long a reset link works for.
+pub const RESET_TOKEN_LIFETIME_MINUTES: i64 = 60;
+
+/// Stores a new single-use reset token for the user and returns its link.
+async fn create_reset_link(state: &AppState, user: &User) -> Result<String, AppError> {
+    let token = random_secret();
+
+    let mut tx = state.db.begin().await?;
+
+    // Only the newest link works
+    query!(
+        "UPDATE password_resets SET used_at = NOW() WHERE user_id = $1 AND used_at IS NULL",
+        user.id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    query!(
+        "INSERT INTO password_resets (user_id, token_hash, expires_at) VALUES ($1, $2, $3)",
+        user.id,
+        hash_token(&token),
+        Utc::now() + Duration::minutes(RESET_TOKEN_LIFETIME_MINUTES)
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(format!("{}/reset-password?token={}", state.config.app_url, token))
+}
+
+/// Emails a reset link if `email` belongs to an account. The lookup and the
+/// email happen in the background and failures are only logged, so the form
+/// answers the same way, just as quickly, whether or not someone has signed up.
+pub fn request_reset(state: &AppState, email: &str) {
+    let state = state.clone();
+    let email = email.to_string();
+
+    tokio::spawn(async move {
+        if let Err(error) = send_reset_link(&state, &email).await {
+            tracing::error!(error = %error, "couldn't send a password reset link");
+        }
+    });
+}
+
+async fn send_reset_link(state: &AppState, email: &str) -> Result<(), AppError> {
+    let user = query_as::<_, User>(
+        "SELECT * FROM users WHERE email = $1"
+    )
+    .bind(email)
+    .fetch_optional(&state.db)
+    .await?;
+
+    let Some(user) = user else {
+        return Ok(());
+    };
+
+    let link = create_reset_link(state, &user).await?;
+    let email = Email {
+        to: user.email.clone(),
+        subject: "Reset your Superior 6 password".to_string(),
+        body: format!(
+            "Hi {},\n\n\
+             Someone asked to reset the password for your Superior 6 account.\n\
+             To choose a new one, open this link within {} minutes:\n\n\
+             {}\n\n\
+             If it wasn't you, ignore this email and your password will stay the same.\n",
+            user.display_name, RESET_TOKEN_LIFETIME_MINUTES, link
+        ),
+    };
+
+    state.mailer.send(&email).await?;
+
+    Ok(())
+}
+
+/// Sets a new password using a reset token, which can only be used once.
+/// Changing the password signs the user out of every existing session. The
+/// link proves the user owns the address, so it also confirms their email.
+pub async fn reset_password(state: &AppState, token: &str, password: &str) -> Result<User, AppError> {
+    let mut tx = state.db.begin().await?;
+
+    let user_id = query!(
+        r#"
+        UPDATE password_resets SET used_at = NOW()
+        WHERE token_hash = $1 AND used_at IS NULL AND expires_at > NOW()
+        RETURNING user_id
+        "#,
+        hash_token(token)
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(AppError::InvalidResetToken)?
+    .user_id;
+
+    let user = query_as::<_, User>(
+        r#"
+        UPDATE users SET password_hash = $1, email_verified_at = COALESCE(email_verified_at, NOW())
+        WHERE id = $2
+        RETURNING *
+        "#
+    )
+    .bind(hash_password(password, state.config.bcrypt_cost)?)
+    .bind(user_id)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(user)
+}
+
+/// Makes the user choose a new password: they are emailed a reset link, then
+/// the old password stops working and every session is signed out. Nothing
+/// changes if the email can't be sent, so they're never locked out without
+/// a link. The password is changed on `db`, so the caller can audit it in the
+/// same transaction.
+pub async fn force_reset(state: &AppState, db: &mut PgConnection, user: &User) -> Result<(), AppError> {
+    let link = create_reset_link(state, user).await?;
+    let email = Email {
+        to: user.email.clone(),
+        subject: "Choose a new Superior 6 password".to_string(),
+        body: format!(
+            "Hi {},\n\n\
+             A league admin has reset the password on your Superior 6 account, so\n\
+             you'll need to choose a new one before you can sign in again.\n\
+             Open this link within {} minutes:\n\n\
+             {}\n\n\
+             If the link has expired, use \"Forgot your password?\" on the sign-in page.\n",
+            user.display_name, RESET_TOKEN_LIFETIME_MINUTES, link
+        ),
+    };
+
+    state.mailer.send(&email).await?;
+
+    // Nobody knows this password, so only the emailed link gets them back in.
+    // Left alone if they've already used the link to choose a new one.
+    let unusable = unusable_password_hash()?;
+
+    query!(
+        "UPDATE users SET password_hash = $1 WHERE id = $2 AND password_hash = $3",
+        unusable,
+        user.id,
+        user.password_hash
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
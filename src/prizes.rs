@@ -0,0 +1,353 @@
+// prizes.rs
+
+use std::collections::HashMap;
+use std::str::FromStr;
+use chrono::{DateTime, Datelike, Utc};
+use sqlx::{query_as, FromRow, PgPool};
+use uuid::Uuid;
+use crate::errors::AppError;
+use crate::models::Gameweek;
+use crate::scoring::GAMEWEEKS_PER_SEASON;
+
+/// Number of paid places at the end of the season.
+pub const SEASON_PRIZE_PLACES: usize = 3;
+
+/// How players level on points are separated when deciding a prize.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TieSplit {
+    /// Everyone level on points shares the prize.
+    Share,
+    /// Ties are broken on exact scores, then correct results; anyone still
+    /// level shares the prize.
+    CountBack,
+}
+
+impl FromStr for TieSplit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "share" => Ok(TieSplit::Share),
+            "countback" | "count_back" => Ok(TieSplit::CountBack),
+            other => Err(format!("unknown tie split '{}'", other)),
+        }
+    }
+}
+
+/// How gameweeks are grouped into monthly prize periods.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MonthDefinition {
+    /// Grouped by the calendar month of each gameweek's deadline.
+    Calendar,
+    /// Explicit inclusive gameweek ranges, e.g. `1-4,5-8,9-13`.
+    GameweekRanges(Vec<(i32, i32)>),
+}
+
+impl FromStr for MonthDefinition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.trim().eq_ignore_ascii_case("calendar") {
+            return Ok(MonthDefinition::Calendar);
+        }
+
+        let ranges = s
+            .split(',')
+            .map(|range| {
+                let (start, end) = range
+                    .trim()
+                    .split_once('-')
+                    .ok_or_else(|| format!("invalid gameweek range '{}'", range))?;
+                let start: i32 = start.trim().parse().map_err(|_| format!("invalid gameweek range '{}'", range))?;
+                let end: i32 = end.trim().parse().map_err(|_| format!("invalid gameweek range '{}'", range))?;
+                if start > end {
+                    return Err(format!("invalid gameweek range '{}'", range));
+                }
+                Ok((start, end))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(MonthDefinition::GameweekRanges(ranges))
+    }
+}
+
+#[derive(Debug, Clone, FromRow)]
+pub struct ScoreRow {
+    pub gameweek_id: Uuid,
+    pub user_id: Uuid,
+    pub display_name: String,
+    pub total_points: i32,
+    pub exact_scores: i32,
+    pub correct_results: i32,
+}
+
+#[derive(Debug, Clone)]
+pub struct PrizeWinner {
+    pub user_id: Uuid,
+    pub display_name: String,
+    /// Finishing place; tied players share the same place.
+    pub place: usize,
+    /// Number of players sharing this place, and so the prize money for
+    /// places `place..place + tie_size`.
+    pub tie_size: usize,
+    pub points: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PeriodKind {
+    Weekly,
+    Monthly,
+    Season,
+}
+
+#[derive(Debug, Clone)]
+pub struct PrizePeriod {
+    pub kind: PeriodKind,
+    pub label: String,
+    pub gameweek_ids: Vec<Uuid>,
+    /// False while later gameweeks in the period are still to be played.
+    pub is_final: bool,
+    pub winners: Vec<PrizeWinner>,
+}
+
+#[derive(Debug, Clone)]
+pub struct RollOfHonour {
+    pub season: String,
+    pub weekly: Vec<PrizePeriod>,
+    pub monthly: Vec<PrizePeriod>,
+    pub season_places: PrizePeriod,
+}
+
+struct Total {
+    user_id: Uuid,
+    display_name: String,
+    points: i32,
+    exact_scores: i32,
+    correct_results: i32,
+}
+
+fn totals_for(rows: &[ScoreRow], gameweek_ids: &[Uuid]) -> Vec<Total> {
+    let mut totals: HashMap<Uuid, Total> = HashMap::new();
+    for row in rows.iter().filter(|r| gameweek_ids.contains(&r.gameweek_id)) {
+        let total = totals.entry(row.user_id).or_insert_with(|| Total {
+            user_id: row.user_id,
+            display_name: row.display_name.clone(),
+            points: 0,
+            exact_scores: 0,
+            correct_results: 0,
+        });
+        total.points += row.total_points;
+        total.exact_scores += row.exact_scores;
+        total.correct_results += row.correct_results;
+    }
+    totals.into_values().collect()
+}
+
+/// Ranks totals and returns everyone finishing in the first `places` places.
+/// Players without points never win anything.
+fn award_places(mut totals: Vec<Total>, places: usize, tie_split: TieSplit) -> Vec<PrizeWinner> {
+    let key = |t: &Total| match tie_split {
+        TieSplit::Share => (t.points, 0, 0),
+        TieSplit::CountBack => (t.points, t.exact_scores, t.correct_results),
+    };
+
+    totals.retain(|t| t.points > 0);
+    totals.sort_by(|a, b| key(b).cmp(&key(a)).then_with(|| a.display_name.cmp(&b.display_name)));
+
+    let mut winners = Vec::new();
+    let mut place = 1;
+    let mut i = 0;
+    while i < totals.len() && place <= places {
+        let group_key = key(&totals[i]);
+        let group: Vec<&Total> = totals[i..].iter().take_while(|t| key(t) == group_key).collect();
+
+        for total in &group {
+            winners.push(PrizeWinner {
+                user_id: total.user_id,
+                display_name: total.display_name.clone(),
+                place,
+                tie_size: group.len(),
+                points: total.points,
+            });
+        }
+
+        place += group.len();
+        i += group.len();
+    }
+
+    winners
+}
+
+fn monthly_periods(gameweeks: &[Gameweek], months: &MonthDefinition) -> Vec<(String, Vec<Uuid>, bool)> {
+    let last_week = gameweeks.iter().map(|gw| gw.week_number).max().unwrap_or(0);
+
+    match months {
+        MonthDefinition::Calendar => {
+            let last_deadline: Option<DateTime<Utc>> = gameweeks.iter().map(|gw| gw.deadline).max();
+            let mut periods: Vec<((i32, u32), String, Vec<Uuid>)> = Vec::new();
+
+            for gw in gameweeks {
+                let month = (gw.deadline.year(), gw.deadline.month());
+                match periods.iter_mut().find(|(m, _, _)| *m == month) {
+                    Some((_, _, ids)) => ids.push(gw.id),
+                    None => periods.push((month, gw.deadline.format("%B %Y").to_string(), vec![gw.id])),
+                }
+            }
+
+            periods
+                .into_iter()
+                .map(|(month, label, ids)| {
+                    // A calendar month is settled once a later month has a completed gameweek
+                    let is_final = last_deadline
+                        .is_some_and(|d| (d.year(), d.month()) > month);
+                    (label, ids, is_final)
+                })
+                .collect()
+        }
+        MonthDefinition::GameweekRanges(ranges) => ranges
+            .iter()
+            .filter_map(|(start, end)| {
+                let ids: Vec<Uuid> = gameweeks
+                    .iter()
+                    .filter(|gw| gw.week_number >= *start && gw.week_number <= *end)
+                    .map(|gw| gw.id)
+                    .collect();
+
+                if ids.is_empty() {
+                    return None;
+                }
+
+                Some((format!("Gameweeks {}-{}", start, end), ids, last_week >= *end))
+            })
+            .collect(),
+    }
+}
+
+/// Works out weekly, monthly and season prize winners from the completed
+/// gameweeks of a season.
+pub fn compute_roll_of_honour(
+    season: &str,
+    gameweeks: &[Gameweek],
+    rows: &[ScoreRow],
+    months: &MonthDefinition,
+    tie_split: TieSplit,
+) -> RollOfHonour {
+    let weekly = gameweeks
+        .iter()
+        .map(|gw| PrizePeriod {
+            kind: PeriodKind::Weekly,
+            label: format!("Gameweek {}", gw.week_number),
+            gameweek_ids: vec![gw.id],
+            is_final: true,
+            winners: award_places(totals_for(rows, &[gw.id]), 1, tie_split),
+        })
+        .collect();
+
+    let monthly = monthly_periods(gameweeks, months)
+        .into_iter()
+        .map(|(label, gameweek_ids, is_final)| PrizePeriod {
+            kind: PeriodKind::Monthly,
+            label,
+            winners: award_places(totals_for(rows, &gameweek_ids), 1, tie_split),
+            gameweek_ids,
+            is_final,
+        })
+        .collect();
+
+    let season_ids: Vec<Uuid> = gameweeks.iter().map(|gw| gw.id).collect();
+    let season_places = PrizePeriod {
+        kind: PeriodKind::Season,
+        label: format!("{} Season", season),
+        winners: award_places(totals_for(rows, &season_ids), SEASON_PRIZE_PLACES, tie_split),
+        gameweek_ids: season_ids,
+        is_final: gameweeks.len() >= GAMEWEEKS_PER_SEASON,
+    };
+
+    RollOfHonour {
+        season: season.to_string(),
+        weekly,
+        monthly,
+        season_places,
+    }
+}
+
+pub async fn roll_of_honour(
+    db: &PgPool,
+    season: &str,
+    months: &MonthDefinition,
+    tie_split: TieSplit,
+) -> Result<RollOfHonour, AppError> {
+    let gameweeks = query_as::<_, Gameweek>(
+        "SELECT * FROM gameweeks WHERE season = $1 AND is_completed = true ORDER BY week_number",
+    )
+    .bind(season)
+    .fetch_all(db)
+    .await?;
+
+    let rows = query_as::<_, ScoreRow>(
+        r#"
+        SELECT
+            gs.gameweek_id,
+            gs.user_id,
+            u.display_name,
+            COALESCE(gs.total_points, 0) as total_points,
+            COALESCE(gs.exact_scores, 0) as exact_scores,
+            COALESCE(gs.correct_results, 0) as correct_results
+        FROM gameweek_scores gs
+        JOIN gameweeks gw ON gs.gameweek_id = gw.id
+        JOIN users u ON gs.user_id = u.id
+        WHERE gw.season = $1 AND gw.is_completed = true AND u.is_admin = false AND u.email_verified_at IS NOT NULL AND u.suspended_at IS NULL
+        "#,
+    )
+    .bind(season)
+    .fetch_all(db)
+    .await?;
+
+    Ok(compute_roll_of_honour(season, &gameweeks, &rows, months, tie_split))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn total(name: &str, points: i32, exact_scores: i32) -> Total {
+        Total {
+            user_id: Uuid::new_v4(),
+            display_name: name.to_string(),
+            points,
+            exact_scores,
+            correct_results: 0,
+        }
+    }
+
+    #[test]
+    fn test_shared_places() {
+        let totals = vec![total("a", 20, 1), total("b", 20, 3), total("c", 15, 0), total("d", 10, 0)];
+        let winners = award_places(totals, 3, TieSplit::Share);
+
+        let places: Vec<(usize, usize)> = winners.iter().map(|w| (w.place, w.tie_size)).collect();
+        assert_eq!(places, vec![(1, 2), (1, 2), (3, 1)]);
+    }
+
+    #[test]
+    fn test_count_back_breaks_ties() {
+        let totals = vec![total("a", 20, 1), total("b", 20, 3), total("c", 15, 0)];
+        let winners = award_places(totals, 1, TieSplit::CountBack);
+
+        assert_eq!(winners.len(), 1);
+        assert_eq!(winners[0].display_name, "b");
+    }
+
+    #[test]
+    fn test_no_winner_without_points() {
+        let totals = vec![total("a", 0, 0), total("b", 0, 0)];
+        assert!(award_places(totals, 1, TieSplit::Share).is_empty());
+    }
+
+    #[test]
+    fn test_parse_month_definition() {
+        assert_eq!("calendar".parse(), Ok(MonthDefinition::Calendar));
+        assert_eq!("1-4, 5-8".parse(), Ok(MonthDefinition::GameweekRanges(vec![(1, 4), (5, 8)])));
+        assert!("5-1".parse::<MonthDefinition>().is_err());
+    }
+}
@@ -0,0 +1,279 @@
+// queries.rs
+//
+// Queries shared by the HTML handlers and the JSON API.
+
+use chrono::Utc;
+use sqlx::{query, query_as, PgExecutor, PgPool};
+use uuid::Uuid;
+use validator::Validate;
+use crate::errors::AppError;
+use crate::metrics;
+use crate::models::{CreatePrediction, Fixture, FixtureWithPrediction, Gameweek, Prediction, User, UserWithScore};
+
+/// Fixtures every gameweek must have before predictions can be made.
+pub const FIXTURES_PER_GAMEWEEK: usize = 6;
+
+pub async fn active_gameweek(db: &PgPool) -> Result<Option<Gameweek>, AppError> {
+    let gameweek = query_as::<_, Gameweek>(
+        "SELECT * FROM gameweeks WHERE is_active = true LIMIT 1"
+    )
+        .fetch_optional(db)
+        .await?;
+
+    Ok(gameweek)
+}
+
+pub async fn gameweek_by_id(db: &PgPool, gameweek_id: Uuid) -> Result<Option<Gameweek>, AppError> {
+    let gameweek = query_as::<_, Gameweek>(
+        "SELECT * FROM gameweeks WHERE id = $1"
+    )
+        .bind(gameweek_id)
+        .fetch_optional(db)
+        .await?;
+
+    Ok(gameweek)
+}
+
+pub async fn season_gameweeks(db: &PgPool, season: &str) -> Result<Vec<Gameweek>, AppError> {
+    let gameweeks = query_as::<_, Gameweek>(
+        "SELECT * FROM gameweeks WHERE season = $1 ORDER BY week_number"
+    )
+        .bind(season)
+        .fetch_all(db)
+        .await?;
+
+    Ok(gameweeks)
+}
+
+/// A season's gameweek by its number, e.g. for a past week's leaderboard.
+pub async fn gameweek_by_week(db: &PgPool, season: &str, week_number: i32) -> Result<Option<Gameweek>, AppError> {
+    let gameweek = query_as::<_, Gameweek>(
+        "SELECT * FROM gameweeks WHERE season = $1 AND week_number = $2"
+    )
+        .bind(season)
+        .bind(week_number)
+        .fetch_optional(db)
+        .await?;
+
+    Ok(gameweek)
+}
+
+/// The active gameweek's season, falling back to the default season.
+pub async fn current_season(db: &PgPool) -> Result<String, AppError> {
+    let season = active_gameweek(db)
+        .await?
+        .map(|gw| gw.season)
+        .unwrap_or_else(|| "2024-25".to_string());
+
+    Ok(season)
+}
+
+pub async fn season_leaderboard(db: &PgPool, season: &str) -> Result<Vec<UserWithScore>, AppError> {
+    let leaderboard_data = query!(
+        r#"
+        SELECT
+            u.id, u.name, u.display_name, u.email, u.password_hash, u.is_admin, u.email_verified_at, u.suspended_at, u.created_at, u.updated_at,
+            COALESCE(ss.total_points, 0) as total_points,
+            COALESCE(ss.total_exact_scores, 0) as exact_scores,
+            COALESCE(ss.total_correct_results, 0) as correct_results,
+            ROW_NUMBER() OVER (ORDER BY COALESCE(ss.total_points, 0) DESC, COALESCE(ss.total_exact_scores, 0) DESC, u.display_name ASC) as position
+        FROM users u
+        LEFT JOIN season_scores ss ON u.id = ss.user_id AND ss.season = $1
+        WHERE u.is_admin = false AND u.email_verified_at IS NOT NULL AND u.suspended_at IS NULL
+        ORDER BY total_points DESC, exact_scores DESC, u.display_name ASC
+        "#,
+        season
+    )
+        .fetch_all(db)
+        .await?;
+
+    Ok(leaderboard_data
+        .into_iter()
+        .map(|row| UserWithScore {
+            user: User {
+                id: row.id,
+                name: row.name,
+                display_name: row.display_name,
+                email: row.email,
+                password_hash: row.password_hash,
+                is_admin: row.is_admin.unwrap_or(false),
+                email_verified_at: row.email_verified_at,
+                suspended_at: row.suspended_at,
+                created_at: row.created_at.unwrap_or(Utc::now()),
+                updated_at: row.updated_at.unwrap_or(Utc::now()),
+            },
+            score: row.total_points.unwrap_or(0),
+            exact_scores: row.exact_scores.unwrap_or(0),
+            correct_results: row.correct_results.unwrap_or(0),
+            position: row.position.unwrap_or(0) as i32,
+        })
+        .collect())
+}
+
+pub async fn weekly_leaderboard(db: &PgPool, gameweek_id: Uuid) -> Result<Vec<UserWithScore>, AppError> {
+    let leaderboard_data = query!(
+        r#"
+        SELECT
+            u.id, u.name, u.display_name, u.email, u.password_hash, u.is_admin, u.email_verified_at, u.suspended_at, u.created_at, u.updated_at,
+            COALESCE(gs.total_points, 0) as total_points,
+            COALESCE(gs.exact_scores, 0) as exact_scores,
+            COALESCE(gs.correct_results, 0) as correct_results,
+            ROW_NUMBER() OVER (ORDER BY COALESCE(gs.total_points, 0) DESC, COALESCE(gs.exact_scores, 0) DESC, u.display_name ASC) as position
+        FROM users u
+        LEFT JOIN gameweek_scores gs ON u.id = gs.user_id AND gs.gameweek_id = $1
+        WHERE u.is_admin = false AND u.email_verified_at IS NOT NULL AND u.suspended_at IS NULL
+        ORDER BY total_points DESC, exact_scores DESC, u.display_name ASC
+        "#,
+        gameweek_id
+    )
+        .fetch_all(db)
+        .await?;
+
+    Ok(leaderboard_data
+        .into_iter()
+        .map(|row| UserWithScore {
+            user: User {
+                id: row.id,
+                name: row.name,
+                display_name: row.display_name,
+                email: row.email,
+                password_hash: row.password_hash,
+                is_admin: row.is_admin.unwrap_or(false),
+                email_verified_at: row.email_verified_at,
+                suspended_at: row.suspended_at,
+                created_at: row.created_at.unwrap_or(Utc::now()),
+                updated_at: row.updated_at.unwrap_or(Utc::now()),
+            },
+            score: row.total_points.unwrap_or(0),
+            exact_scores: row.exact_scores.unwrap_or(0),
+            correct_results: row.correct_results.unwrap_or(0),
+            position: row.position.unwrap_or(0) as i32,
+        })
+        .collect())
+}
+
+pub async fn gameweek_fixtures(db: impl PgExecutor<'_>, gameweek_id: Uuid) -> Result<Vec<Fixture>, AppError> {
+    let fixtures = query_as::<_, Fixture>(
+        "SELECT * FROM fixtures WHERE gameweek_id = $1 ORDER BY fixture_order"
+    )
+        .bind(gameweek_id)
+        .fetch_all(db)
+        .await?;
+
+    Ok(fixtures)
+}
+
+/// A gameweek's fixtures, each paired with the user's prediction if they made one.
+pub async fn fixtures_with_predictions(
+    db: &PgPool,
+    gameweek_id: Uuid,
+    user_id: Uuid,
+) -> Result<Vec<FixtureWithPrediction>, AppError> {
+    let fixtures = gameweek_fixtures(db, gameweek_id).await?;
+
+    let existing_predictions = query_as::<_, Prediction>(
+        r#"
+            SELECT p.* FROM predictions p
+            JOIN fixtures f ON p.fixture_id = f.id
+            WHERE f.gameweek_id = $1 AND p.user_id = $2
+        "#
+    )
+        .bind(gameweek_id)
+        .bind(user_id)
+        .fetch_all(db)
+        .await?;
+
+    Ok(fixtures
+        .into_iter()
+        .map(|fixture| {
+            let prediction = existing_predictions
+                .iter()
+                .find(|p| p.fixture_id == fixture.id)
+                .cloned();
+
+            FixtureWithPrediction {
+                fixture,
+                prediction
+            }
+        })
+        .collect())
+}
+
+/// Validates and stores a full set of predictions for the active gameweek,
+/// returning the gameweek they were made for.
+pub async fn submit_predictions(
+    db: &PgPool,
+    user_id: Uuid,
+    predictions: &[CreatePrediction],
+) -> Result<Uuid, AppError> {
+    // Validate all predictions
+    for prediction in predictions {
+        prediction.validate()?;
+    }
+
+    // Get the current active gameweek
+    let current_gameweek = active_gameweek(db)
+        .await?
+        .ok_or(AppError::NotFound)?;
+
+    // Check if deadline has passed
+    if current_gameweek.deadline <= Utc::now() {
+        return Err(AppError::DeadlinePassed);
+    }
+
+    // Check if predictions are exactly 6
+    if predictions.len() != FIXTURES_PER_GAMEWEEK {
+        return Err(AppError::InvalidPrediction);
+    }
+
+    // Verify all fixture IDs belong to the current gameweek
+    let fixture_ids: Vec<_> = predictions.iter().map(|p| p.fixture_id).collect();
+    let valid_fixtures = query!(
+        "SELECT COUNT(*) as count FROM fixtures WHERE gameweek_id = $1 AND id = ANY($2)",
+        current_gameweek.id,
+        &fixture_ids
+    )
+        .fetch_one(db)
+        .await?;
+
+    if valid_fixtures.count != Some(FIXTURES_PER_GAMEWEEK as i64) {
+        return Err(AppError::InvalidPrediction);
+    }
+
+    // Check if user already has predictions for this gameweek
+    let existing_count = query!(
+        r#"
+        SELECT COUNT(*) as count FROM predictions p
+        JOIN fixtures f ON p.fixture_id = f.id
+        WHERE f.gameweek_id = $1 AND p.user_id = $2
+        "#,
+        current_gameweek.id,
+        user_id
+    )
+        .fetch_one(db)
+        .await?;
+
+    if existing_count.count.unwrap_or(0) > 0 {
+        return Err(AppError::PredictionsAlreadySubmitted);
+    }
+
+    // Insert all predictions
+    for prediction in predictions {
+        query!(
+            r#"
+            INSERT INTO predictions (user_id, fixture_id, home_score_prediction, away_score_prediction)
+            VALUES ($1, $2, $3, $4)
+            "#,
+            user_id,
+            prediction.fixture_id,
+            prediction.home_score_prediction,
+            prediction.away_score_prediction
+        )
+            .execute(db)
+            .await?;
+    }
+
+    metrics::prediction_submitted(&current_gameweek.season, current_gameweek.week_number);
+
+    Ok(current_gameweek.id)
+}
@@ -0,0 +1,294 @@
+// roles.rs
+//
+// Admin roles and the permissions they grant. `users.is_admin` is kept in
+// step with "is a super-admin": they run the league rather than play in it,
+// so the leaderboards leave them out. Narrower roles go to staff who still
+// play, and don't change it.
+
+use std::str::FromStr;
+use sqlx::{query, Connection, PgConnection, PgExecutor, PgPool};
+use uuid::Uuid;
+use crate::errors::AppError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    ResultsEditor,
+    FixtureManager,
+    LeagueModerator,
+    SuperAdmin,
+}
+
+/// Something an admin page lets you do. Each admin handler requires one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    /// Enter final scores, which triggers scoring.
+    EnterResults,
+    /// Create gameweeks and set up their fixtures.
+    ManageFixtures,
+    /// Look after players and the league's money.
+    ModerateLeague,
+    /// Site-wide settings and maintenance jobs.
+    ManageSettings,
+    ManageRoles,
+    /// Read the audit log.
+    ViewAudit,
+    /// Make changes while viewing the site as a player. Viewing alone only
+    /// needs `ModerateLeague`.
+    ActAsUser,
+}
+
+impl Role {
+    pub const ALL: [Role; 4] = [Role::ResultsEditor, Role::FixtureManager, Role::LeagueModerator, Role::SuperAdmin];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::ResultsEditor => "results_editor",
+            Role::FixtureManager => "fixture_manager",
+            Role::LeagueModerator => "league_moderator",
+            Role::SuperAdmin => "super_admin",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Role::ResultsEditor => "Results editor",
+            Role::FixtureManager => "Fixture manager",
+            Role::LeagueModerator => "League moderator",
+            Role::SuperAdmin => "Super-admin",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Role::ResultsEditor => "Enters final scores",
+            Role::FixtureManager => "Creates gameweeks and sets up fixtures",
+            Role::LeagueModerator => "Manages players, entry fees and payouts",
+            Role::SuperAdmin => "Everything, including settings and granting roles",
+        }
+    }
+
+    pub fn grants(&self, permission: Permission) -> bool {
+        match self {
+            Role::SuperAdmin => true,
+            Role::ResultsEditor => permission == Permission::EnterResults,
+            Role::FixtureManager => permission == Permission::ManageFixtures,
+            Role::LeagueModerator => permission == Permission::ModerateLeague,
+        }
+    }
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Role::ALL
+            .into_iter()
+            .find(|role| role.as_str() == s)
+            .ok_or_else(|| format!("unknown role '{}'", s))
+    }
+}
+
+pub fn any_grants(roles: &[Role], permission: Permission) -> bool {
+    roles.iter().any(|role| role.grants(permission))
+}
+
+/// What the signed-in admin may do, for deciding which links to show.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AdminPermissions {
+    pub enter_results: bool,
+    pub manage_fixtures: bool,
+    pub moderate_league: bool,
+    pub manage_settings: bool,
+    pub manage_roles: bool,
+    pub view_audit: bool,
+    pub act_as_user: bool,
+}
+
+impl AdminPermissions {
+    pub fn for_roles(roles: &[Role]) -> Self {
+        Self {
+            enter_results: any_grants(roles, Permission::EnterResults),
+            manage_fixtures: any_grants(roles, Permission::ManageFixtures),
+            moderate_league: any_grants(roles, Permission::ModerateLeague),
+            manage_settings: any_grants(roles, Permission::ManageSettings),
+            manage_roles: any_grants(roles, Permission::ManageRoles),
+            view_audit: any_grants(roles, Permission::ViewAudit),
+            act_as_user: any_grants(roles, Permission::ActAsUser),
+        }
+    }
+}
+
+/// Someone holding at least one role.
+#[derive(Debug, Clone)]
+pub struct StaffMember {
+    pub user_id: Uuid,
+    pub display_name: String,
+    pub email: String,
+    pub roles: Vec<Role>,
+}
+
+fn parse_roles(names: Vec<String>) -> Vec<Role> {
+    let mut roles: Vec<Role> = names.iter().filter_map(|name| name.parse().ok()).collect();
+    roles.sort();
+    roles
+}
+
+pub async fn user_roles(db: impl PgExecutor<'_>, user_id: Uuid) -> Result<Vec<Role>, AppError> {
+    let names = query!("SELECT role FROM user_roles WHERE user_id = $1", user_id)
+        .fetch_all(db)
+        .await?
+        .into_iter()
+        .map(|row| row.role)
+        .collect();
+
+    Ok(parse_roles(names))
+}
+
+pub async fn staff(db: &PgPool) -> Result<Vec<StaffMember>, AppError> {
+    let rows = query!(
+        r#"
+        SELECT u.id, u.display_name, u.email, ARRAY_AGG(r.role) AS "roles!"
+        FROM users u
+        JOIN user_roles r ON r.user_id = u.id
+        GROUP BY u.id, u.display_name, u.email
+        ORDER BY u.display_name
+        "#
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| StaffMember {
+            user_id: row.id,
+            display_name: row.display_name,
+            email: row.email,
+            roles: parse_roles(row.roles),
+        })
+        .collect())
+}
+
+/// Grants a role. Returns false if the user already had it.
+pub async fn grant_role(db: &mut PgConnection, user_id: Uuid, role: Role, granted_by: Option<Uuid>) -> Result<bool, AppError> {
+    let mut tx = db.begin().await?;
+
+    let granted = query!(
+        "INSERT INTO user_roles (user_id, role, granted_by) VALUES ($1, $2, $3) ON CONFLICT DO NOTHING",
+        user_id,
+        role.as_str(),
+        granted_by
+    )
+    .execute(&mut *tx)
+    .await?
+    .rows_affected() > 0;
+
+    if role == Role::SuperAdmin {
+        query!("UPDATE users SET is_admin = true WHERE id = $1 AND is_admin = false", user_id)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(granted)
+}
+
+/// Takes a role away, refusing to remove the last super-admin so the league
+/// can't be locked out of its own admin pages. Returns false if the user
+/// didn't have it.
+pub async fn revoke_role(db: &mut PgConnection, user_id: Uuid, role: Role) -> Result<bool, AppError> {
+    let mut tx = db.begin().await?;
+
+    // Serialises concurrent revocations so two can't both see a spare super-admin
+    query!("LOCK TABLE user_roles IN SHARE ROW EXCLUSIVE MODE")
+        .execute(&mut *tx)
+        .await?;
+
+    let revoked = query!(
+        "DELETE FROM user_roles WHERE user_id = $1 AND role = $2",
+        user_id,
+        role.as_str()
+    )
+    .execute(&mut *tx)
+    .await?
+    .rows_affected() > 0;
+
+    if role == Role::SuperAdmin {
+        let remaining = query!("SELECT COUNT(*) AS count FROM user_roles WHERE role = 'super_admin'")
+            .fetch_one(&mut *tx)
+            .await?
+            .count
+            .unwrap_or(0);
+
+        if remaining == 0 {
+            return Err(AppError::LastSuperAdmin);
+        }
+    }
+
+    if role == Role::SuperAdmin && revoked {
+        query!("UPDATE users SET is_admin = false WHERE id = $1", user_id)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(revoked)
+}
+
+/// Makes the account with `email` a super-admin if the league has none yet,
+/// so a fresh install has someone who can grant roles. Only an account that
+/// has confirmed the address counts, and only if exactly one does, so nobody
+/// can claim the role by registering the address first or a variant of it.
+/// Returns whether it did.
+pub async fn bootstrap_super_admin(db: &PgPool, email: &str) -> Result<bool, AppError> {
+    let has_super_admin = query!("SELECT EXISTS (SELECT 1 FROM user_roles WHERE role = 'super_admin') AS \"exists!\"")
+        .fetch_one(db)
+        .await?
+        .exists;
+
+    if has_super_admin {
+        return Ok(false);
+    }
+
+    let users = query!(
+        r#"
+        SELECT id FROM users
+        WHERE LOWER(email) = LOWER($1)
+          AND email_verified_at IS NOT NULL
+          AND suspended_at IS NULL
+          AND deleted_at IS NULL
+        "#,
+        email.trim()
+    )
+    .fetch_all(db)
+    .await?;
+
+    match users.as_slice() {
+        [user] => grant_role(&mut *db.acquire().await?, user.id, Role::SuperAdmin, None).await,
+        _ => Ok(false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_role_permissions() {
+        assert!(Role::ResultsEditor.grants(Permission::EnterResults));
+        assert!(!Role::ResultsEditor.grants(Permission::ManageFixtures));
+        assert!(!Role::FixtureManager.grants(Permission::EnterResults));
+        assert!(!Role::LeagueModerator.grants(Permission::ManageRoles));
+        assert!(Role::SuperAdmin.grants(Permission::ManageRoles));
+
+        let permissions = AdminPermissions::for_roles(&[Role::ResultsEditor, Role::FixtureManager]);
+        assert!(permissions.enter_results && permissions.manage_fixtures);
+        assert!(!permissions.moderate_league && !permissions.manage_settings && !permissions.manage_roles);
+
+        for role in Role::ALL {
+            assert_eq!(role.as_str().parse::<Role>(), Ok(role));
+        }
+        assert!("admin".parse::<Role>().is_err());
+    }
+}
@@ -1,13 +1,18 @@
 // scoring.rs
 
 use std::cmp::Ordering;
-use sqlx::{query, query_as, PgPool};
+use std::time::Instant;
+use serde::Serialize;
+use sqlx::{query, query_as, FromRow, PgConnection, PgExecutor};
 use uuid::Uuid;
 use crate::errors::AppError;
+use crate::metrics;
 use crate::models::{Fixture, Prediction};
 
 pub const POINTS_EXACT_SCORE: i32 = 5;
 pub const POINTS_CORRECT_RESULT: i32 = 2;
+/// A season counts as finished once this many gameweeks have been completed.
+pub const GAMEWEEKS_PER_SEASON: usize = 38;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum MatchResult {
@@ -46,15 +51,46 @@ pub fn calculate_points(
     0 // No points awarded
 }
 
-pub async fn calculate_gameweek_scores(
-    db: &PgPool,
+/// A player's total for one gameweek, as recorded in the audit log around a
+/// recalculation.
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct PlayerPoints {
+    pub user_id: Uuid,
+    pub display_name: String,
+    pub total_points: i32,
+}
+
+pub async fn gameweek_points(db: impl PgExecutor<'_>, gameweek_id: Uuid) -> Result<Vec<PlayerPoints>, AppError> {
+    let points = query_as::<_, PlayerPoints>(
+        r#"
+        SELECT gs.user_id, u.display_name, COALESCE(gs.total_points, 0) AS total_points
+        FROM gameweek_scores gs
+        JOIN users u ON gs.user_id = u.id
+        WHERE gs.gameweek_id = $1
+        ORDER BY u.display_name, gs.user_id
+        "#
+    )
+    .bind(gameweek_id)
+    .fetch_all(db)
+    .await?;
+
+    Ok(points)
+}
+
+/// Scores the gameweek from its saved results, as part of a transaction the
+/// caller has open, so a failure part-way through leaves the previous scores
+/// in place.
+pub async fn score_gameweek(
+    db: &mut PgConnection,
     gameweek_id: Uuid,
 ) -> Result<(), AppError> {
+    let started = Instant::now();
+
     let fixtures = query_as::<_, Fixture>(
         "SELECT * FROM fixtures WHERE gameweek_id = $1 AND home_score IS NOT NULL AND away_score IS NOT NULL",
     )
     .bind(gameweek_id)
-    .fetch_all(db)
+    .fetch_all(&mut *db)
     .await?;
 
     if fixtures.is_empty() {
@@ -67,7 +103,7 @@ pub async fn calculate_gameweek_scores(
         "SELECT * FROM predictions WHERE fixture_id = ANY($1)",
     )
     .bind(&fixture_ids)
-    .fetch_all(db)
+    .fetch_all(&mut *db)
     .await?;
 
     for prediction in predictions {
@@ -84,7 +120,7 @@ pub async fn calculate_gameweek_scores(
             )
             .bind(points)
             .bind(prediction.id)
-            .execute(db)
+            .execute(&mut *db)
             .await?;
         }
     }
@@ -105,7 +141,7 @@ pub async fn calculate_gameweek_scores(
         POINTS_CORRECT_RESULT,
         gameweek_id
     )
-    .fetch_all(db)
+    .fetch_all(&mut *db)
     .await?;
 
     for score in user_scores {
@@ -126,24 +162,27 @@ pub async fn calculate_gameweek_scores(
         .bind(score.total_points.unwrap_or(0) as i32)
         .bind(score.exact_scores.unwrap_or(0) as i32)
         .bind(score.correct_results.unwrap_or(0) as i32)
-        .execute(db)
+        .execute(&mut *db)
         .await?;
     }
 
     update_season_scores(db, gameweek_id).await?;
+    update_season_positions(db, gameweek_id).await?;
+
+    metrics::scoring_finished(started.elapsed());
 
     Ok(())
 }
 
 pub async fn update_season_scores(
-    db: &PgPool,
+    db: &mut PgConnection,
     gameweek_id: Uuid
 ) -> Result<(), AppError> {
     let gameweek = query!(
         "SELECT season FROM gameweeks WHERE id = $1",
         gameweek_id
     )
-    .fetch_one(db)
+    .fetch_one(&mut *db)
     .await?;
 
     let season_totals = query!(
@@ -161,7 +200,7 @@ pub async fn update_season_scores(
         "#,
         gameweek.season
     )
-    .fetch_all(db)
+    .fetch_all(&mut *db)
     .await?;
 
     for total in season_totals {
@@ -184,13 +223,88 @@ pub async fn update_season_scores(
         .bind(total.total_exact_scores.unwrap_or(0) as i32)
         .bind(total.total_correct_results.unwrap_or(0) as i32)
         .bind(total.gameweeks_played.unwrap_or(0) as i32)
-        .execute(db)
+        .execute(&mut *db)
         .await?;
     }
 
     Ok(())
 }
 
+/// Re-snapshots season positions for this gameweek and every later completed
+/// gameweek in the same season, since rescoring an old week shifts them all.
+pub async fn update_season_positions(
+    db: &mut PgConnection,
+    gameweek_id: Uuid,
+) -> Result<(), AppError> {
+    let gameweeks = query!(
+        r#"
+        SELECT gw.id
+        FROM gameweeks gw
+        JOIN gameweeks cur ON cur.id = $1
+        WHERE gw.season = cur.season
+        AND gw.week_number >= cur.week_number
+        AND (gw.id = cur.id OR gw.is_completed = true)
+        ORDER BY gw.week_number
+        "#,
+        gameweek_id
+    )
+    .fetch_all(&mut *db)
+    .await?;
+
+    for gameweek in gameweeks {
+        snapshot_season_positions(db, gameweek.id).await?;
+    }
+
+    Ok(())
+}
+
+/// Stores every player's season position as it stood at the end of the given
+/// gameweek, using the same ordering as the season leaderboard.
+pub async fn snapshot_season_positions(
+    db: &mut PgConnection,
+    gameweek_id: Uuid,
+) -> Result<(), AppError> {
+    query(
+        r#"
+        WITH totals AS (
+            SELECT
+                gs.user_id,
+                SUM(gs.total_points) as total_points,
+                SUM(gs.exact_scores) as exact_scores
+            FROM gameweek_scores gs
+            JOIN gameweeks gw ON gs.gameweek_id = gw.id
+            JOIN gameweeks cur ON cur.id = $1
+            WHERE gw.season = cur.season AND gw.week_number <= cur.week_number
+            GROUP BY gs.user_id
+        )
+        INSERT INTO season_positions (user_id, gameweek_id, season, week_number, position, total_points)
+        SELECT
+            u.id,
+            cur.id,
+            cur.season,
+            cur.week_number,
+            ROW_NUMBER() OVER (
+                ORDER BY COALESCE(t.total_points, 0) DESC, COALESCE(t.exact_scores, 0) DESC, u.display_name ASC
+            )::INTEGER,
+            COALESCE(t.total_points, 0)::INTEGER
+        FROM users u
+        JOIN gameweeks cur ON cur.id = $1
+        LEFT JOIN totals t ON t.user_id = u.id
+        WHERE u.is_admin = false AND u.email_verified_at IS NOT NULL AND u.suspended_at IS NULL
+        ON CONFLICT (user_id, gameweek_id)
+        DO UPDATE SET
+            position = EXCLUDED.position,
+            total_points = EXCLUDED.total_points,
+            updated_at = NOW()
+        "#
+    )
+    .bind(gameweek_id)
+    .execute(&mut *db)
+    .await?;
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,300 @@
+// sessions.rs
+
+use std::net::SocketAddr;
+use axum::{async_trait, RequestPartsExt};
+use axum::extract::{ConnectInfo, FromRequestParts, Request, State};
+use axum::http::header::{SET_COOKIE, USER_AGENT};
+use axum::http::request::Parts;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum_extra::extract::cookie::Cookie;
+use axum_extra::extract::CookieJar;
+use chrono::{DateTime, Duration, Utc};
+use sqlx::{query, query_as, PgExecutor, PgPool};
+use uuid::Uuid;
+use crate::AppState;
+use crate::auth::Claims;
+use crate::config::Config;
+use crate::errors::AppError;
+use crate::models::{Session, User};
+
+pub const SESSION_COOKIE: &str = "auth_token";
+/// Active sessions get a fresh token, and a later expiry, this often.
+pub const SESSION_ROTATE_HOURS: i64 = 24;
+/// How stale `last_seen_at` may get before a request updates it.
+const LAST_SEEN_GRANULARITY_MINUTES: i64 = 5;
+/// How long the token a rotation replaced keeps working, for requests the
+/// browser already had in flight with it.
+const ROTATION_GRACE_SECONDS: i64 = 30;
+
+/// Where a sign-in came from, recorded against the session.
+#[derive(Debug, Clone, Default)]
+pub struct ClientInfo {
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for ClientInfo {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let user_agent = parts
+            .headers
+            .get(USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.chars().take(500).collect());
+
+        let ip_address = parts
+            .extract::<Option<ConnectInfo<SocketAddr>>>()
+            .await
+            .ok()
+            .flatten()
+            .map(|ConnectInfo(addr)| addr.ip().to_string());
+
+        Ok(ClientInfo { user_agent, ip_address })
+    }
+}
+
+/// A short, human description of a user agent such as "Firefox on Linux".
+pub fn describe_device(user_agent: Option<&str>) -> String {
+    let Some(user_agent) = user_agent else {
+        return "Unknown device".to_string();
+    };
+
+    // Order matters: Edge and Opera also claim to be Chrome, and Chrome claims to be Safari
+    let browser = [
+        ("Edg/", "Edge"),
+        ("OPR/", "Opera"),
+        ("Firefox/", "Firefox"),
+        ("Chrome/", "Chrome"),
+        ("Safari/", "Safari"),
+        ("curl/", "curl"),
+    ]
+    .iter()
+    .find(|(marker, _)| user_agent.contains(marker))
+    .map(|(_, name)| *name)
+    .unwrap_or("Unknown browser");
+
+    let os = [
+        ("iPhone", "iPhone"),
+        ("iPad", "iPad"),
+        ("Android", "Android"),
+        ("Windows", "Windows"),
+        ("Mac OS X", "macOS"),
+        ("Linux", "Linux"),
+    ]
+    .iter()
+    .find(|(marker, _)| user_agent.contains(marker))
+    .map(|(_, name)| *name);
+
+    match os {
+        Some(os) => format!("{} on {}", browser, os),
+        None => browser.to_string(),
+    }
+}
+
+pub fn session_cookie(token: String, config: &Config) -> Cookie<'static> {
+    Cookie::build((SESSION_COOKIE, token))
+        .path("/")
+        .http_only(true)
+        .secure(config.cookies.secure)
+        .same_site(config.cookies.same_site)
+        .max_age(time::Duration::days(config.session_lifetime_days))
+        .build()
+}
+
+pub fn clear_session_cookie() -> Cookie<'static> {
+    Cookie::build((SESSION_COOKIE, ""))
+        .path("/")
+        .max_age(time::Duration::seconds(0))
+        .build()
+}
+
+fn session_expiry(config: &Config) -> DateTime<Utc> {
+    Utc::now() + Duration::days(config.session_lifetime_days)
+}
+
+/// Records a new session for the user and returns the token for its cookie.
+pub async fn start_session(
+    state: &AppState,
+    user: &User,
+    client: &ClientInfo,
+) -> Result<String, AppError> {
+    let expires_at = session_expiry(&state.config);
+
+    let session_id = query!(
+        r#"
+        INSERT INTO sessions (user_id, device, user_agent, ip_address, expires_at)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING id
+        "#,
+        user.id,
+        describe_device(client.user_agent.as_deref()),
+        client.user_agent,
+        client.ip_address,
+        expires_at
+    )
+    .fetch_one(&state.db)
+    .await?
+    .id;
+
+    Claims::new(user.id, session_id, 0, expires_at, &state.config.jwt_secret)
+}
+
+/// The user behind a live session, or `InvalidToken` if it has been revoked,
+/// has expired, or the token has since been rotated.
+pub async fn session_user(db: &PgPool, claims: &Claims) -> Result<User, AppError> {
+    query_as::<_, User>(
+        r#"
+        SELECT u.* FROM sessions s
+        JOIN users u ON s.user_id = u.id
+        WHERE s.id = $1 AND s.user_id = $2 AND s.revoked_at IS NULL AND s.expires_at > NOW()
+          AND (
+              s.generation = $3
+              OR (s.generation = $3 + 1 AND s.rotated_at > NOW() - make_interval(secs => $4))
+          )
+        "#
+    )
+    .bind(claims.sid)
+    .bind(claims.sub)
+    .bind(claims.generation)
+    .bind(ROTATION_GRACE_SECONDS as f64)
+    .fetch_optional(db)
+    .await?
+    .ok_or(AppError::InvalidToken)
+}
+
+pub async fn user_sessions(db: &PgPool, user_id: Uuid) -> Result<Vec<Session>, AppError> {
+    let sessions = query_as::<_, Session>(
+        r#"
+        SELECT * FROM sessions
+        WHERE user_id = $1 AND revoked_at IS NULL AND expires_at > NOW()
+        ORDER BY last_seen_at DESC
+        "#
+    )
+    .bind(user_id)
+    .fetch_all(db)
+    .await?;
+
+    Ok(sessions)
+}
+
+pub async fn revoke_session(db: &PgPool, user_id: Uuid, session_id: Uuid) -> Result<(), AppError> {
+    query!(
+        "UPDATE sessions SET revoked_at = NOW() WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL",
+        session_id,
+        user_id
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn revoke_all_sessions(db: impl PgExecutor<'_>, user_id: Uuid) -> Result<(), AppError> {
+    query!(
+        "UPDATE sessions SET revoked_at = NOW() WHERE user_id = $1 AND revoked_at IS NULL",
+        user_id
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+/// Keeps a live session's last-seen time current and, once its token is old
+/// enough, slides the expiry forward and returns a replacement token.
+async fn refresh_session(state: &AppState, claims: &Claims) -> Result<Option<String>, AppError> {
+    let issued_at = DateTime::from_timestamp(claims.iat, 0).unwrap_or_default();
+
+    if Utc::now() - issued_at < Duration::hours(SESSION_ROTATE_HOURS) {
+        query!(
+            r#"
+            UPDATE sessions SET last_seen_at = NOW()
+            WHERE id = $1 AND last_seen_at < NOW() - make_interval(mins => $2)
+            "#,
+            claims.sid,
+            LAST_SEEN_GRANULARITY_MINUTES as i32
+        )
+        .execute(&state.db)
+        .await?;
+
+        return Ok(None);
+    }
+
+    // Only the current token can be rotated, so a replaced one can't be
+    // used to mint another
+    let expires_at = session_expiry(&state.config);
+    let generation = query!(
+        r#"
+        UPDATE sessions
+        SET last_seen_at = NOW(), expires_at = $3, generation = generation + 1, rotated_at = NOW()
+        WHERE id = $1 AND user_id = $2 AND generation = $4 AND revoked_at IS NULL AND expires_at > NOW()
+        RETURNING generation
+        "#,
+        claims.sid,
+        claims.sub,
+        expires_at,
+        claims.generation
+    )
+    .fetch_optional(&state.db)
+    .await?
+    .map(|row| row.generation);
+
+    let Some(generation) = generation else {
+        return Ok(None);
+    };
+
+    Claims::new(claims.sub, claims.sid, generation, expires_at, &state.config.jwt_secret).map(Some)
+}
+
+/// Middleware that rotates the session cookie on a sliding window. Runs
+/// after the handler, which still sees the token the request came with.
+pub async fn rotate_session_cookie(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    request: Request,
+    next: Next,
+) -> Response {
+    let claims = jar
+        .get(SESSION_COOKIE)
+        .and_then(|cookie| Claims::from_token(cookie.value(), &state.config.jwt_secret).ok());
+
+    let response = next.run(request).await;
+
+    // Sign-in and sign-out set the cookie themselves and must win
+    let sets_session_cookie = response
+        .headers()
+        .get_all(SET_COOKIE)
+        .iter()
+        .any(|value| value.as_bytes().starts_with(format!("{}=", SESSION_COOKIE).as_bytes()));
+
+    let rotated = match claims {
+        Some(claims) if !sets_session_cookie => refresh_session(&state, &claims).await.ok().flatten(),
+        _ => None,
+    };
+
+    match rotated {
+        Some(token) => (CookieJar::new().add(session_cookie(token, &state.config)), response).into_response(),
+        None => response,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_describe_device() {
+        let firefox = "Mozilla/5.0 (X11; Linux x86_64; rv:128.0) Gecko/20100101 Firefox/128.0";
+        let edge = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0 Safari/537.36 Edg/120.0";
+        let iphone = "Mozilla/5.0 (iPhone; CPU iPhone OS 17_0 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.0 Mobile/15E148 Safari/604.1";
+
+        assert_eq!(describe_device(Some(firefox)), "Firefox on Linux");
+        assert_eq!(describe_device(Some(edge)), "Edge on Windows");
+        assert_eq!(describe_device(Some(iphone)), "Safari on iPhone");
+        assert_eq!(describe_device(Some("curl/8.5.0")), "curl");
+        assert_eq!(describe_device(None), "Unknown device");
+    }
+}
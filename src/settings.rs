@@ -0,0 +1,36 @@
+// settings.rs
+//
+// Site-wide settings stored in the `settings` table, so admins can change
+// them without a restart. Unlike `Config`, these are read per request.
+
+use sqlx::{query, PgExecutor};
+use crate::errors::AppError;
+
+/// Whether new accounts must confirm their email before signing in.
+pub const REQUIRE_EMAIL_VERIFICATION: &str = "require_email_verification";
+
+/// A boolean setting; missing keys read as `false`.
+pub async fn flag(db: impl PgExecutor<'_>, key: &str) -> Result<bool, AppError> {
+    let value = query!("SELECT value FROM settings WHERE key = $1", key)
+        .fetch_optional(db)
+        .await?
+        .map(|row| row.value == "true")
+        .unwrap_or(false);
+
+    Ok(value)
+}
+
+pub async fn set_flag(db: impl PgExecutor<'_>, key: &str, value: bool) -> Result<(), AppError> {
+    query!(
+        r#"
+        INSERT INTO settings (key, value) VALUES ($1, $2)
+        ON CONFLICT (key) DO UPDATE SET value = EXCLUDED.value
+        "#,
+        key,
+        value.to_string()
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
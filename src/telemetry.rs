@@ -0,0 +1,120 @@
+// telemetry.rs
+//
+// Logging for the web server. Every request gets a span carrying its request
+// id, route, status, latency and, once authenticated, the user's id, so each
+// log line can be tied back to the request and player that caused it.
+
+use std::str::FromStr;
+use std::time::Duration;
+use axum::body::Body;
+use axum::extract::MatchedPath;
+use axum::http::{Request, Response};
+use tracing::field::{display, Empty};
+use tracing::Span;
+use tracing_subscriber::EnvFilter;
+use uuid::Uuid;
+use crate::config::Config;
+
+/// Set by `SetRequestIdLayer`, or passed through from a proxy in front of us.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human-readable lines, for development.
+    Pretty,
+    /// One JSON object per line, for log collectors.
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "pretty" => Ok(LogFormat::Pretty),
+            "json" => Ok(LogFormat::Json),
+            other => Err(format!("unknown log format '{}'", other)),
+        }
+    }
+}
+
+/// Checks a `RUST_LOG`-style filter such as `info,sqlx=warn`.
+pub fn parse_filter(filter: &str) -> Result<String, String> {
+    let filter = filter.trim();
+    EnvFilter::try_new(filter)
+        .map(|_| filter.to_string())
+        .map_err(|error| error.to_string())
+}
+
+/// Installs the global subscriber. Call once, before anything is logged.
+pub fn init(config: &Config) {
+    let subscriber = tracing_subscriber::fmt().with_env_filter(EnvFilter::new(&config.log_filter));
+
+    match config.log_format {
+        LogFormat::Pretty => subscriber.init(),
+        LogFormat::Json => subscriber
+            .json()
+            .with_current_span(true)
+            .with_span_list(false)
+            .init(),
+    }
+}
+
+/// The span every request runs in. The query string is left out, since
+/// reset and verification links carry their tokens there.
+pub fn request_span(request: &Request<Body>) -> Span {
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str())
+        .unwrap_or_default();
+
+    tracing::info_span!(
+        "request",
+        request_id,
+        method = %request.method(),
+        path = request.uri().path(),
+        route,
+        status = Empty,
+        latency_ms = Empty,
+        user_id = Empty,
+        impersonated_by = Empty,
+    )
+}
+
+pub fn record_response(response: &Response<Body>, latency: Duration, span: &Span) {
+    span.record("status", response.status().as_u16());
+    span.record("latency_ms", latency.as_millis() as u64);
+
+    tracing::info!("request completed");
+}
+
+/// Notes who the request is for on its span. When an admin is viewing the
+/// site as a player, `user_id` is the player.
+pub fn record_user(user_id: Uuid, impersonated_by: Option<Uuid>) {
+    let span = Span::current();
+    span.record("user_id", display(user_id));
+    if let Some(admin_id) = impersonated_by {
+        span.record("impersonated_by", display(admin_id));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_settings() {
+        assert_eq!("JSON".parse::<LogFormat>(), Ok(LogFormat::Json));
+        assert!("xml".parse::<LogFormat>().is_err());
+
+        assert_eq!(parse_filter(" info,sqlx=warn ").unwrap(), "info,sqlx=warn");
+        assert!(parse_filter("sqlx=loud").is_err());
+    }
+}
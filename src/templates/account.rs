@@ -0,0 +1,157 @@
+// templates/account.rs
+
+use askama::Template;
+use uuid::Uuid;
+use crate::models::{ApiToken, Session, User};
+
+#[derive(Template)]
+#[template(path = "account/tokens.html")]
+pub struct ApiTokensTemplate<'a> {
+    pub user: &'a User,
+    pub tokens: Vec<ApiToken>,
+    /// The secret of a token just created; this is the only time it is shown.
+    pub new_token: Option<String>,
+    pub error: Option<String>,
+
+    pub has_user: bool,
+    pub display_name: String,
+    pub is_admin: bool,
+}
+
+impl<'a> ApiTokensTemplate<'a> {
+    pub fn new(
+        user: &'a User,
+        tokens: Vec<ApiToken>,
+        new_token: Option<String>,
+        error: Option<String>,
+    ) -> Self {
+        Self {
+            user,
+            tokens,
+            new_token,
+            error,
+            has_user: true,
+            display_name: user.display_name.clone(),
+            is_admin: user.is_admin,
+        }
+    }
+}
+
+#[derive(Template)]
+#[template(path = "account/sessions.html")]
+pub struct SessionsTemplate<'a> {
+    pub user: &'a User,
+    pub sessions: Vec<Session>,
+    /// The session making this request, which can't be revoked from here.
+    pub current_session_id: Option<Uuid>,
+
+    pub has_user: bool,
+    pub display_name: String,
+    pub is_admin: bool,
+}
+
+impl<'a> SessionsTemplate<'a> {
+    pub fn new(user: &'a User, sessions: Vec<Session>, current_session_id: Option<Uuid>) -> Self {
+        Self {
+            user,
+            sessions,
+            current_session_id,
+            has_user: true,
+            display_name: user.display_name.clone(),
+            is_admin: user.is_admin,
+        }
+    }
+
+    pub fn is_current(&self, session: &Session) -> bool {
+        self.current_session_id == Some(session.id)
+    }
+}
+
+/// A new secret waiting for its first code.
+pub struct TwoFactorSetup {
+    pub secret: String,
+    /// Inline SVG of the `otpauth://` URI.
+    pub qr_svg: String,
+}
+
+#[derive(Template)]
+#[template(path = "account/two_factor.html")]
+pub struct TwoFactorTemplate<'a> {
+    pub user: &'a User,
+    pub enabled: bool,
+    pub recovery_codes_left: i64,
+    pub setup: Option<TwoFactorSetup>,
+    /// Fresh recovery codes; this is the only time they are shown.
+    pub recovery_codes: Option<Vec<String>>,
+    /// Admins can't turn 2FA off when the server requires it for them.
+    pub required: bool,
+    pub error: Option<String>,
+
+    pub has_user: bool,
+    pub display_name: String,
+    pub is_admin: bool,
+}
+
+impl<'a> TwoFactorTemplate<'a> {
+    pub fn new(
+        user: &'a User,
+        enabled: bool,
+        recovery_codes_left: i64,
+        required: bool,
+    ) -> Self {
+        Self {
+            user,
+            enabled,
+            recovery_codes_left,
+            setup: None,
+            recovery_codes: None,
+            required,
+            error: None,
+            has_user: true,
+            display_name: user.display_name.clone(),
+            is_admin: user.is_admin,
+        }
+    }
+}
+
+#[derive(Template)]
+#[template(path = "account/settings.html")]
+pub struct AccountSettingsTemplate<'a> {
+    pub user: &'a User,
+    /// False while the player has ledger entries, which must be kept.
+    pub can_erase: bool,
+    /// Staff have to give up their roles before deleting their account.
+    pub has_roles: bool,
+    /// Set when the player can sign in with single sign-on again instead of
+    /// giving their current password.
+    pub sso_provider: Option<String>,
+    pub error: Option<String>,
+    pub success: Option<String>,
+
+    pub has_user: bool,
+    pub display_name: String,
+    pub is_admin: bool,
+}
+
+impl<'a> AccountSettingsTemplate<'a> {
+    pub fn new(
+        user: &'a User,
+        can_erase: bool,
+        has_roles: bool,
+        sso_provider: Option<String>,
+        error: Option<String>,
+        success: Option<String>,
+    ) -> Self {
+        Self {
+            user,
+            can_erase,
+            has_roles,
+            sso_provider,
+            error,
+            success,
+            has_user: true,
+            display_name: user.display_name.clone(),
+            is_admin: user.is_admin,
+        }
+    }
+}
@@ -3,7 +3,18 @@
 use askama::Template;
 use chrono::{DateTime, Utc};
 use uuid::Uuid;
+use crate::announcements::{Announcement, Severity};
+use crate::audit::{AuditPage, AuditQuery};
+use crate::ledger::{format_pounds, DerivedPayout, LedgerRow, PlayerBalance};
 use crate::models::{User, Gameweek, Fixture};
+use crate::roles::{AdminPermissions, Role, StaffMember};
+use crate::user_admin::UserPage;
+
+mod filters {
+    pub fn pounds(pence: &i64) -> ::askama::Result<String> {
+        Ok(super::format_pounds(*pence))
+    }
+}
 
 #[derive(Debug)]
 pub struct GameweekInfo {
@@ -33,6 +44,11 @@ pub struct AdminDashboardTemplate<'a> {
     pub active_gameweek: Option<GameweekInfo>,
     pub total_users: i32,
     pub recent_gameweeks: Vec<GameweekInfo>,
+    pub require_email_verification: bool,
+    /// Accounts still waiting to confirm their email.
+    pub pending_verifications: i64,
+    /// Which actions to offer, from the admin's roles.
+    pub can: AdminPermissions,
 }
 
 #[derive(Template)]
@@ -62,4 +78,232 @@ pub struct ResultsTemplate<'a> {
     pub fixtures: Vec<FixtureInfo>,
     pub error: Option<String>,
     pub success: Option<String>,
-}
\ No newline at end of file
+}
+
+#[derive(Template)]
+#[template(path = "admin/ledger.html")]
+pub struct LedgerTemplate<'a> {
+    pub user: &'a User,
+    pub season: String,
+    pub entry_fee_pence: i64,
+    pub balances: Vec<PlayerBalance>,
+    pub entries: Vec<LedgerRow>,
+    pub outstanding: Vec<DerivedPayout>,
+    pub pot_pence: i64,
+    pub paid_out_pence: i64,
+    pub players_paid: usize,
+
+    pub has_user: bool,
+    pub display_name: String,
+    pub is_admin: bool,
+}
+
+impl<'a> LedgerTemplate<'a> {
+    pub fn new(
+        user: &'a User,
+        season: String,
+        entry_fee_pence: i64,
+        balances: Vec<PlayerBalance>,
+        entries: Vec<LedgerRow>,
+        outstanding: Vec<DerivedPayout>,
+    ) -> Self {
+        Self {
+            user,
+            season,
+            entry_fee_pence,
+            pot_pence: balances.iter().map(|b| b.contributed_pence()).sum(),
+            paid_out_pence: balances.iter().map(|b| b.payouts_pence).sum(),
+            players_paid: balances.iter().filter(|b| b.has_paid(entry_fee_pence)).count(),
+            balances,
+            entries,
+            outstanding,
+            has_user: true,
+            display_name: user.display_name.clone(),
+            is_admin: user.is_admin,
+        }
+    }
+
+    /// Entry fee still owed by a player, zero once they have paid in full.
+    pub fn owed_pence(&self, balance: &PlayerBalance) -> i64 {
+        (self.entry_fee_pence - balance.contributed_pence()).max(0)
+    }
+}
+
+#[derive(Template)]
+#[template(path = "admin/roles.html")]
+pub struct RolesTemplate<'a> {
+    pub user: &'a User,
+    pub staff: Vec<StaffMember>,
+    pub roles: [Role; 4],
+    pub error: Option<String>,
+
+    pub has_user: bool,
+    pub display_name: String,
+    pub is_admin: bool,
+}
+
+impl<'a> RolesTemplate<'a> {
+    pub fn new(user: &'a User, staff: Vec<StaffMember>, error: Option<String>) -> Self {
+        Self {
+            user,
+            staff,
+            roles: Role::ALL,
+            error,
+            has_user: true,
+            display_name: user.display_name.clone(),
+            is_admin: user.is_admin,
+        }
+    }
+}
+
+#[derive(Template)]
+#[template(path = "admin/users.html")]
+pub struct UsersTemplate<'a> {
+    pub user: &'a User,
+    pub search: String,
+    pub results: UserPage,
+
+    pub has_user: bool,
+    pub display_name: String,
+    pub is_admin: bool,
+}
+
+impl<'a> UsersTemplate<'a> {
+    pub fn new(user: &'a User, search: String, results: UserPage) -> Self {
+        Self {
+            user,
+            search,
+            results,
+            has_user: true,
+            display_name: user.display_name.clone(),
+            is_admin: user.is_admin,
+        }
+    }
+}
+
+#[derive(Template)]
+#[template(path = "admin/user.html")]
+pub struct UserDetailTemplate<'a> {
+    pub user: &'a User,
+    /// The account being managed.
+    pub member: User,
+    pub member_roles: Vec<Role>,
+    pub predictions: i64,
+    /// Whether the admin may view the site as this player.
+    pub can_impersonate: bool,
+    pub roles: [Role; 4],
+    pub can: AdminPermissions,
+    pub error: Option<String>,
+    pub success: Option<String>,
+
+    pub has_user: bool,
+    pub display_name: String,
+    pub is_admin: bool,
+}
+
+impl<'a> UserDetailTemplate<'a> {
+    pub fn new(
+        user: &'a User,
+        member: User,
+        member_roles: Vec<Role>,
+        predictions: i64,
+        can_impersonate: bool,
+        can: AdminPermissions,
+        error: Option<String>,
+        success: Option<String>,
+    ) -> Self {
+        Self {
+            user,
+            member,
+            member_roles,
+            predictions,
+            can_impersonate,
+            roles: Role::ALL,
+            can,
+            error,
+            success,
+            has_user: true,
+            display_name: user.display_name.clone(),
+            is_admin: user.is_admin,
+        }
+    }
+
+    pub fn is_self(&self) -> bool {
+        self.user.id == self.member.id
+    }
+
+    /// Other admins' accounts are left to those who can manage roles.
+    pub fn can_manage(&self) -> bool {
+        self.is_self() || self.member_roles.is_empty() || self.can.manage_roles
+    }
+
+    pub fn return_to(&self) -> String {
+        format!("/admin/users/{}", self.member.id)
+    }
+}
+
+#[derive(Template)]
+#[template(path = "admin/audit.html")]
+pub struct AuditTemplate<'a> {
+    pub user: &'a User,
+    pub filter: AuditQuery,
+    /// The filter as a query string, for the pagination links.
+    pub query: String,
+    pub results: AuditPage,
+    pub categories: [(&'static str, &'static str); 11],
+
+    pub has_user: bool,
+    pub display_name: String,
+    pub is_admin: bool,
+}
+
+impl<'a> AuditTemplate<'a> {
+    pub fn new(user: &'a User, filter: AuditQuery, query: String, results: AuditPage) -> Self {
+        Self {
+            user,
+            filter,
+            query,
+            results,
+            categories: crate::audit::CATEGORIES,
+            has_user: true,
+            display_name: user.display_name.clone(),
+            is_admin: user.is_admin,
+        }
+    }
+}
+
+#[derive(Template)]
+#[template(path = "admin/announcements.html")]
+pub struct AnnouncementsTemplate<'a> {
+    pub user: &'a User,
+    pub announcements: Vec<Announcement>,
+    pub severities: [Severity; 3],
+    pub now: DateTime<Utc>,
+    pub error: Option<String>,
+    pub success: Option<String>,
+
+    pub has_user: bool,
+    pub display_name: String,
+    pub is_admin: bool,
+}
+
+impl<'a> AnnouncementsTemplate<'a> {
+    pub fn new(
+        user: &'a User,
+        announcements: Vec<Announcement>,
+        error: Option<String>,
+        success: Option<String>,
+    ) -> Self {
+        Self {
+            user,
+            announcements,
+            severities: Severity::ALL,
+            now: Utc::now(),
+            error,
+            success,
+            has_user: true,
+            display_name: user.display_name.clone(),
+            is_admin: user.is_admin,
+        }
+    }
+}
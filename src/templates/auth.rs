@@ -6,6 +6,8 @@ use askama::Template;
 #[template(path = "auth/login.html")]
 pub struct LoginTemplate {
     pub error: Option<String>,
+    /// Name of the single sign-on provider, when one is configured.
+    pub sso_provider: Option<String>,
 
     pub has_user: bool,
     pub display_name: String,
@@ -22,11 +24,71 @@ pub struct RegisterTemplate {
     pub is_admin: bool,
 }
 
+#[derive(Template)]
+#[template(path = "auth/forgot_password.html")]
+pub struct ForgotPasswordTemplate {
+    /// Set once a request has been made, whether or not the email matched an account.
+    pub sent: bool,
+    pub error: Option<String>,
+
+    pub has_user: bool,
+    pub display_name: String,
+    pub is_admin: bool,
+}
+
+#[derive(Template)]
+#[template(path = "auth/reset_password.html")]
+pub struct ResetPasswordTemplate {
+    pub token: String,
+    pub error: Option<String>,
+
+    pub has_user: bool,
+    pub display_name: String,
+    pub is_admin: bool,
+}
+
+/// Shown while an account waits for its email to be confirmed.
+#[derive(Template)]
+#[template(path = "auth/verify_email.html")]
+pub struct VerifyEmailTemplate {
+    pub email: String,
+    pub notice: Option<String>,
+    pub error: Option<String>,
+
+    pub has_user: bool,
+    pub display_name: String,
+    pub is_admin: bool,
+}
+
+/// Opened from the confirmation email. Confirming takes a click, so link
+/// scanners that fetch the page don't use up the token.
+#[derive(Template)]
+#[template(path = "auth/confirm_email.html")]
+pub struct ConfirmEmailTemplate {
+    pub token: String,
+
+    pub has_user: bool,
+    pub display_name: String,
+    pub is_admin: bool,
+}
+
+/// The second login step, asking for an authenticator or recovery code.
+#[derive(Template)]
+#[template(path = "auth/two_factor.html")]
+pub struct TwoFactorLoginTemplate {
+    pub error: Option<String>,
+
+    pub has_user: bool,
+    pub display_name: String,
+    pub is_admin: bool,
+}
+
 // Helper implementations
 impl LoginTemplate {
     pub fn new(error: Option<String>) -> Self {
         Self {
             error,
+            sso_provider: None,
             has_user: false,
             display_name: "Guest".to_string(),
             is_admin: false,
@@ -43,4 +105,63 @@ impl RegisterTemplate {
             is_admin: false,
         }
     }
-}
\ No newline at end of file
+}
+
+impl ForgotPasswordTemplate {
+    pub fn new(sent: bool, error: Option<String>) -> Self {
+        Self {
+            sent,
+            error,
+            has_user: false,
+            display_name: "Guest".to_string(),
+            is_admin: false,
+        }
+    }
+}
+
+impl ResetPasswordTemplate {
+    pub fn new(token: String, error: Option<String>) -> Self {
+        Self {
+            token,
+            error,
+            has_user: false,
+            display_name: "Guest".to_string(),
+            is_admin: false,
+        }
+    }
+}
+
+impl ConfirmEmailTemplate {
+    pub fn new(token: String) -> Self {
+        Self {
+            token,
+            has_user: false,
+            display_name: "Guest".to_string(),
+            is_admin: false,
+        }
+    }
+}
+
+impl VerifyEmailTemplate {
+    pub fn new(email: String, notice: Option<String>, error: Option<String>) -> Self {
+        Self {
+            email,
+            notice,
+            error,
+            has_user: false,
+            display_name: "Guest".to_string(),
+            is_admin: false,
+        }
+    }
+}
+
+impl TwoFactorLoginTemplate {
+    pub fn new(error: Option<String>) -> Self {
+        Self {
+            error,
+            has_user: false,
+            display_name: "Guest".to_string(),
+            is_admin: false,
+        }
+    }
+}
@@ -1,5 +1,6 @@
 // templates/home.rs
 
+use crate::announcements::Announcement;
 use crate::models::User;
 use askama::Template;
 use chrono::{DateTime, Utc};
@@ -24,6 +25,9 @@ pub struct HomeTemplate<'a> {
     pub user: Option<&'a User>,
     pub current_gameweek: Option<CurrentGameweek>,
     pub top_players: Vec<TopPlayer>,
+    pub announcements: Vec<Announcement>,
+    /// Where dismissing an announcement returns to.
+    pub page_path: &'static str,
 
     pub has_user: bool,
     pub has_gameweek: bool,
@@ -38,11 +42,14 @@ impl<'a> HomeTemplate<'a> {
         user: Option<&'a User>,
         current_gameweek: Option<CurrentGameweek>,
         top_players: Vec<TopPlayer>,
+        announcements: Vec<Announcement>,
     ) -> Self {
         Self {
             user,
             current_gameweek: current_gameweek.clone(),
             top_players: top_players.clone(),
+            announcements,
+            page_path: "/",
             has_user: user.is_some(),
             has_gameweek: current_gameweek.is_some(),
             has_players: !top_players.is_empty(),
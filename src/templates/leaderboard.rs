@@ -2,13 +2,44 @@
 
 use askama::Template;
 use crate::models::{User, UserWithScore};
+use crate::prizes::RollOfHonour;
+
+/// Number of recent gameweeks shown in the season leaderboard form guide.
+pub const FORM_GUIDE_WEEKS: i64 = 5;
+
+#[derive(Debug)]
+pub struct FormWeek {
+    pub week_number: i32,
+    pub points: Option<i32>,
+}
+
+impl FormWeek {
+    /// CSS class used to colour the form guide cell.
+    pub fn rating(&self) -> &'static str {
+        match self.points {
+            Some(points) if points >= 10 => "form-good",
+            Some(points) if points > 0 => "form-ok",
+            Some(_) => "form-none",
+            None => "form-missed",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct SeasonStanding {
+    pub player: UserWithScore,
+    /// Places gained (positive) or lost (negative) since the previous gameweek.
+    /// `None` when there is no earlier snapshot to compare against.
+    pub movement: Option<i32>,
+    pub form: Vec<FormWeek>,
+}
 
 #[derive(Template)]
 #[template(path = "leaderboard/season.html")]
 pub struct SeasonLeaderboardTemplate<'a> {
     pub user: Option<&'a User>,
     pub season: &'a str,
-    pub leaderboard: Vec<UserWithScore>,
+    pub leaderboard: Vec<SeasonStanding>,
 
     pub has_user: bool,
     pub display_name: String,
@@ -24,6 +55,8 @@ pub struct WeeklyLeaderboardTemplate<'a> {
     pub season: &'a str,
     pub leaderboard: Vec<UserWithScore>,
     pub error: Option<String>,
+    /// The admins' note on the gameweek.
+    pub note: Option<String>,
 
     pub has_user: bool,
     pub display_name: String,
@@ -34,7 +67,7 @@ impl<'a> SeasonLeaderboardTemplate<'a> {
     pub fn new(
         user: Option<&'a User>,
         season: &'a str,
-        leaderboard: Vec<UserWithScore>,
+        leaderboard: Vec<SeasonStanding>,
         error: Option<String>,
     ) -> Self {
         Self {
@@ -56,6 +89,7 @@ impl<'a> WeeklyLeaderboardTemplate<'a> {
         season: &'a str,
         leaderboard: Vec<UserWithScore>,
         error: Option<String>,
+        note: Option<String>,
     ) -> Self {
         Self {
             user,
@@ -63,9 +97,42 @@ impl<'a> WeeklyLeaderboardTemplate<'a> {
             season,
             leaderboard,
             error,
+            note,
+            has_user: user.is_some(),
+            display_name: user.map(|u| u.display_name.clone()).unwrap_or_else(|| "Guest".to_string()),
+            is_admin: user.map(|u| u.is_admin).unwrap_or(false),
+        }
+    }
+}
+
+#[derive(Template)]
+#[template(path = "leaderboard/honours.html")]
+pub struct RollOfHonourTemplate<'a> {
+    pub user: Option<&'a User>,
+    pub honours: Option<RollOfHonour>,
+    pub seasons: Vec<String>,
+    pub error: Option<String>,
+
+    pub has_user: bool,
+    pub display_name: String,
+    pub is_admin: bool,
+}
+
+impl<'a> RollOfHonourTemplate<'a> {
+    pub fn new(
+        user: Option<&'a User>,
+        honours: Option<RollOfHonour>,
+        seasons: Vec<String>,
+        error: Option<String>,
+    ) -> Self {
+        Self {
+            user,
+            honours,
+            seasons,
+            error,
             has_user: user.is_some(),
             display_name: user.map(|u| u.display_name.clone()).unwrap_or_else(|| "Guest".to_string()),
             is_admin: user.map(|u| u.is_admin).unwrap_or(false),
         }
     }
-}
\ No newline at end of file
+}
@@ -9,6 +9,7 @@ pub mod user;
 pub mod predictions;
 pub mod admin;
 pub mod leaderboard;
+pub mod account;
 
 #[derive(Template)]
 #[template(path = "base.html")]
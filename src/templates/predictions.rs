@@ -3,6 +3,7 @@
 use askama::Template;
 use chrono::{DateTime, Utc};
 use uuid::Uuid;
+use crate::crowd::{crowd_score, CrowdScore, CrowdStats};
 use crate::models::{User, FixtureWithPrediction};
 
 #[derive(Debug, Clone)]
@@ -11,6 +12,7 @@ pub struct CurrentGameweekInfo {
     pub week_number: i32,
     pub season: String,
     pub deadline: DateTime<Utc>,
+    pub note: Option<String>,
 }
 
 #[derive(Template)]
@@ -22,6 +24,9 @@ pub struct PredictionsTemplate<'a> {
     pub deadline_passed: bool,
     pub already_submitted: bool,
     pub error: Option<String>,
+    /// Empty until the deadline has passed.
+    pub crowd: Vec<CrowdStats>,
+    pub crowd_score: Option<CrowdScore>,
 
     pub has_user: bool,
     pub display_name: String,
@@ -37,6 +42,7 @@ impl<'a> PredictionsTemplate<'a> {
         deadline_passed: bool,
         already_submitted: bool,
         error: Option<String>,
+        crowd: Vec<CrowdStats>,
     ) -> Self {
         Self {
             user,
@@ -45,6 +51,8 @@ impl<'a> PredictionsTemplate<'a> {
             deadline_passed,
             already_submitted,
             error,
+            crowd_score: crowd_score(&crowd),
+            crowd,
 
             has_user: true,
             display_name: user.display_name.clone(),
@@ -52,4 +60,18 @@ impl<'a> PredictionsTemplate<'a> {
             has_gameweek: current_gameweek.is_some(),
         }
     }
-}
\ No newline at end of file
+
+    pub fn crowd_for(&self, fixture_id: &Uuid) -> Option<&CrowdStats> {
+        self.crowd.iter().find(|stats| stats.fixture_id == *fixture_id)
+    }
+
+    /// The user's points from fixtures that have a result, to set against
+    /// the crowd's.
+    pub fn user_points(&self) -> i32 {
+        self.fixtures_with_predictions
+            .iter()
+            .filter(|f| f.fixture.home_score.is_some() && f.fixture.away_score.is_some())
+            .filter_map(|f| f.prediction.as_ref().map(|p| p.points_awarded))
+            .sum()
+    }
+}
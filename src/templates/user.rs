@@ -3,7 +3,8 @@
 use askama::Template;
 use chrono::{DateTime, Utc};
 use uuid::Uuid;
-use crate::models::User;
+use crate::announcements::Announcement;
+use crate::models::{SeasonPosition, SeasonScore, User};
 
 #[derive(Debug)]
 pub struct UserStats {
@@ -25,6 +26,16 @@ pub struct RecentGameweek {
     pub is_completed: bool,
 }
 
+#[derive(Debug)]
+pub struct BadgeInfo {
+    pub icon: &'static str,
+    pub name: &'static str,
+    pub description: &'static str,
+    pub season: String,
+    pub week_number: Option<i32>,
+    pub awarded_at: DateTime<Utc>,
+}
+
 #[derive(Debug, Clone)]
 pub struct CurrentGameweek {
     pub id: Uuid,
@@ -43,6 +54,10 @@ pub struct DashboardTemplate<'a> {
     pub recent_gameweeks: Vec<RecentGameweek>,
     pub current_gameweek: Option<CurrentGameweek>,
     pub has_predictions: bool,
+    pub badges: Vec<BadgeInfo>,
+    pub announcements: Vec<Announcement>,
+    /// Where dismissing an announcement returns to.
+    pub page_path: &'static str,
 
     pub has_user: bool,
     pub display_name: String,
@@ -57,6 +72,8 @@ impl<'a> DashboardTemplate<'a> {
         recent_gameweeks: Vec<RecentGameweek>,
         current_gameweek: Option<CurrentGameweek>,
         has_predictions: bool,
+        badges: Vec<BadgeInfo>,
+        announcements: Vec<Announcement>,
     ) -> Self {
         Self {
             user,
@@ -64,10 +81,124 @@ impl<'a> DashboardTemplate<'a> {
             recent_gameweeks,
             current_gameweek: current_gameweek.clone(),
             has_predictions,
+            badges,
+            announcements,
+            page_path: "/dashboard",
             has_user: true,
             display_name: user.display_name.clone(),
             is_admin: user.is_admin,
             has_current_gameweek: current_gameweek.is_some(),
         }
     }
-}
\ No newline at end of file
+}
+
+const CHART_WIDTH: f64 = 600.0;
+const CHART_HEIGHT: f64 = 200.0;
+const CHART_PADDING: f64 = 20.0;
+
+#[derive(Debug)]
+pub struct ChartMarker {
+    pub x: f64,
+    pub y: f64,
+    pub week_number: i32,
+    pub position: i32,
+}
+
+/// Pre-computed SVG geometry for the position-over-time line chart.
+/// Position 1 is drawn at the top of the chart.
+#[derive(Debug)]
+pub struct PositionChart {
+    pub width: f64,
+    pub height: f64,
+    pub points: String,
+    pub markers: Vec<ChartMarker>,
+    pub max_position: i32,
+}
+
+impl PositionChart {
+    pub fn new(history: &[SeasonPosition], max_position: i32) -> Option<Self> {
+        if history.is_empty() {
+            return None;
+        }
+
+        let max_position = max_position.max(1);
+        let inner_width = CHART_WIDTH - 2.0 * CHART_PADDING;
+        let inner_height = CHART_HEIGHT - 2.0 * CHART_PADDING;
+
+        let markers: Vec<ChartMarker> = history
+            .iter()
+            .enumerate()
+            .map(|(i, snapshot)| {
+                let x = if history.len() == 1 {
+                    CHART_WIDTH / 2.0
+                } else {
+                    CHART_PADDING + inner_width * i as f64 / (history.len() - 1) as f64
+                };
+                let y = if max_position == 1 {
+                    CHART_PADDING
+                } else {
+                    CHART_PADDING + inner_height * (snapshot.position - 1) as f64 / (max_position - 1) as f64
+                };
+
+                ChartMarker {
+                    x,
+                    y,
+                    week_number: snapshot.week_number,
+                    position: snapshot.position,
+                }
+            })
+            .collect();
+
+        let points = markers
+            .iter()
+            .map(|m| format!("{:.1},{:.1}", m.x, m.y))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        Some(Self {
+            width: CHART_WIDTH,
+            height: CHART_HEIGHT,
+            points,
+            markers,
+            max_position,
+        })
+    }
+}
+
+#[derive(Template)]
+#[template(path = "user/profile.html")]
+pub struct ProfileTemplate<'a> {
+    pub user: Option<&'a User>,
+    pub profile: User,
+    pub season: String,
+    pub season_score: Option<SeasonScore>,
+    pub position_chart: Option<PositionChart>,
+    pub badges: Vec<BadgeInfo>,
+
+    pub has_user: bool,
+    pub display_name: String,
+    pub is_admin: bool,
+}
+
+impl<'a> ProfileTemplate<'a> {
+    pub fn new(
+        user: Option<&'a User>,
+        profile: User,
+        season: String,
+        season_score: Option<SeasonScore>,
+        position_chart: Option<PositionChart>,
+        badges: Vec<BadgeInfo>,
+    ) -> Self {
+        Self {
+            user,
+            profile,
+            season,
+            season_score,
+            position_chart,
+            badges,
+            has_user: user.is_some(),
+            display_name: user.map(|u| u.display_name.clone()).unwrap_or_else(|| "Guest".to_string()),
+            is_admin: user.map(|u| u.is_admin).unwrap_or(false),
+        }
+    }
+}
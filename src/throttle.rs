@@ -0,0 +1,200 @@
+// throttle.rs
+//
+// Brute-force protection and rate limits, kept in the `throttles` table so
+// they survive restarts and are shared between server processes.
+
+use chrono::Utc;
+use sqlx::{query, PgPool};
+use uuid::Uuid;
+use crate::api_tokens::hash_token;
+use crate::errors::AppError;
+
+/// Failed logins are forgotten after this long without a lockout.
+const FAILURE_WINDOW_SECS: i64 = 60 * 60;
+/// Failures allowed per account before backoff starts.
+const ACCOUNT_FREE_FAILURES: i32 = 5;
+/// Failures allowed per IP address; higher, since many people can share one.
+const IP_FREE_FAILURES: i32 = 20;
+const BASE_LOCKOUT_SECS: i64 = 30;
+const MAX_LOCKOUT_SECS: i64 = 60 * 60;
+
+const REGISTRATIONS_PER_IP_PER_HOUR: i32 = 5;
+const PREDICTION_SUBMITS_PER_USER_PER_MINUTE: i32 = 10;
+
+/// The keys a failed login counts against.
+pub struct LoginKeys {
+    pub ip: String,
+    pub account: String,
+}
+
+impl LoginKeys {
+    /// The email typed in is hashed, so however long it is the key fits the
+    /// column, and the table doesn't collect addresses.
+    pub fn new(ip_address: Option<&str>, account: &str) -> Self {
+        Self {
+            ip: format!("login:ip:{}", ip_address.unwrap_or("unknown")),
+            account: format!("login:account:{}", hash_token(&account.trim().to_lowercase())),
+        }
+    }
+
+    /// Wrong two-factor codes count against the user rather than the email
+    /// typed in, and against the same per-IP budget as passwords.
+    pub fn two_factor(ip_address: Option<&str>, user_id: Uuid) -> Self {
+        Self {
+            ip: format!("login:ip:{}", ip_address.unwrap_or("unknown")),
+            account: format!("two_factor:user:{}", user_id),
+        }
+    }
+}
+
+/// How long to lock a key out for after its `failures`th failure: nothing
+/// while under the free allowance, then doubling up to the maximum.
+pub fn lockout_secs(failures: i32, free_failures: i32) -> Option<i64> {
+    let over = failures - free_failures;
+    if over <= 0 {
+        return None;
+    }
+
+    let doublings = (over - 1).min(20) as u32;
+    Some((BASE_LOCKOUT_SECS << doublings).min(MAX_LOCKOUT_SECS))
+}
+
+/// Fails with `RateLimited` if any of `keys` is locked out.
+pub async fn check_locked(db: &PgPool, keys: &[&str]) -> Result<(), AppError> {
+    let keys: Vec<String> = keys.iter().map(|key| key.to_string()).collect();
+
+    let locked_until = query!(
+        "SELECT MAX(locked_until) AS locked_until FROM throttles WHERE key = ANY($1) AND locked_until > NOW()",
+        &keys
+    )
+    .fetch_one(db)
+    .await?
+    .locked_until;
+
+    match locked_until {
+        Some(until) => Err(AppError::RateLimited((until - Utc::now()).num_seconds().max(1))),
+        None => Ok(()),
+    }
+}
+
+/// Counts a hit against `key` in a window of `window_secs`, returning the
+/// count so far.
+async fn count_hit(db: &PgPool, key: &str, window_secs: i64) -> Result<(i32, i64), AppError> {
+    let row = query!(
+        r#"
+        INSERT INTO throttles (key, count, window_started_at) VALUES ($1, 1, NOW())
+        ON CONFLICT (key) DO UPDATE SET
+            count = CASE
+                WHEN throttles.window_started_at < NOW() - make_interval(secs => $2) THEN 1
+                ELSE throttles.count + 1
+            END,
+            window_started_at = CASE
+                WHEN throttles.window_started_at < NOW() - make_interval(secs => $2) THEN NOW()
+                ELSE throttles.window_started_at
+            END
+        RETURNING count, EXTRACT(EPOCH FROM window_started_at + make_interval(secs => $2) - NOW())::BIGINT AS "remaining!"
+        "#,
+        key,
+        window_secs as f64
+    )
+    .fetch_one(db)
+    .await?;
+
+    Ok((row.count, row.remaining))
+}
+
+/// Records a failed login against both keys, locking out whichever has had
+/// too many. Returns the lockout, in seconds, if this failure caused one.
+pub async fn record_login_failure(db: &PgPool, keys: &LoginKeys) -> Result<Option<i64>, AppError> {
+    let mut longest = None;
+
+    for (key, free_failures) in [(&keys.account, ACCOUNT_FREE_FAILURES), (&keys.ip, IP_FREE_FAILURES)] {
+        let (failures, _) = count_hit(db, key, FAILURE_WINDOW_SECS).await?;
+
+        if let Some(secs) = lockout_secs(failures, free_failures) {
+            query!(
+                "UPDATE throttles SET locked_until = NOW() + make_interval(secs => $2) WHERE key = $1",
+                key,
+                secs as f64
+            )
+            .execute(db)
+            .await?;
+
+            longest = longest.max(Some(secs));
+        }
+    }
+
+    Ok(longest)
+}
+
+/// A successful login forgets the account's failures. The IP's are kept, so
+/// one good password can't be used to keep guessing at other accounts.
+pub async fn clear_login_failures(db: &PgPool, keys: &LoginKeys) -> Result<(), AppError> {
+    query!("DELETE FROM throttles WHERE key = $1", keys.account)
+        .execute(db)
+        .await?;
+
+    Ok(())
+}
+
+/// A simple fixed-window limit: at most `limit` hits per `window_secs`.
+async fn limit(db: &PgPool, key: &str, limit: i32, window_secs: i64) -> Result<(), AppError> {
+    let (count, remaining) = count_hit(db, key, window_secs).await?;
+
+    if count > limit {
+        return Err(AppError::RateLimited(remaining.max(1)));
+    }
+
+    Ok(())
+}
+
+pub async fn limit_registration(db: &PgPool, ip_address: Option<&str>) -> Result<(), AppError> {
+    let key = format!("register:ip:{}", ip_address.unwrap_or("unknown"));
+
+    limit(db, &key, REGISTRATIONS_PER_IP_PER_HOUR, 60 * 60).await
+}
+
+pub async fn limit_prediction_submit(db: &PgPool, user_id: Uuid) -> Result<(), AppError> {
+    let key = format!("predictions:user:{}", user_id);
+
+    limit(db, &key, PREDICTION_SUBMITS_PER_USER_PER_MINUTE, 60).await
+}
+
+/// "30 seconds", "4 minutes" and so on, for telling people how long to wait.
+pub fn describe_wait(secs: i64) -> String {
+    match secs {
+        s if s < 60 => format!("{} seconds", s.max(1)),
+        s if s < 120 => "a minute".to_string(),
+        s => format!("{} minutes", (s + 59) / 60),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lockout_backs_off_exponentially() {
+        assert_eq!(lockout_secs(1, 5), None);
+        assert_eq!(lockout_secs(5, 5), None);
+        assert_eq!(lockout_secs(6, 5), Some(30));
+        assert_eq!(lockout_secs(7, 5), Some(60));
+        assert_eq!(lockout_secs(9, 5), Some(240));
+        assert_eq!(lockout_secs(13, 5), Some(3600));
+        assert_eq!(lockout_secs(500, 5), Some(MAX_LOCKOUT_SECS));
+
+        assert_eq!(describe_wait(30), "30 seconds");
+        assert_eq!(describe_wait(90), "a minute");
+        assert_eq!(describe_wait(240), "4 minutes");
+    }
+
+    #[test]
+    fn test_login_keys_fit_the_column() {
+        let long = format!("{}@example.com", "a".repeat(1000));
+        let keys = LoginKeys::new(Some("2001:db8::1"), &long);
+        assert!(keys.account.len() <= 255);
+        assert!(!keys.account.contains("example.com"));
+
+        assert_eq!(keys.account, LoginKeys::new(None, &format!(" {} ", long.to_uppercase())).account);
+    }
+}
@@ -0,0 +1,345 @@
+// two_factor.rs
+//
+// RFC 6238 TOTP with SHA-1, 6 digits and a 30 second step, which is what
+// every common authenticator app expects.
+
+use axum_extra::extract::cookie::Cookie;
+use base32::Alphabet;
+use chrono::{Duration, Utc};
+use hmac::{Hmac, Mac};
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use qrcode::render::svg;
+use qrcode::QrCode;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use sqlx::{query, query_as, PgPool};
+use uuid::Uuid;
+use crate::api_tokens::hash_token;
+use crate::config::CookieSettings;
+use crate::errors::AppError;
+use crate::models::TwoFactor;
+
+const ISSUER: &str = "Superior 6";
+const SECRET_BYTES: usize = 20;
+const STEP_SECS: i64 = 30;
+const DIGITS: u32 = 6;
+/// Steps either side of now that are still accepted, for clock drift.
+const ALLOWED_SKEW: i64 = 1;
+const RECOVERY_CODE_COUNT: usize = 10;
+
+pub const PENDING_LOGIN_COOKIE: &str = "two_factor_pending";
+/// How long the second login step may take after the password is accepted.
+pub const PENDING_LOGIN_MINUTES: i64 = 5;
+
+const BASE32: Alphabet = Alphabet::Rfc4648 { padding: false };
+
+pub fn generate_secret() -> String {
+    let mut bytes = [0u8; SECRET_BYTES];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    base32::encode(BASE32, &bytes)
+}
+
+/// RFC 4226 HOTP value for one counter.
+fn hotp(key: &[u8], counter: u64) -> u32 {
+    let mut mac = Hmac::<Sha1>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(&counter.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([hash[offset], hash[offset + 1], hash[offset + 2], hash[offset + 3]]) & 0x7fff_ffff;
+
+    binary % 10u32.pow(DIGITS)
+}
+
+/// The time step `code` is valid for, if any, within the allowed skew of
+/// `unix_time`.
+pub fn verify_code(secret: &str, code: &str, unix_time: i64) -> Option<i64> {
+    let key = base32::decode(BASE32, secret)?;
+    let code = code.trim().replace(' ', "");
+    if code.len() != DIGITS as usize {
+        return None;
+    }
+    let code: u32 = code.parse().ok()?;
+
+    let current = unix_time / STEP_SECS;
+    (current - ALLOWED_SKEW..=current + ALLOWED_SKEW)
+        .find(|&step| step >= 0 && hotp(&key, step as u64) == code)
+}
+
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// The `otpauth://` URI that authenticator apps scan.
+pub fn provisioning_uri(secret: &str, account: &str) -> String {
+    format!(
+        "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+        percent_encode(ISSUER),
+        percent_encode(account),
+        secret,
+        percent_encode(ISSUER),
+        DIGITS,
+        STEP_SECS
+    )
+}
+
+pub fn qr_code_svg(uri: &str) -> Result<String, AppError> {
+    let code = QrCode::new(uri.as_bytes()).map_err(|_| AppError::Internal)?;
+
+    Ok(code.render::<svg::Color>().min_dimensions(200, 200).build())
+}
+
+/// One-time codes such as `3f9a-c27e`, for when the authenticator is lost.
+pub fn generate_recovery_codes() -> Vec<String> {
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let mut bytes = [0u8; 4];
+            rand::thread_rng().fill_bytes(&mut bytes);
+            let code = hex::encode(bytes);
+            format!("{}-{}", &code[..4], &code[4..])
+        })
+        .collect()
+}
+
+fn normalize_recovery_code(code: &str) -> String {
+    code.trim().replace('-', "").to_ascii_lowercase()
+}
+
+pub async fn two_factor_for(db: &PgPool, user_id: Uuid) -> Result<Option<TwoFactor>, AppError> {
+    let two_factor = query_as::<_, TwoFactor>(
+        "SELECT * FROM two_factor WHERE user_id = $1"
+    )
+    .bind(user_id)
+    .fetch_optional(db)
+    .await?;
+
+    Ok(two_factor)
+}
+
+pub async fn is_enabled(db: &PgPool, user_id: Uuid) -> Result<bool, AppError> {
+    Ok(two_factor_for(db, user_id).await?.is_some_and(|tf| tf.enabled_at.is_some()))
+}
+
+pub async fn unused_recovery_codes(db: &PgPool, user_id: Uuid) -> Result<i64, AppError> {
+    let count = query!(
+        "SELECT COUNT(*) AS count FROM recovery_codes WHERE user_id = $1 AND used_at IS NULL",
+        user_id
+    )
+    .fetch_one(db)
+    .await?
+    .count
+    .unwrap_or(0);
+
+    Ok(count)
+}
+
+/// Starts (or restarts) enrolment with a fresh secret. 2FA isn't switched on
+/// until `confirm_enrolment` sees a working code.
+pub async fn begin_enrolment(db: &PgPool, user_id: Uuid) -> Result<String, AppError> {
+    let secret = generate_secret();
+
+    // Leaves an already-enabled secret alone; that has to be disabled first
+    query!(
+        r#"
+        INSERT INTO two_factor (user_id, secret) VALUES ($1, $2)
+        ON CONFLICT (user_id) DO UPDATE SET secret = EXCLUDED.secret, last_used_step = NULL
+        WHERE two_factor.enabled_at IS NULL
+        RETURNING user_id
+        "#,
+        user_id,
+        secret
+    )
+    .fetch_optional(db)
+    .await?
+    .ok_or(AppError::Forbidden)?;
+
+    Ok(secret)
+}
+
+/// Switches 2FA on once the user proves their app works, returning the
+/// recovery codes to show them once.
+pub async fn confirm_enrolment(db: &PgPool, user_id: Uuid, code: &str) -> Result<Vec<String>, AppError> {
+    let pending = two_factor_for(db, user_id)
+        .await?
+        .filter(|tf| tf.enabled_at.is_none())
+        .ok_or(AppError::InvalidTwoFactorCode)?;
+
+    let step = verify_code(&pending.secret, code, Utc::now().timestamp())
+        .ok_or(AppError::InvalidTwoFactorCode)?;
+
+    query!(
+        "UPDATE two_factor SET enabled_at = NOW(), last_used_step = $2 WHERE user_id = $1",
+        user_id,
+        step
+    )
+    .execute(db)
+    .await?;
+
+    replace_recovery_codes(db, user_id).await
+}
+
+pub async fn replace_recovery_codes(db: &PgPool, user_id: Uuid) -> Result<Vec<String>, AppError> {
+    let codes = generate_recovery_codes();
+    let hashes: Vec<String> = codes.iter().map(|code| hash_token(&normalize_recovery_code(code))).collect();
+
+    let mut tx = db.begin().await?;
+
+    query!("DELETE FROM recovery_codes WHERE user_id = $1", user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    query!(
+        "INSERT INTO recovery_codes (user_id, code_hash) SELECT $1, UNNEST($2::VARCHAR[])",
+        user_id,
+        &hashes
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(codes)
+}
+
+pub async fn disable(db: &PgPool, user_id: Uuid) -> Result<(), AppError> {
+    let mut tx = db.begin().await?;
+
+    query!("DELETE FROM recovery_codes WHERE user_id = $1", user_id)
+        .execute(&mut *tx)
+        .await?;
+    query!("DELETE FROM two_factor WHERE user_id = $1", user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Checks a code from the user's app, or failing that one of their recovery
+/// codes. Each TOTP step and each recovery code can only be used once.
+pub async fn verify_user_code(db: &PgPool, user_id: Uuid, code: &str) -> Result<(), AppError> {
+    let two_factor = two_factor_for(db, user_id)
+        .await?
+        .filter(|tf| tf.enabled_at.is_some())
+        .ok_or(AppError::InvalidTwoFactorCode)?;
+
+    if let Some(step) = verify_code(&two_factor.secret, code, Utc::now().timestamp()) {
+        let accepted = query!(
+            r#"
+            UPDATE two_factor SET last_used_step = $2
+            WHERE user_id = $1 AND (last_used_step IS NULL OR last_used_step < $2)
+            "#,
+            user_id,
+            step
+        )
+        .execute(db)
+        .await?
+        .rows_affected();
+
+        return if accepted == 1 { Ok(()) } else { Err(AppError::InvalidTwoFactorCode) };
+    }
+
+    let used = query!(
+        r#"
+        UPDATE recovery_codes SET used_at = NOW()
+        WHERE user_id = $1 AND code_hash = $2 AND used_at IS NULL
+        "#,
+        user_id,
+        hash_token(&normalize_recovery_code(code))
+    )
+    .execute(db)
+    .await?
+    .rows_affected();
+
+    if used == 1 { Ok(()) } else { Err(AppError::InvalidTwoFactorCode) }
+}
+
+/// Carries a user who has given the right password, but not yet their
+/// code, to the second login step. It can't be used as a session.
+#[derive(Debug, Serialize, Deserialize)]
+struct PendingLogin {
+    sub: Uuid,
+    purpose: String,
+    exp: i64,
+}
+
+const PENDING_LOGIN_PURPOSE: &str = "two_factor";
+
+pub fn pending_login_cookie(token: String, cookies: &CookieSettings) -> Cookie<'static> {
+    Cookie::build((PENDING_LOGIN_COOKIE, token))
+        .path("/login")
+        .http_only(true)
+        .secure(cookies.secure)
+        .same_site(cookies.same_site)
+        .max_age(time::Duration::minutes(PENDING_LOGIN_MINUTES))
+        .build()
+}
+
+pub fn clear_pending_login_cookie() -> Cookie<'static> {
+    Cookie::build((PENDING_LOGIN_COOKIE, ""))
+        .path("/login")
+        .max_age(time::Duration::seconds(0))
+        .build()
+}
+
+pub fn pending_login_token(user_id: Uuid, jwt_secret: &str) -> Result<String, AppError> {
+    let claims = PendingLogin {
+        sub: user_id,
+        purpose: PENDING_LOGIN_PURPOSE.to_string(),
+        exp: (Utc::now() + Duration::minutes(PENDING_LOGIN_MINUTES)).timestamp(),
+    };
+
+    encode(&Header::default(), &claims, &EncodingKey::from_secret(jwt_secret.as_ref()))
+        .map_err(|_| AppError::TokenCreation)
+}
+
+pub fn pending_login_user(token: &str, jwt_secret: &str) -> Result<Uuid, AppError> {
+    decode::<PendingLogin>(token, &DecodingKey::from_secret(jwt_secret.as_ref()), &Validation::default())
+        .ok()
+        .filter(|data| data.claims.purpose == PENDING_LOGIN_PURPOSE)
+        .map(|data| data.claims.sub)
+        .ok_or(AppError::InvalidToken)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rfc6238_vectors() {
+        // The RFC's SHA-1 key is the ASCII string "12345678901234567890"
+        let secret = base32::encode(BASE32, b"12345678901234567890");
+
+        assert_eq!(verify_code(&secret, "287082", 59), Some(1));
+        assert_eq!(verify_code(&secret, "081804", 1111111109), Some(37037036));
+        assert_eq!(verify_code(&secret, "005924", 1234567890), Some(41152263));
+        // One step either side is allowed for drift, but no further
+        assert_eq!(verify_code(&secret, "081804", 1111111109 + 30), Some(37037036));
+        assert_eq!(verify_code(&secret, "081804", 1111111109 + 90), None);
+        assert_eq!(verify_code(&secret, "28708", 59), None);
+        assert_eq!(verify_code(&secret, "abcdef", 59), None);
+    }
+
+    #[test]
+    fn test_provisioning_uri_and_recovery_codes() {
+        let uri = provisioning_uri("JBSWY3DPEHPK3PXP", "alex+s6@example.com");
+        assert_eq!(
+            uri,
+            "otpauth://totp/Superior%206:alex%2Bs6%40example.com?secret=JBSWY3DPEHPK3PXP&issuer=Superior%206&algorithm=SHA1&digits=6&period=30"
+        );
+        assert!(qr_code_svg(&uri).unwrap().contains("<svg"));
+
+        let codes = generate_recovery_codes();
+        assert_eq!(codes.len(), RECOVERY_CODE_COUNT);
+        assert!(codes.iter().all(|code| code.len() == 9 && code.as_bytes()[4] == b'-'));
+        assert_eq!(normalize_recovery_code(" 3F9A-C27E "), "3f9ac27e");
+    }
+}
@@ -0,0 +1,265 @@
+// user_admin.rs
+//
+// Finding and managing player accounts from the admin console.
+
+use chrono::{DateTime, Utc};
+use sqlx::{query, query_as, Connection, FromRow, PgConnection, PgExecutor, PgPool};
+use uuid::Uuid;
+use crate::errors::AppError;
+use crate::roles;
+use crate::scoring::score_gameweek;
+use crate::sessions::revoke_all_sessions;
+
+pub const USERS_PER_PAGE: i64 = 25;
+
+/// A row in the user list.
+#[derive(Debug, Clone, FromRow)]
+pub struct UserSummary {
+    pub id: Uuid,
+    pub name: String,
+    pub display_name: String,
+    pub email: String,
+    pub is_admin: bool,
+    pub email_verified_at: Option<DateTime<Utc>>,
+    pub suspended_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub predictions: i64,
+}
+
+/// One page of search results.
+#[derive(Debug)]
+pub struct UserPage {
+    pub users: Vec<UserSummary>,
+    pub total: i64,
+    /// 1-based.
+    pub page: i64,
+}
+
+impl UserPage {
+    pub fn total_pages(&self) -> i64 {
+        ((self.total + USERS_PER_PAGE - 1) / USERS_PER_PAGE).max(1)
+    }
+
+    pub fn has_previous(&self) -> bool {
+        self.page > 1
+    }
+
+    pub fn has_next(&self) -> bool {
+        self.page < self.total_pages()
+    }
+}
+
+/// Turns what the admin typed into an ILIKE pattern, so `%` and `_` match
+/// themselves rather than acting as wildcards.
+pub fn search_pattern(search: &str) -> String {
+    let escaped = search
+        .trim()
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+
+    format!("%{}%", escaped)
+}
+
+/// Users whose name, display name or email contains `search`, newest first.
+pub async fn search_users(db: &PgPool, search: &str, page: i64) -> Result<UserPage, AppError> {
+    let pattern = search_pattern(search);
+    let page = page.max(1);
+
+    let total = query!(
+        "SELECT COUNT(*) AS count FROM users WHERE name ILIKE $1 OR display_name ILIKE $1 OR email ILIKE $1",
+        pattern
+    )
+    .fetch_one(db)
+    .await?
+    .count
+    .unwrap_or(0);
+
+    let users = query_as::<_, UserSummary>(
+        r#"
+        SELECT
+            u.id, u.name, u.display_name, u.email, u.is_admin, u.email_verified_at, u.suspended_at, u.created_at,
+            (SELECT COUNT(*) FROM predictions p WHERE p.user_id = u.id) AS predictions
+        FROM users u
+        WHERE u.name ILIKE $1 OR u.display_name ILIKE $1 OR u.email ILIKE $1
+        ORDER BY u.created_at DESC
+        LIMIT $2 OFFSET $3
+        "#
+    )
+    .bind(&pattern)
+    .bind(USERS_PER_PAGE)
+    .bind((page - 1) * USERS_PER_PAGE)
+    .fetch_all(db)
+    .await?;
+
+    Ok(UserPage { users, total, page })
+}
+
+pub async fn update_names(db: impl PgExecutor<'_>, user_id: Uuid, name: &str, display_name: &str) -> Result<(), AppError> {
+    let updated = query!(
+        "UPDATE users SET name = $1, display_name = $2 WHERE id = $3",
+        name.trim(),
+        display_name.trim(),
+        user_id
+    )
+    .execute(db)
+    .await?
+    .rows_affected();
+
+    if updated == 0 {
+        return Err(AppError::UserNotFound);
+    }
+
+    Ok(())
+}
+
+/// Blocks the account and signs it out everywhere. Refuses to suspend the
+/// last super-admin who isn't already suspended, just as the role can't be
+/// taken from them.
+pub async fn suspend(db: &mut PgConnection, user_id: Uuid) -> Result<(), AppError> {
+    let mut tx = db.begin().await?;
+
+    // The same lock as revoking a role, so the two can't race each other
+    query!("LOCK TABLE user_roles IN SHARE ROW EXCLUSIVE MODE")
+        .execute(&mut *tx)
+        .await?;
+
+    query!(
+        "UPDATE users SET suspended_at = NOW() WHERE id = $1 AND suspended_at IS NULL",
+        user_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    let orphaned = query!(
+        r#"
+        SELECT
+            EXISTS (SELECT 1 FROM user_roles WHERE user_id = $1 AND role = 'super_admin')
+            AND NOT EXISTS (
+                SELECT 1 FROM user_roles r JOIN users u ON u.id = r.user_id
+                WHERE r.role = 'super_admin' AND u.suspended_at IS NULL
+            ) AS "orphaned!"
+        "#,
+        user_id
+    )
+    .fetch_one(&mut *tx)
+    .await?
+    .orphaned;
+
+    if orphaned {
+        return Err(AppError::LastSuperAdmin);
+    }
+
+    revoke_all_sessions(&mut *tx, user_id).await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+pub async fn reinstate(db: impl PgExecutor<'_>, user_id: Uuid) -> Result<(), AppError> {
+    query!("UPDATE users SET suspended_at = NULL WHERE id = $1", user_id)
+        .execute(db)
+        .await?;
+
+    Ok(())
+}
+
+/// Folds a duplicate account into the one the player actually uses, then
+/// deletes the duplicate. Where both accounts predicted the same fixture,
+/// or earned the same badge, the surviving account's copy is kept. Scores
+/// are recalculated for every gameweek the moved predictions were in, as
+/// part of the same transaction.
+pub async fn merge_users(db: &mut PgConnection, duplicate_id: Uuid, keep_id: Uuid) -> Result<(), AppError> {
+    if duplicate_id == keep_id {
+        return Err(AppError::Forbidden);
+    }
+
+    // Roles are granted deliberately; make an admin remove them first
+    if !roles::user_roles(&mut *db, duplicate_id).await?.is_empty() {
+        return Err(AppError::Forbidden);
+    }
+
+    let mut tx = db.begin().await?;
+
+    let moved_gameweeks: Vec<Uuid> = query!(
+        r#"
+        UPDATE predictions p SET user_id = $2
+        FROM fixtures f
+        WHERE p.fixture_id = f.id
+          AND p.user_id = $1
+          AND NOT EXISTS (SELECT 1 FROM predictions kept WHERE kept.user_id = $2 AND kept.fixture_id = p.fixture_id)
+        RETURNING f.gameweek_id
+        "#,
+        duplicate_id,
+        keep_id
+    )
+    .fetch_all(&mut *tx)
+    .await?
+    .into_iter()
+    .map(|row| row.gameweek_id)
+    .collect();
+
+    query!(
+        r#"
+        UPDATE achievements a SET user_id = $2
+        WHERE a.user_id = $1
+          AND NOT EXISTS (
+              SELECT 1 FROM achievements kept
+              WHERE kept.user_id = $2 AND kept.badge = a.badge AND kept.season = a.season
+                AND kept.gameweek_id IS NOT DISTINCT FROM a.gameweek_id
+          )
+        "#,
+        duplicate_id,
+        keep_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    // Money is never dropped
+    query!("UPDATE ledger_entries SET user_id = $2 WHERE user_id = $1", duplicate_id, keep_id)
+        .execute(&mut *tx)
+        .await?;
+
+    // Everything else the duplicate owned, including its scores, goes with it
+    let deleted = query!("DELETE FROM users WHERE id = $1", duplicate_id)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+
+    if deleted == 0 {
+        return Err(AppError::UserNotFound);
+    }
+
+    // Rescored before committing, so the standings never disagree with
+    // where the predictions now live
+    let mut gameweeks = moved_gameweeks;
+    gameweeks.sort();
+    gameweeks.dedup();
+
+    for gameweek_id in gameweeks {
+        score_gameweek(&mut tx, gameweek_id).await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_search_pattern_and_pages() {
+        assert_eq!(search_pattern(" sam "), "%sam%");
+        assert_eq!(search_pattern("100%_"), "%100\\%\\_%");
+        assert_eq!(search_pattern(""), "%%");
+
+        let page = |total, page| UserPage { users: vec![], total, page };
+        assert_eq!(page(0, 1).total_pages(), 1);
+        assert_eq!(page(25, 1).total_pages(), 1);
+        assert_eq!(page(26, 1).total_pages(), 2);
+        assert!(!page(26, 1).has_previous() && page(26, 1).has_next());
+        assert!(page(26, 2).has_previous() && !page(26, 2).has_next());
+    }
+}